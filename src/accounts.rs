@@ -0,0 +1,89 @@
+use crate::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+// One row of the account mapping file : the client is a member of the account
+#[derive(Deserialize)]
+struct Membership {
+    client: u16,
+    account: u16,
+}
+
+// Account of each member, read from a csv file with a client,account header. Clients missing
+// from the file are their own account.
+pub fn read_accounts<R: Read>(reader: R) -> Result<HashMap<u16, u16>, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    rdr.deserialize()
+        .map(|membership| membership.map(|m: Membership| (m.client, m.account)))
+        .collect()
+}
+
+pub fn write_accounts<W: Write>(writer: W, accounts: &HashMap<u16, u16>) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(["client", "account"])?;
+    let mut memberships: Vec<_> = accounts.iter().collect();
+    memberships.sort_unstable();
+    for membership in memberships {
+        wtr.serialize(membership)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// Balances keyed by member instead of by account : each member of a joint account gets a
+// copy of the shared balances
+pub fn members(
+    clients: &HashMap<u16, Client>,
+    accounts: &HashMap<u16, u16>,
+) -> HashMap<u16, Client> {
+    let mut members: HashMap<u16, Client> = clients
+        .iter()
+        .filter(|(account, _)| !accounts.values().any(|a| a == *account))
+        .map(|(client_id, client)| (*client_id, client.clone()))
+        .collect();
+    for (member, account) in accounts {
+        if let Some(client) = clients.get(account) {
+            members.insert(*member, client.clone());
+        }
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, Engine};
+
+    #[test]
+    fn joint_account() {
+        let accounts = read_accounts("client,account\n1,10\n2,10\n".as_bytes()).unwrap();
+        let transactions = get_transactions_from_file("src/testSamples/jointAccount.csv").unwrap();
+        let mut engine = Engine::default();
+        engine.set_accounts(accounts.clone());
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        // Client 2 withdraws and disputes funds deposited by client 1
+        let clients = engine.clients();
+        assert_eq!(clients.len(), 2);
+        assert_eq!(
+            (
+                clients[&10].available,
+                clients[&10].held,
+                clients[&10].total
+            ),
+            (3.0, 5.0, 8.0)
+        );
+        assert_eq!(clients[&3].total, 1.0);
+
+        let members = members(clients, &accounts);
+        assert_eq!(members.len(), 3);
+        assert_eq!(members[&1].total, 8.0);
+        assert_eq!(members[&2].total, 8.0);
+        assert_eq!(members[&3].total, 1.0);
+    }
+}
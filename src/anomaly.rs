@@ -0,0 +1,134 @@
+use crate::config::AnomalySettings;
+use crate::{Transaction, TransactionCategory};
+use std::collections::{HashMap, VecDeque};
+
+const DEFAULT_WINDOW: usize = 50;
+const DEFAULT_MIN_SAMPLES: usize = 10;
+const DEFAULT_Z_SCORE: f64 = 3.0;
+
+// A deposit or withdrawal far from the amounts the client usually moves
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub row: usize,
+    pub tx: u32,
+    pub client_id: u16,
+    pub category: TransactionCategory,
+    pub amount: f64,
+    // Of the previous amounts of the client in the window
+    pub mean: f64,
+    pub std_dev: f64,
+    pub z_score: f64,
+}
+
+// Rolling amounts of each client, deposits and withdrawals apart, within the last `window`
+// of each. Kept in memory only : the window starts empty on each run.
+#[derive(Clone, Debug, Default)]
+pub struct AnomalyDetector {
+    // By client and whether they are withdrawals, oldest first
+    amounts: HashMap<(u16, bool), VecDeque<f64>>,
+    anomalies: Vec<Anomaly>,
+}
+
+impl AnomalyDetector {
+    // Scores the amount against the previous ones of the client before adding it to the
+    // window. Needs `min_samples` previous amounts, and some variation between them.
+    pub fn observe(
+        &mut self,
+        row: usize,
+        t: &Transaction,
+        client_id: u16,
+        settings: &AnomalySettings,
+    ) -> Option<&Anomaly> {
+        let amount = t.amount?;
+        let key = (client_id, t.category == TransactionCategory::Withdrawal);
+        let amounts = self.amounts.entry(key).or_default();
+        let mut found = false;
+        if amounts.len() >= settings.min_samples.unwrap_or(DEFAULT_MIN_SAMPLES) {
+            let count = amounts.len() as f64;
+            let mean = amounts.iter().sum::<f64>() / count;
+            let variance = amounts.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / count;
+            let std_dev = variance.sqrt();
+            let z_score = (amount - mean) / std_dev;
+            if std_dev > 0.0 && z_score.abs() > settings.z_score.unwrap_or(DEFAULT_Z_SCORE) {
+                self.anomalies.push(Anomaly {
+                    row,
+                    tx: t.tx,
+                    client_id,
+                    category: t.category,
+                    amount,
+                    mean,
+                    std_dev,
+                    z_score,
+                });
+                found = true;
+            }
+        }
+        amounts.push_back(amount);
+        if amounts.len() > settings.window.unwrap_or(DEFAULT_WINDOW) {
+            amounts.pop_front();
+        }
+        found.then(|| &self.anomalies[self.anomalies.len() - 1])
+    }
+
+    // For a rollback. Amounts pushed out of the window meanwhile stay out.
+    pub fn pop(&mut self, row: usize, t: &Transaction, client_id: u16) {
+        let key = (client_id, t.category == TransactionCategory::Withdrawal);
+        if let Some(amounts) = self.amounts.get_mut(&key) {
+            amounts.pop_back();
+        }
+        if self.anomalies.last().is_some_and(|a| a.row == row) {
+            self.anomalies.pop();
+        }
+    }
+
+    // In row order
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deposit(tx: u32, amount: f64) -> Transaction {
+        Transaction {
+            category: TransactionCategory::Deposit,
+            client_id: 1,
+            tx,
+            amount: Some(amount),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn z_scores() {
+        let settings: AnomalySettings =
+            toml::from_str("window = 4\nmin_samples = 3\nz_score = 3.0").unwrap();
+        let mut detector = AnomalyDetector::default();
+        for (tx, amount) in [(1, 10.0), (2, 12.0), (3, 8.0)] {
+            assert!(detector
+                .observe(tx as usize, &deposit(tx, amount), 1, &settings)
+                .is_none());
+        }
+        let anomaly = detector
+            .observe(4, &deposit(4, 100.0), 1, &settings)
+            .unwrap();
+        assert_eq!((anomaly.tx, anomaly.mean), (4, 10.0));
+        // The withdrawals of the client have their own window
+        let mut withdrawal = deposit(5, 100.0);
+        withdrawal.category = TransactionCategory::Withdrawal;
+        assert!(detector.observe(5, &withdrawal, 1, &settings).is_none());
+
+        detector.pop(4, &deposit(4, 100.0), 1);
+        assert!(detector.anomalies().is_empty());
+        // 100 is now part of the window, so 60 is within 3 standard deviations
+        detector.observe(4, &deposit(4, 100.0), 1, &settings);
+        assert!(detector
+            .observe(6, &deposit(6, 60.0), 1, &settings)
+            .is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{Client, Event, Outcome};
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+    UInt64Array,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Arc;
+
+// Writes <directory>/clients.arrow and <directory>/events.arrow, readable with
+// polars.read_ipc or pandas.read_feather
+pub fn write_arrow_output(
+    directory: &str,
+    clients: &HashMap<u16, Client>,
+    events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(directory)?;
+    write_batch(
+        &Path::new(directory).join("clients.arrow"),
+        &clients_batch(clients)?,
+    )?;
+    write_batch(
+        &Path::new(directory).join("events.arrow"),
+        &events_batch(events)?,
+    )?;
+    Ok(())
+}
+
+fn write_batch(path: &Path, batch: &RecordBatch) -> Result<(), Box<dyn Error>> {
+    let mut writer = FileWriter::try_new(File::create(path)?, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn clients_batch(clients: &HashMap<u16, Client>) -> Result<RecordBatch, Box<dyn Error>> {
+    let schema = Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", DataType::Float64, false),
+        Field::new("held", DataType::Float64, false),
+        Field::new("total", DataType::Float64, false),
+        Field::new("locked", DataType::Boolean, false),
+        Field::new("frozen", DataType::Boolean, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(clients.keys().copied().collect::<UInt16Array>()),
+        Arc::new(
+            clients
+                .values()
+                .map(|c| c.available)
+                .collect::<Float64Array>(),
+        ),
+        Arc::new(clients.values().map(|c| c.held).collect::<Float64Array>()),
+        Arc::new(clients.values().map(|c| c.total).collect::<Float64Array>()),
+        Arc::new(
+            clients
+                .values()
+                .map(|c| Some(c.locked))
+                .collect::<BooleanArray>(),
+        ),
+        Arc::new(
+            clients
+                .values()
+                .map(|c| Some(c.frozen))
+                .collect::<BooleanArray>(),
+        ),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+fn events_batch(events: &[Event]) -> Result<RecordBatch, Box<dyn Error>> {
+    let schema = Schema::new(vec![
+        Field::new("row", DataType::UInt64, false),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("client", DataType::UInt16, false),
+        Field::new("tx", DataType::UInt32, false),
+        Field::new("amount", DataType::Float64, true),
+        Field::new("outcome", DataType::Utf8, false),
+        Field::new("reason", DataType::Utf8, true),
+        Field::new("rule", DataType::Utf8, true),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(events.iter().map(|e| e.row as u64).collect::<UInt64Array>()),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| Some(e.transaction.category.as_str()))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| e.transaction.client_id)
+                .collect::<UInt16Array>(),
+        ),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| e.transaction.tx)
+                .collect::<UInt32Array>(),
+        ),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| e.transaction.amount)
+                .collect::<Float64Array>(),
+        ),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| match e.outcome {
+                    Outcome::Applied => Some("applied"),
+                    Outcome::Held => Some("held"),
+                    Outcome::Deferred => Some("deferred"),
+                    Outcome::Ignored(_) => Some("ignored"),
+                })
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| match e.outcome {
+                    Outcome::Applied | Outcome::Held | Outcome::Deferred => None,
+                    Outcome::Ignored(reason) => Some(reason),
+                })
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            events
+                .iter()
+                .map(|e| e.rule.as_deref())
+                .collect::<StringArray>(),
+        ),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, process_transactions};
+    use arrow_ipc::reader::FileReader;
+
+    #[test]
+    fn write_clients_and_events() {
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        let events = process_transactions(&transactions, &mut clients).unwrap();
+
+        let directory = std::env::temp_dir().join("payments-engine-arrow-test");
+        write_arrow_output(directory.to_str().unwrap(), &clients, &events).unwrap();
+
+        let reader =
+            FileReader::try_new(File::open(directory.join("clients.arrow")).unwrap(), None)
+                .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, clients.len());
+
+        let reader =
+            FileReader::try_new(File::open(directory.join("events.arrow")).unwrap(), None).unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, events.len());
+    }
+}
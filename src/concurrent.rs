@@ -0,0 +1,276 @@
+use crate::config::Config;
+use crate::{Client, Engine, Event, Transaction};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const DEFAULT_SHARDS: usize = 64;
+
+// Engine that can be shared between threads, e.g. behind an Arc. Clients are spread over
+// shards by the partitioner, each shard owning its clients, their transactions history and
+// their disputes, so submissions for clients of different shards never wait on each other.
+// As the history is split, a dispute only finds transactions of clients living in its shard :
+// per client ordering is kept as long as each client is submitted from a single thread.
+pub struct ConcurrentEngine {
+    shards: Vec<Mutex<Engine>>,
+    partitioner: Partitioner,
+}
+
+// Shard owning each client. Every row goes to the shard of its client, the dispute flow
+// included, so a dispute of a client's own transaction always resolves within the shard that
+// recorded it. A dispute of another client's transaction only resolves when both clients
+// share a shard, it is ignored as an unknown transaction otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct Partitioner {
+    shards: usize,
+}
+
+impl Partitioner {
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "A concurrent engine needs at least one shard");
+        Partitioner { shards }
+    }
+
+    pub fn shard_of(&self, client_id: u16) -> usize {
+        client_id as usize % self.shards
+    }
+}
+
+impl ConcurrentEngine {
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+
+    pub fn with_shards(shards: usize) -> Self {
+        ConcurrentEngine {
+            partitioner: Partitioner::new(shards),
+            shards: (0..shards).map(|_| Mutex::new(Engine::default())).collect(),
+        }
+    }
+
+    // For hosts splitting their input by shard upstream, e.g. one queue and one thread per
+    // shard, so that no submission ever waits on a lock
+    pub fn partitioner(&self) -> Partitioner {
+        self.partitioner
+    }
+
+    // Same config for every shard
+    pub fn set_config(&self, config: Config) {
+        for shard in &self.shards {
+            shard
+                .lock()
+                .expect("A thread panicked while processing a transaction")
+                .set_config(config.clone());
+        }
+    }
+
+    // The row of the returned event counts the transactions submitted to the shard. A close
+    // sweeping the funds of its client credits the house account in the shard of the client,
+    // the credit is then moved to the shard owning the house account. Only one shard is locked
+    // at a time.
+    pub fn submit(&self, t: &Transaction) -> Result<Event, String> {
+        let mut shard = self
+            .shard(t.client_id)
+            .lock()
+            .expect("A thread panicked while processing a transaction");
+        let event = shard.process(t)?;
+        let Some(house) = shard.config.admin.house_account else {
+            return Ok(event);
+        };
+        if self.partitioner.shard_of(house) == self.partitioner.shard_of(t.client_id) {
+            return Ok(event);
+        }
+        let Some(swept) = shard.clients.remove(&house) else {
+            return Ok(event);
+        };
+        drop(shard);
+        let mut shard = self
+            .shard(house)
+            .lock()
+            .expect("A thread panicked while processing a transaction");
+        let client = shard.clients.entry(house).or_default();
+        client.available += swept.available;
+        client.total += swept.total;
+        Ok(event)
+    }
+
+    pub fn client(&self, client_id: u16) -> Option<Client> {
+        self.shard(client_id)
+            .lock()
+            .expect("A thread panicked while processing a transaction")
+            .clients()
+            .get(&client_id)
+            .cloned()
+    }
+
+    // Copy of every client, shards are locked one after the other so it isn't an atomic
+    // snapshot while other threads keep submitting
+    pub fn clients(&self) -> HashMap<u16, Client> {
+        let mut clients = HashMap::new();
+        for shard in &self.shards {
+            let shard = shard
+                .lock()
+                .expect("A thread panicked while processing a transaction");
+            clients.extend(shard.clients().iter().map(|(id, c)| (*id, c.clone())));
+        }
+        clients
+    }
+
+    fn shard(&self, client_id: u16) -> &Mutex<Engine> {
+        &self.shards[self.partitioner.shard_of(client_id)]
+    }
+}
+
+impl Default for ConcurrentEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Outcome, TransactionCategory};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn transaction(
+        category: TransactionCategory,
+        client_id: u16,
+        tx: u32,
+        amount: Option<f64>,
+    ) -> Transaction {
+        Transaction {
+            category,
+            client_id,
+            tx,
+            amount,
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ConcurrentEngine>();
+    }
+
+    #[test]
+    fn submit_from_many_threads() {
+        let engine = Arc::new(ConcurrentEngine::with_shards(4));
+        let handles: Vec<_> = (0..8u16)
+            .map(|thread_id| {
+                let engine = Arc::clone(&engine);
+                thread::spawn(move || {
+                    // Each thread owns 10 clients, so their transactions stay ordered
+                    for client_id in thread_id * 10..(thread_id + 1) * 10 {
+                        let tx = u32::from(client_id) * 10;
+                        let deposit =
+                            transaction(TransactionCategory::Deposit, client_id, tx, Some(3.0));
+                        engine.submit(&deposit).unwrap();
+                        let withdrawal = transaction(
+                            TransactionCategory::Withdrawal,
+                            client_id,
+                            tx + 1,
+                            Some(1.0),
+                        );
+                        engine.submit(&withdrawal).unwrap();
+                        let dispute =
+                            transaction(TransactionCategory::Dispute, client_id, tx, None);
+                        engine.submit(&dispute).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let clients = engine.clients();
+        assert_eq!(clients.len(), 80);
+        for client in clients.values() {
+            assert_eq!(client.available, -1.0);
+            assert_eq!(client.held, 3.0);
+            assert_eq!(client.total, 2.0);
+        }
+        assert_eq!(engine.client(42).unwrap().held, 3.0);
+        assert!(engine.client(1000).is_none());
+    }
+
+    #[test]
+    fn sweeps_reach_the_house_shard() {
+        let engine = Arc::new(ConcurrentEngine::with_shards(4));
+        engine.set_config(toml::from_str("[admin]\nhouse_account = 0").unwrap());
+        engine
+            .submit(&transaction(TransactionCategory::Deposit, 0, 1, Some(1.0)))
+            .unwrap();
+        let handles: Vec<_> = (1..=3u16)
+            .map(|client_id| {
+                let engine = Arc::clone(&engine);
+                thread::spawn(move || {
+                    let tx = u32::from(client_id) * 10;
+                    let deposit =
+                        transaction(TransactionCategory::Deposit, client_id, tx, Some(2.0));
+                    engine.submit(&deposit).unwrap();
+                    let close = transaction(TransactionCategory::Close, client_id, tx + 1, None);
+                    assert_eq!(engine.submit(&close).unwrap().outcome, Outcome::Applied);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // The house account only lives in its own shard, with every sweep
+        let house = engine.client(0).unwrap();
+        assert_eq!(house.available, 7.0);
+        assert_eq!(house.total, 7.0);
+        assert_eq!(engine.clients()[&0].total, 7.0);
+        for client_id in 1..=3 {
+            assert_eq!(engine.client(client_id).unwrap().total, 0.0);
+        }
+        for shard in &engine.shards[1..] {
+            assert!(!shard.lock().unwrap().clients().contains_key(&0));
+        }
+    }
+
+    #[test]
+    fn disputes_resolve_in_their_shard() {
+        let engine = ConcurrentEngine::with_shards(8);
+        for client_id in 0..200u16 {
+            let tx = u32::from(client_id);
+            let deposit = transaction(TransactionCategory::Deposit, client_id, tx, Some(2.0));
+            let dispute = transaction(TransactionCategory::Dispute, client_id, tx, None);
+            let chargeback = transaction(TransactionCategory::Chargeback, client_id, tx, None);
+            for t in [deposit, dispute, chargeback] {
+                assert_eq!(engine.submit(&t).unwrap().outcome, Outcome::Applied);
+            }
+        }
+        // Every shard only holds the clients, history and disputes the partitioner gives it
+        let partitioner = engine.partitioner();
+        for (index, shard) in engine.shards.iter().enumerate() {
+            let shard = shard.lock().unwrap();
+            assert!(shard
+                .clients()
+                .keys()
+                .all(|id| partitioner.shard_of(*id) == index));
+            assert!(shard
+                .transactions_history
+                .values()
+                .all(|t| partitioner.shard_of(t.client_id) == index));
+            assert!(shard
+                .disputes()
+                .all(|d| partitioner.shard_of(d.client_id) == index));
+        }
+
+        // Clients 1 and 202 live in different shards
+        let deposit = transaction(TransactionCategory::Deposit, 202, 1000, Some(2.0));
+        engine.submit(&deposit).unwrap();
+        let other_client = transaction(TransactionCategory::Dispute, 1, 1000, None);
+        let event = engine.submit(&other_client).unwrap();
+        assert!(matches!(event.outcome, Outcome::Ignored(_)));
+        assert_eq!(engine.client(202).unwrap().held, 0.0);
+    }
+}
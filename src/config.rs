@@ -0,0 +1,312 @@
+use crate::rules::Rule;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// Settings read from the toml file given with --config <file>, e.g. :
+//   [tiers]
+//   vip = [1, 2]
+//
+//   [[rules]]
+//   name = "large withdrawals"
+//   category = "withdrawal"
+//   min_amount = 1000.0
+//   action = "hold"
+//
+//   [admin]
+//   adjustments = true
+//   opening_balances = true
+//   house_account = 0
+//   anonymized_account = 65535
+//
+//   [bonus]
+//   clawback_window_seconds = 2592000
+//
+//   [disputes]
+//   client_mismatch = "route"
+//   withdrawals = "provisional_credit"
+//   grace_rows = 1000
+//   grace_seconds = 60
+//   insufficient_funds = "cap"
+//
+//   [history]
+//   reused_tx = "first_wins"
+//   retention_seconds = 15552000
+//
+//   [dedup]
+//   rows = 100000
+//   seconds = 3600
+//
+//   [anomalies]
+//   window = 50
+//   min_samples = 10
+//   z_score = 3.0
+//
+//   [scripting]
+//   path = "rules.rhai"
+//   max_operations = 100000
+//
+//   [plugin]
+//   path = "policy.wasm"
+//   fuel = 1000000
+//   max_memory_bytes = 16777216
+//
+//   [input]
+//   delimiter = ";"
+//   columns = { type = "kind", client = "customer_id" }
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // Client ids by tier name
+    #[serde(default)]
+    pub tiers: HashMap<String, Vec<u16>>,
+    // Evaluated in order, the first matching rule decides
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub disputes: DisputeSettings,
+    #[serde(default)]
+    pub admin: AdminSettings,
+    #[serde(default)]
+    pub bonus: BonusSettings,
+    #[serde(default)]
+    pub input: InputSettings,
+    #[serde(default)]
+    pub dedup: DedupSettings,
+    #[serde(default)]
+    pub history: HistorySettings,
+    pub anomalies: Option<AnomalySettings>,
+    pub scripting: Option<ScriptSettings>,
+    pub plugin: Option<PluginSettings>,
+}
+
+// Rule script evaluated on the deposits and withdrawals no rule matches, see
+// scripting::Script. Needs the scripting feature.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptSettings {
+    pub path: String,
+    // Operations allowed per transaction, 100000 when not set
+    pub max_operations: Option<u64>,
+}
+
+// WebAssembly validation module evaluated after the rule script, see plugins::Plugin. Needs
+// the plugins feature.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PluginSettings {
+    pub path: String,
+    // Fuel given to each call, 1000000 when not set
+    pub fuel: Option<u64>,
+    // Linear memory the module can grow to, 16 MiB when not set
+    pub max_memory_bytes: Option<usize>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HistorySettings {
+    #[serde(default)]
+    pub reused_tx: ReusedTx,
+    // History entries dated more than this many seconds before the latest one of the history
+    // can't be disputed anymore, and are evicted along with the charged back ones. Nothing is
+    // evicted when it isn't set.
+    pub retention_seconds: Option<u64>,
+}
+
+// What a deposit or withdrawal reusing the tx id of a recorded transaction does. Disputes
+// reference the record kept in the history.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReusedTx {
+    // Applied, and replaces the recorded transaction in the history
+    #[default]
+    LastWins,
+    // Applied, the history keeps the recorded transaction
+    FirstWins,
+    // Ignored, as "Transaction id already used"
+    Reject,
+    // Ignored, and the event is flagged with the "reused tx id" rule
+    RejectAndFlag,
+}
+
+// A deposit or withdrawal whose tx id was seen in the last `rows` rows recorded, and within
+// `seconds` of it (from the timestamp column), is a redelivery and is ignored. There is no
+// deduplication when neither is set.
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DedupSettings {
+    pub rows: Option<usize>,
+    pub seconds: Option<u64>,
+}
+
+impl DedupSettings {
+    pub fn enabled(&self) -> bool {
+        self.rows.is_some() || self.seconds.is_some()
+    }
+}
+
+// A deposit or withdrawal more than `z_score` standard deviations away from the mean of the
+// client's last `window` amounts of the same type is flagged, once `min_samples` are known.
+// Defaults to 50, 10 and 3.0 when not set.
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AnomalySettings {
+    pub window: Option<usize>,
+    pub min_samples: Option<usize>,
+    pub z_score: Option<f64>,
+}
+
+// Layout of the partner csv files, when it isn't the engine's own
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InputSettings {
+    // A single character, a comma when not set
+    pub delimiter: Option<String>,
+    #[serde(default)]
+    pub columns: ColumnNames,
+}
+
+// Header of the file for each column of the engine, the engine's name when not set
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnNames {
+    #[serde(rename = "type")]
+    pub category: Option<String>,
+    pub client: Option<String>,
+    pub tx: Option<String>,
+    pub amount: Option<String>,
+    pub reason: Option<String>,
+    pub timestamp: Option<String>,
+    pub wallet: Option<String>,
+}
+
+impl InputSettings {
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter.as_deref().map_or(b',', |d| d.as_bytes()[0])
+    }
+
+    // Headers of the file renamed to the engine's columns
+    pub fn rename_headers(&self, headers: &csv::StringRecord) -> csv::StringRecord {
+        let c = &self.columns;
+        let renames = [
+            (&c.category, "type"),
+            (&c.client, "client"),
+            (&c.tx, "tx"),
+            (&c.amount, "amount"),
+            (&c.reason, "reason"),
+            (&c.timestamp, "timestamp"),
+            (&c.wallet, "wallet"),
+        ];
+        headers
+            .iter()
+            .map(|header| {
+                renames
+                    .iter()
+                    .find(|(name, _)| name.as_deref() == Some(header))
+                    .map_or(header, |(_, column)| column)
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BonusSettings {
+    // A chargeback claws back the bonuses granted within this many seconds before it. Bonuses
+    // are never clawed back when not set.
+    pub clawback_window_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AdminSettings {
+    // Whether adjustment rows are applied, they are ignored otherwise
+    #[serde(default)]
+    pub adjustments: bool,
+    // Whether opening_balance rows are applied, they are ignored otherwise
+    #[serde(default)]
+    pub opening_balances: bool,
+    // Client the residual balance of closed clients is swept to, none by default
+    pub house_account: Option<u16>,
+    // Client the balances of erased clients are folded into, erasures are refused without it
+    pub anonymized_account: Option<u16>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeSettings {
+    #[serde(default)]
+    pub client_mismatch: ClientMismatch,
+    #[serde(default)]
+    pub withdrawals: WithdrawalDisputes,
+    // A dispute flow row coming before the transaction it references is parked until that
+    // transaction arrives, if it does within this many rows and seconds (from the timestamp
+    // column). Nothing is parked when neither is set.
+    pub grace_rows: Option<usize>,
+    pub grace_seconds: Option<u64>,
+    #[serde(default)]
+    pub insufficient_funds: InsufficientFunds,
+}
+
+// What to do with a dispute, review, resolve or chargeback whose client isn't the client of
+// the transaction it references
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientMismatch {
+    // Ignored, as "Transaction belongs to another client"
+    #[default]
+    Reject,
+    // Applied to the client of the referenced transaction
+    Route,
+}
+
+// What a dispute of a withdrawal does
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalDisputes {
+    // Ignored, as "Only deposits can be disputed"
+    #[default]
+    Reject,
+    // Like card issuers with a disputed debit : the amount is credited back to held, a
+    // resolve takes it back and a chargeback makes it available
+    ProvisionalCredit,
+}
+
+// What a dispute of a deposit does when the client has less available funds than the deposit,
+// e.g. after withdrawing it
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InsufficientFunds {
+    // The whole amount is held, the available funds go negative
+    #[default]
+    AllowNegative,
+    // Only the available funds are held, the event is flagged with the "capped hold" rule
+    Cap,
+    // Nothing is held until a deposit of the client covers the amount, the dispute is
+    // deferred in the meantime
+    Defer,
+}
+
+impl Config {
+    pub fn tier(&self, client_id: u16) -> Option<&str> {
+        self.tiers
+            .iter()
+            .find(|(_, clients)| clients.contains(&client_id))
+            .map(|(tier, _)| tier.as_str())
+    }
+}
+
+pub fn load_config(path: &str) -> Result<Config, Box<dyn Error>> {
+    let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+    if let Some(delimiter) = &config.input.delimiter {
+        if delimiter.len() != 1 {
+            return Err(format!(
+                "The input delimiter must be a single character : {}",
+                delimiter
+            )
+            .into());
+        }
+    }
+    Ok(config)
+}
@@ -0,0 +1,60 @@
+// Amounts of the human readable outputs (the table and the text statements). The machine
+// formats keep the raw amounts with 4 decimals whatever the display currency.
+
+// Symbol of the common ISO 4217 codes, other codes are written before the amount
+fn symbol(code: &str) -> Option<&'static str> {
+    match code.to_ascii_uppercase().as_str() {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "JPY" | "CNY" => Some("¥"),
+        "INR" => Some("₹"),
+        "KRW" => Some("₩"),
+        _ => None,
+    }
+}
+
+// 1234.5 is 1234.5000 without a currency, $1,234.5000 in USD and CHF 1,234.5000 in CHF
+pub fn format_amount(amount: f64, currency: Option<&str>) -> String {
+    let Some(code) = currency else {
+        return format!("{:.4}", amount);
+    };
+    let digits = format!("{:.4}", amount.abs());
+    let (units, decimals) = digits.split_once('.').unwrap_or((&digits, ""));
+    let mut grouped = String::new();
+    for (i, digit) in units.chars().enumerate() {
+        if i > 0 && (units.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if amount < 0.0 && digits.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+        "-"
+    } else {
+        ""
+    };
+    match symbol(code) {
+        Some(symbol) => format!("{}{}{}.{}", sign, symbol, grouped, decimals),
+        None => format!(
+            "{}{} {}.{}",
+            sign,
+            code.to_ascii_uppercase(),
+            grouped,
+            decimals
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grouped_amounts() {
+        assert_eq!(format_amount(1234.5, None), "1234.5000");
+        assert_eq!(format_amount(1234.5, Some("usd")), "$1,234.5000");
+        assert_eq!(format_amount(-1234567.25, Some("EUR")), "-€1,234,567.2500");
+        assert_eq!(format_amount(999.0, Some("CHF")), "CHF 999.0000");
+        assert_eq!(format_amount(-0.00001, Some("USD")), "$0.0000");
+    }
+}
@@ -0,0 +1,138 @@
+// Dates of the command line and of the statements. The engine only deals with unix times in
+// seconds, the dates are read and written in UTC.
+
+const DAY: u64 = 24 * 60 * 60;
+
+// A unix time in seconds or a UTC date time : 2024-03-31T23:59:59Z
+pub fn parse_time(value: &str) -> Result<u64, String> {
+    if let Ok(seconds) = value.parse() {
+        return Ok(seconds);
+    }
+    let incorrect = || format!("Incorrect date : {}, expected 2024-03-31T23:59:59Z", value);
+    let (date, time) = value
+        .strip_suffix('Z')
+        .and_then(|value| value.split_once('T'))
+        .ok_or_else(incorrect)?;
+    let date: Vec<u64> = numbers(date, '-').ok_or_else(incorrect)?;
+    let time: Vec<u64> = numbers(time, ':').ok_or_else(incorrect)?;
+    let (&[year, month, day], &[hours, minutes, seconds]) = (&date[..], &time[..]) else {
+        return Err(incorrect());
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 59
+    {
+        return Err(incorrect());
+    }
+    Ok(days_since_epoch(year, month, day)? * DAY + hours * 3600 + minutes * 60 + seconds)
+}
+
+// First second of a month given as 2024-03, and first second of the next one
+pub fn parse_month(value: &str) -> Result<(u64, u64), String> {
+    let incorrect = || format!("Incorrect month : {}, expected 2024-03", value);
+    let (year, month) = value.split_once('-').ok_or_else(incorrect)?;
+    let (Ok(year), Ok(month)) = (year.parse::<u64>(), month.parse::<u64>()) else {
+        return Err(incorrect());
+    };
+    if !(1..=12).contains(&month) {
+        return Err(incorrect());
+    }
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    Ok((
+        days_since_epoch(year, month, 1)? * DAY,
+        days_since_epoch(next_year, next_month, 1)? * DAY,
+    ))
+}
+
+// 2024-03-31T23:59:59Z
+pub fn format_time(time: u64) -> String {
+    let (year, month, day) = civil_from_days(time / DAY);
+    let seconds = time % DAY;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn numbers(value: &str, separator: char) -> Option<Vec<u64>> {
+    value.split(separator).map(|n| n.parse().ok()).collect()
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days from 1970-01-01 to the date, see http://howardhinnant.github.io/date_algorithms.html
+fn days_since_epoch(year: u64, month: u64, day: u64) -> Result<u64, String> {
+    if year < 1970 {
+        return Err(format!("Dates before 1970 aren't supported : {}", year));
+    }
+    // Years starting in March, so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Ok(era * 146097 + day_of_era - 719468)
+}
+
+// Inverse of days_since_epoch
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_dates() {
+        assert_eq!(parse_time("1711929599"), Ok(1711929599));
+        assert_eq!(parse_time("1970-01-01T00:00:00Z"), Ok(0));
+        assert_eq!(parse_time("2024-03-31T23:59:59Z"), Ok(1711929599));
+        assert_eq!(parse_time("2024-02-29T12:00:00Z"), Ok(1709208000));
+        assert!(parse_time("2023-02-29T12:00:00Z").is_err());
+        assert!(parse_time("2024-03-31T24:00:00Z").is_err());
+        assert!(parse_time("2024-03-31 23:59:59").is_err());
+        assert!(parse_time("1969-12-31T23:59:59Z").is_err());
+    }
+
+    #[test]
+    fn months() {
+        assert_eq!(parse_month("2024-03"), Ok((1709251200, 1711929600)));
+        assert_eq!(parse_month("2023-12"), Ok((1701388800, 1704067200)));
+        assert!(parse_month("2024-13").is_err());
+        assert!(parse_month("2024").is_err());
+        assert_eq!(format_time(1711929599), "2024-03-31T23:59:59Z");
+        assert_eq!(format_time(1709208000), "2024-02-29T12:00:00Z");
+        assert_eq!(format_time(0), "1970-01-01T00:00:00Z");
+    }
+}
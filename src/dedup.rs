@@ -0,0 +1,70 @@
+use crate::config::DedupSettings;
+use std::collections::{HashSet, VecDeque};
+
+// Tx ids of the deposits and withdrawals recently processed, so the messages redelivered by an
+// at-least-once source don't credit or debit a client twice. Bounded by the rows and seconds
+// of the dedup settings, and kept in memory only : it starts empty on each run.
+#[derive(Clone, Debug, Default)]
+pub struct DedupWindow {
+    // Oldest first, with the timestamp of the row
+    entries: VecDeque<(u32, Option<u64>)>,
+    txs: HashSet<u32>,
+}
+
+impl DedupWindow {
+    // Forgets what is out of the window as of `timestamp` (or of the last row without one)
+    // before checking `tx`
+    pub fn contains(&mut self, tx: u32, timestamp: Option<u64>, settings: &DedupSettings) -> bool {
+        let now = timestamp.or_else(|| self.entries.back().and_then(|(_, at)| *at));
+        while let Some(&(oldest, at)) = self.entries.front() {
+            let too_many = settings.rows.is_some_and(|rows| self.entries.len() > rows);
+            let too_old = match (settings.seconds, at, now) {
+                (Some(seconds), Some(at), Some(now)) => now.saturating_sub(at) > seconds,
+                _ => false,
+            };
+            if !too_many && !too_old {
+                break;
+            }
+            self.entries.pop_front();
+            self.txs.remove(&oldest);
+        }
+        self.txs.contains(&tx)
+    }
+
+    pub fn push(&mut self, tx: u32, timestamp: Option<u64>) {
+        self.entries.push_back((tx, timestamp));
+        self.txs.insert(tx);
+    }
+
+    // For a rollback. What was forgotten meanwhile stays forgotten, as it would be again once
+    // the rows are processed anew.
+    pub fn pop(&mut self) {
+        if let Some((tx, _)) = self.entries.pop_back() {
+            self.txs.remove(&tx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_bounds() {
+        let settings: DedupSettings = toml::from_str("rows = 2\nseconds = 60").unwrap();
+        let mut window = DedupWindow::default();
+        window.push(1, Some(100));
+        window.push(2, Some(110));
+        assert!(window.contains(1, Some(120), &settings));
+        window.push(3, Some(120));
+        // Only the last 2 are kept
+        assert!(!window.contains(1, Some(120), &settings));
+        assert!(window.contains(2, Some(120), &settings));
+        // 2 is 65 seconds older than this row
+        assert!(!window.contains(2, Some(175), &settings));
+        assert!(window.contains(3, None, &settings));
+
+        window.pop();
+        assert!(!window.contains(3, None, &settings));
+    }
+}
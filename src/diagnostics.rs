@@ -0,0 +1,150 @@
+use serde::Serialize;
+use std::fmt;
+
+// Where and why an input row failed to parse, so the exact cell can be pointed at.
+// Lines and columns start at 1, the header being line 1.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ParseDiagnostic {
+    pub line: Option<u64>,
+    pub column: Option<usize>,
+    pub field: Option<String>,
+    pub expected: Option<&'static str>,
+    pub raw_value: Option<String>,
+    pub message: String,
+}
+
+impl ParseDiagnostic {
+    // Error of a row read from the csv file, deserialized with the headers
+    pub fn new(
+        error: &csv::Error,
+        headers: &csv::StringRecord,
+        record: &csv::StringRecord,
+    ) -> Self {
+        let field = match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.field().map(|field| field as usize),
+            _ => None,
+        };
+        let name = field.and_then(|field| headers.get(field));
+        ParseDiagnostic {
+            line: record
+                .position()
+                .or(error.position())
+                .map(|position| position.line()),
+            column: field.map(|field| field + 1),
+            field: name.map(str::to_string),
+            expected: name.and_then(expected),
+            raw_value: field
+                .and_then(|field| record.get(field))
+                .map(str::to_string),
+            message: match error.kind() {
+                csv::ErrorKind::Deserialize { err, .. } => err.kind().to_string(),
+                _ => error.to_string(),
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("A diagnostic always serializes")
+    }
+}
+
+// What each column of the input accepts. An unknown type lists the valid ones in its message,
+// see TransactionCategory::names.
+fn expected(field: &str) -> Option<&'static str> {
+    match field {
+        "client" => Some("a client id, integer between 0 and 65535"),
+        "tx" => Some("a transaction id, integer between 0 and 4294967295"),
+        "amount" => Some("a decimal number, or nothing for the dispute flow"),
+        "reason" => Some("a reason code"),
+        "timestamp" => Some("a unix time in seconds"),
+        _ => None,
+    }
+}
+
+// Errors that aren't tied to a row, like a file that can't be read
+impl From<csv::Error> for ParseDiagnostic {
+    fn from(error: csv::Error) -> Self {
+        ParseDiagnostic {
+            line: error.position().map(|position| position.line()),
+            column: None,
+            field: None,
+            expected: None,
+            raw_value: None,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<std::io::Error> for ParseDiagnostic {
+    fn from(error: std::io::Error) -> Self {
+        csv::Error::from(error).into()
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}", line)?;
+            if let (Some(column), Some(field)) = (self.column, &self.field) {
+                write!(f, ", column {} ({})", column, field)?;
+            }
+            write!(f, " : ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(raw_value) = &self.raw_value {
+            write!(f, ", found {:?}", raw_value)?;
+        }
+        if let Some(expected) = self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseDiagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn diagnostic_of_an_invalid_cell() {
+        let diagnostic =
+            get_transactions_from_file("src/testSamples/invalidAmountType.csv").unwrap_err();
+        assert_eq!(diagnostic.line, Some(2));
+        assert_eq!(diagnostic.column, Some(4));
+        assert_eq!(diagnostic.field.as_deref(), Some("amount"));
+        assert_eq!(diagnostic.raw_value.as_deref(), Some("a"));
+        assert_eq!(
+            diagnostic.to_string(),
+            "line 2, column 4 (amount) : invalid float literal, found \"a\", expected a decimal number, or nothing for the dispute flow"
+        );
+        assert!(diagnostic
+            .to_json()
+            .starts_with(r#"{"line":2,"column":4,"field":"amount","#));
+
+        let diagnostic =
+            get_transactions_from_file("src/testSamples/invalidClientID.csv").unwrap_err();
+        assert_eq!(diagnostic.field.as_deref(), Some("client"));
+    }
+
+    #[test]
+    fn unknown_types() {
+        let input = "type,client,tx,amount\nrefund,1,1,1.0\n";
+        let message = crate::read_transactions(input.as_bytes())
+            .unwrap_err()
+            .message;
+        assert!(message.starts_with(
+            "unknown transaction type `refund`, expected one of deposit, withdrawal, dispute"
+        ));
+        for category in ["freeze", "close", "opening_balance", "transfer", "bonus"] {
+            assert!(message.contains(category));
+        }
+
+        crate::handlers::register_category("loyalty_points").unwrap();
+        let message = crate::read_transactions(input.as_bytes())
+            .unwrap_err()
+            .message;
+        assert!(message.contains(", loyalty_points"));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{Client, Engine, Outcome, Transaction};
+use std::collections::HashMap;
+use std::io::Write;
+
+// Would-be effect of a file on an engine state, the processed engine itself being thrown away
+pub struct DryRunReport {
+    before: HashMap<u16, Client>,
+    after: HashMap<u16, Client>,
+    applied: usize,
+    held: usize,
+    deferred: usize,
+    // Row in the file, transaction, and why the rules ignored it
+    ignored: Vec<(usize, Transaction, &'static str)>,
+    // Transactions that would have stopped a real run
+    rejected: Vec<(usize, Transaction, String)>,
+}
+
+pub fn dry_run(mut engine: Engine, transactions: &[Transaction]) -> DryRunReport {
+    let before = engine.clients.clone();
+    let mut applied = 0;
+    let mut held = 0;
+    let mut deferred = 0;
+    let mut ignored = Vec::new();
+    let mut rejected = Vec::new();
+    for (i, t) in transactions.iter().enumerate() {
+        match engine.process(t) {
+            Ok(event) => match event.outcome {
+                Outcome::Applied => applied += 1,
+                Outcome::Held => held += 1,
+                Outcome::Deferred => deferred += 1,
+                Outcome::Ignored(reason) => ignored.push((i + 1, t.to_owned(), reason)),
+            },
+            Err(e) => rejected.push((i + 1, t.to_owned(), e)),
+        }
+    }
+    DryRunReport {
+        before,
+        after: engine.clients,
+        applied,
+        held,
+        deferred,
+        ignored,
+        rejected,
+    }
+}
+
+impl DryRunReport {
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        let default = Client::default();
+        let mut changed: Vec<(&u16, &Client, &Client)> = self
+            .after
+            .iter()
+            .map(|(client_id, after)| {
+                (
+                    client_id,
+                    self.before.get(client_id).unwrap_or(&default),
+                    after,
+                )
+            })
+            .filter(|(_, before, after)| {
+                before.available != after.available
+                    || before.held != after.held
+                    || before.total != after.total
+                    || before.locked != after.locked
+                    || before.frozen != after.frozen
+            })
+            .collect();
+        changed.sort_by_key(|(client_id, _, _)| **client_id);
+
+        writeln!(writer, "Changed clients :")?;
+        for (client_id, before, after) in &changed {
+            writeln!(
+                writer,
+                "  client {} : available {:.4} -> {:.4}, held {:.4} -> {:.4}, total {:.4} -> {:.4}{}{}",
+                client_id,
+                before.available,
+                after.available,
+                before.held,
+                after.held,
+                before.total,
+                after.total,
+                if after.locked && !before.locked {
+                    ", newly locked"
+                } else {
+                    ""
+                },
+                match (before.frozen, after.frozen) {
+                    (false, true) => ", newly frozen",
+                    (true, false) => ", unfrozen",
+                    _ => "",
+                }
+            )?;
+        }
+        writeln!(writer, "Ignored transactions :")?;
+        for (row, t, reason) in &self.ignored {
+            writeln!(
+                writer,
+                "  row {} : {} of tx {} for client {} : {}",
+                row,
+                t.category.as_str(),
+                t.tx,
+                t.client_id,
+                reason
+            )?;
+        }
+        writeln!(writer, "Rejected transactions :")?;
+        for (row, t, reason) in &self.rejected {
+            writeln!(
+                writer,
+                "  row {} : {} of tx {} for client {} : {}",
+                row,
+                t.category.as_str(),
+                t.tx,
+                t.client_id,
+                reason
+            )?;
+        }
+        let newly_locked = changed
+            .iter()
+            .filter(|(_, before, after)| after.locked && !before.locked)
+            .count();
+        writeln!(
+            writer,
+            "{} applied, {} held, {} deferred, {} ignored, {} rejected, {} clients changed, {} newly locked. Nothing was saved.",
+            self.applied,
+            self.held,
+            self.deferred,
+            self.ignored.len(),
+            self.rejected.len(),
+            changed.len(),
+            newly_locked
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn report_changes_without_touching_state() {
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let candidate = get_transactions_from_file("src/testSamples/dryRun.csv").unwrap();
+        let report = dry_run(engine, &candidate);
+        let mut output = Vec::new();
+        report.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            "Changed clients :
+  client 1 : available 0.5000 -> 0.5000, held 1.0000 -> 0.0000, total 1.5000 -> 0.5000, newly locked
+  client 2 : available 2.0000 -> 5.0000, held 0.0000 -> 0.0000, total 2.0000 -> 5.0000
+Ignored transactions :
+  row 2 : dispute of tx 1 for client 1 : Transaction is already under dispute
+  row 4 : deposit of tx 11 for client 1 : Client account is locked
+Rejected transactions :
+  row 5 : deposit of tx 12 for client 3 : Cannot deposit a negative amount
+2 applied, 0 held, 0 deferred, 2 ignored, 1 rejected, 2 clients changed, 1 newly locked. Nothing was saved.
+"
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sha2::{Digest, Sha256};
+
+const NONCE_LENGTH: usize = 12;
+
+// Key of the persisted state, derived from any key material with SHA-256 so a passphrase
+// or the output of `openssl rand -hex 32` can be used as is
+#[derive(Clone)]
+pub struct StateKey(Key<Aes256Gcm>);
+
+impl StateKey {
+    pub fn new(material: &[u8]) -> Self {
+        StateKey(Sha256::digest(material))
+    }
+
+    // AES-256-GCM, the file name is authenticated along with the content so encrypted
+    // files can't be swapped. The random nonce is written in front of the ciphertext.
+    pub fn encrypt(&self, name: &str, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes256Gcm::new(&self.0)
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .expect("AES-GCM encryption can't fail on in-memory buffers");
+        [nonce.as_slice(), &ciphertext].concat()
+    }
+
+    pub fn decrypt(&self, name: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.len() < NONCE_LENGTH {
+            return Err(format!("{} is too short to be encrypted state", name));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        Aes256Gcm::new(&self.0)
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map_err(|_| format!("Cannot decrypt {} : wrong key or modified file", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let key = StateKey::new(b"passphrase");
+        let encrypted = key.encrypt("clients.csv", b"client,available");
+        assert_ne!(&encrypted[NONCE_LENGTH..], b"client,available");
+        assert_eq!(
+            key.decrypt("clients.csv", &encrypted).unwrap(),
+            b"client,available"
+        );
+        assert!(key.decrypt("history.csv", &encrypted).is_err());
+        assert!(StateKey::new(b"other")
+            .decrypt("clients.csv", &encrypted)
+            .is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::config::InputSettings;
+use std::error::Error;
+use std::io::Write;
+
+// Copies the header and the rows of a client, optionally of a single category, byte for byte :
+// a minimal reproduction keeps the spacing, quoting and line endings of the original file.
+// Returns the number of rows copied.
+pub fn extract_rows<W: Write>(
+    input: &[u8],
+    settings: &InputSettings,
+    client: u16,
+    category: Option<&str>,
+    writer: &mut W,
+) -> Result<usize, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(settings.delimiter())
+        .flexible(true)
+        .from_reader(input);
+    let headers = settings.rename_headers(rdr.headers()?);
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| format!("The input has no {} column", name))
+    };
+    let (client_column, type_column) = (column("client")?, column("type")?);
+
+    // The reader is always at the start of the next row
+    let mut start = rdr.position().byte() as usize;
+    writer.write_all(&input[..start])?;
+    let mut record = csv::StringRecord::new();
+    let mut extracted = 0;
+    while rdr.read_record(&mut record)? {
+        let end = rdr.position().byte() as usize;
+        let matches = record.get(client_column).and_then(|c| c.parse().ok()) == Some(client)
+            && category.is_none_or(|category| record.get(type_column) == Some(category));
+        if matches {
+            writer.write_all(&input[start..end])?;
+            extracted += 1;
+        }
+        start = end;
+    }
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_rows_of_a_client() {
+        let input = std::fs::read("src/testSamples/disputeLifecycle.csv").unwrap();
+
+        let mut output = Vec::new();
+        let extracted =
+            extract_rows(&input, &InputSettings::default(), 1, None, &mut output).unwrap();
+        assert_eq!(extracted, 7);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type, client, tx, amount, reason, timestamp
+deposit, 1, 1, 1.0, , 1700000000
+deposit, 1, 3, 3.0, , 1700000020
+dispute, 1, 1, , , 1700000100
+dispute, 1, 3, , duplicate, 1700000300
+review, 1, 3, , , 1700000350
+review, 1, 3, , , 1700000360
+resolve, 1, 1, , , 1700000400
+"
+        );
+
+        let mut output = Vec::new();
+        let settings = InputSettings::default();
+        let extracted = extract_rows(&input, &settings, 2, Some("dispute"), &mut output).unwrap();
+        assert_eq!(extracted, 1);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("\ndispute, 2, 2, , fraud, 1700000200\n"));
+    }
+}
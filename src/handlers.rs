@@ -0,0 +1,154 @@
+use crate::{Client, Outcome, Transaction};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+// Names of the custom categories, process wide : the input is parsed before it reaches an
+// engine, so a name registered on one engine is parsed for all of them. Each distinct name is
+// leaked once, however many engines register it, and stays registered until the process
+// exits.
+static CATEGORIES: RwLock<Vec<&'static str>> = RwLock::new(Vec::new());
+
+// Semantics of a custom `type` value (e.g. `cashback` or `tax`), registered on an engine with
+// Engine::register_handler. The funds it moves are in the main wallet, and available + held
+// must stay equal to total. Returning an error leaves the engine untouched, as for the built in
+// categories. The transactions of custom categories aren't recorded in the history.
+pub trait TransactionHandler: Send {
+    fn apply(
+        &mut self,
+        transaction: &Transaction,
+        client: &mut Client,
+        history: History,
+    ) -> Result<Outcome, String>;
+}
+
+// Read only view of the transactions history for the handlers, previous runs included
+pub struct History<'a> {
+    pub(crate) transactions: &'a HashMap<u32, Transaction>,
+}
+
+impl History<'_> {
+    pub fn get(&self, tx: u32) -> Option<&Transaction> {
+        self.transactions.get(&tx)
+    }
+
+    // In no particular order
+    pub fn of_client(&self, client_id: u16) -> impl Iterator<Item = &Transaction> {
+        self.transactions
+            .values()
+            .filter(move |t| t.client_id == client_id)
+    }
+}
+
+// Makes `name` a valid `type` value of the input, along with the built in ones, for the rest
+// of the process
+pub fn register_category(name: &str) -> Result<&'static str, String> {
+    if crate::TransactionCategory::built_in(name).is_some() {
+        return Err(format!("{} is a built in category", name));
+    }
+    let mut categories = CATEGORIES.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(category) = categories.iter().find(|category| **category == name) {
+        return Ok(category);
+    }
+    let category: &'static str = Box::leak(name.to_string().into_boxed_str());
+    categories.push(category);
+    Ok(category)
+}
+
+// In registration order
+pub fn custom_categories() -> Vec<&'static str> {
+    CATEGORIES.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn custom_category(name: &str) -> Option<&'static str> {
+    let categories = CATEGORIES.read().unwrap_or_else(|e| e.into_inner());
+    categories
+        .iter()
+        .find(|category| **category == name)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_transactions, state, Engine, Event, TransactionCategory};
+
+    // Credits 2% of the deposit referenced by the tx column
+    struct Cashback;
+
+    impl TransactionHandler for Cashback {
+        fn apply(
+            &mut self,
+            transaction: &Transaction,
+            client: &mut Client,
+            history: History,
+        ) -> Result<Outcome, String> {
+            let Some(deposit) = history
+                .get(transaction.tx)
+                .filter(|t| t.category == TransactionCategory::Deposit)
+            else {
+                return Ok(Outcome::Ignored("Unknown transaction"));
+            };
+            let cashback = deposit.amount.unwrap_or_default() * 0.02;
+            client.available += cashback;
+            client.total += cashback;
+            Ok(Outcome::Applied)
+        }
+    }
+
+    #[test]
+    fn custom_categories() {
+        let input = "type, client, tx, amount
+deposit, 1, 1, 50.0
+cashback, 1, 1,
+cashback, 1, 2,
+";
+        assert!(read_transactions(input.as_bytes()).is_err());
+
+        let mut engine = Engine::default();
+        engine
+            .register_handler("cashback", Box::new(Cashback))
+            .unwrap();
+        assert!(engine
+            .register_handler("deposit", Box::new(Cashback))
+            .is_err());
+        let transactions = read_transactions(input.as_bytes()).unwrap();
+        assert_eq!(transactions[1].category.as_str(), "cashback");
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[1].outcome, Outcome::Applied);
+        assert_eq!(events[2].outcome, Outcome::Ignored("Unknown transaction"));
+        assert_eq!(engine.clients()[&1].total, 51.0);
+
+        // Registered by another engine, but without a handler on this one
+        let mut engine = Engine::default();
+        assert_eq!(
+            engine.process(&transactions[1]).unwrap().outcome,
+            Outcome::Ignored("No handler for this transaction type")
+        );
+    }
+
+    #[test]
+    fn handlers_see_previous_runs() {
+        let directory = std::env::temp_dir().join("payments-engine-handlers-test");
+        let _ = std::fs::remove_dir_all(&directory);
+        let directory = directory.to_str().unwrap();
+        let mut engine = Engine::default();
+        engine
+            .register_handler("cashback", Box::new(Cashback))
+            .unwrap();
+        let deposit = read_transactions("type,client,tx,amount\ndeposit,1,1,50.0\n".as_bytes());
+        engine.process(&deposit.unwrap()[0]).unwrap();
+        state::save_engine(&engine, directory).unwrap();
+
+        let mut engine = state::load_engine(directory).unwrap();
+        engine
+            .register_handler("cashback", Box::new(Cashback))
+            .unwrap();
+        let cashback = read_transactions("type,client,tx,amount\ncashback,1,1,\n".as_bytes());
+        let event = engine.process(&cashback.unwrap()[0]).unwrap();
+        assert_eq!(event.outcome, Outcome::Applied);
+        assert_eq!(engine.clients()[&1].total, 51.0);
+    }
+}
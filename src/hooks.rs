@@ -0,0 +1,78 @@
+use crate::{Client, Event, Transaction};
+
+// Extension points of the processing loop for the applications embedding the engine, e.g. a
+// veto from an external fraud service, tagging the rows or counting the outcomes. Set with
+// Engine::set_hooks, both methods do nothing by default.
+pub trait Hooks: Send {
+    // Before each transaction, with the client (or account) of the row when the engine knows
+    // it. The transaction can be changed, and an error ignores it with that reason : the
+    // balances are left untouched and the event tells the reason.
+    fn on_before_apply(
+        &mut self,
+        _transaction: &mut Transaction,
+        _client: Option<&Client>,
+    ) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    // After each transaction processed into an event, with the client it applied to. Not
+    // called for the transactions returning an error.
+    fn on_after_apply(&mut self, _event: &Event, _client: Option<&Client>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, Engine, Outcome, TransactionCategory};
+    use std::sync::{Arc, Mutex};
+
+    // Vetoes the withdrawals above 1.0, tags the deposits and records the outcomes
+    struct HostPolicy {
+        outcomes: Arc<Mutex<Vec<Outcome>>>,
+    }
+
+    impl Hooks for HostPolicy {
+        fn on_before_apply(
+            &mut self,
+            transaction: &mut Transaction,
+            _client: Option<&Client>,
+        ) -> Result<(), &'static str> {
+            match transaction.category {
+                TransactionCategory::Withdrawal if transaction.amount > Some(1.0) => {
+                    Err("Over the host's limit")
+                }
+                TransactionCategory::Deposit => {
+                    transaction.reason = Some("checked".to_string());
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn on_after_apply(&mut self, event: &Event, _client: Option<&Client>) {
+            self.outcomes.lock().unwrap().push(event.outcome.clone());
+        }
+    }
+
+    #[test]
+    fn veto_and_enrichment() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/providedExample.csv").unwrap();
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::default();
+        engine.set_hooks(Box::new(HostPolicy {
+            outcomes: outcomes.clone(),
+        }));
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+
+        assert_eq!(events[0].transaction.reason.as_deref(), Some("checked"));
+        assert_eq!(events[4].outcome, Outcome::Ignored("Over the host's limit"));
+        assert_eq!(engine.clients()[&2].total, 2.0);
+        let outcomes = outcomes.lock().unwrap();
+        assert_eq!(outcomes.len(), events.len());
+        assert_eq!(outcomes[4], events[4].outcome);
+    }
+}
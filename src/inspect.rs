@@ -0,0 +1,283 @@
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+// Lines read to infer the layout of a file, the row count of bigger files is estimated
+const SAMPLE_LINES: usize = 1000;
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+// Headers commonly used by partners for each column of the engine, compared lowercased
+// without punctuation
+const SYNONYMS: [(&str, &[&str]); 7] = [
+    (
+        "type",
+        &[
+            "type",
+            "kind",
+            "category",
+            "transactiontype",
+            "txtype",
+            "txntype",
+            "operation",
+        ],
+    ),
+    (
+        "client",
+        &[
+            "client",
+            "clientid",
+            "customer",
+            "customerid",
+            "account",
+            "accountid",
+            "user",
+            "userid",
+        ],
+    ),
+    (
+        "tx",
+        &[
+            "tx",
+            "txid",
+            "txn",
+            "txnid",
+            "transaction",
+            "transactionid",
+            "id",
+            "reference",
+        ],
+    ),
+    ("amount", &["amount", "value", "amt", "sum"]),
+    ("reason", &["reason", "reasoncode", "note", "memo"]),
+    (
+        "timestamp",
+        &["timestamp", "time", "date", "datetime", "createdat", "ts"],
+    ),
+    ("wallet", &["wallet", "walletid", "walletname"]),
+];
+
+// The engine can't process a file without these
+const REQUIRED: [&str; 3] = ["type", "client", "tx"];
+
+#[derive(Debug, PartialEq)]
+pub enum ColumnKind {
+    Empty,
+    Integer,
+    Decimal,
+    // With a few of its distinct values
+    Text(Vec<String>),
+}
+
+// Layout of a partner file inferred from its first lines
+pub struct Inspection {
+    pub delimiter: u8,
+    pub headers: Vec<String>,
+    pub kinds: Vec<ColumnKind>,
+    pub sampled_rows: usize,
+    // None when the whole file was sampled
+    pub estimated_rows: Option<u64>,
+    // Header of the file for each column of the engine, when one matches
+    pub mapping: Vec<(&'static str, Option<String>)>,
+}
+
+// `size` is the size of the file in bytes, for the row count estimate
+pub fn inspect<R: BufRead>(reader: R, size: u64) -> Result<Inspection, Box<dyn Error>> {
+    let mut lines = Vec::new();
+    let mut whole_file = true;
+    for line in reader.lines() {
+        if lines.len() > SAMPLE_LINES {
+            whole_file = false;
+            break;
+        }
+        lines.push(line?);
+    }
+    let header = lines.first().ok_or("The file is empty")?;
+    let delimiter = detect_delimiter(&lines);
+
+    let sample = lines.join("\n");
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(sample.as_bytes());
+    let headers: Vec<String> = rdr.headers()?.iter().map(str::to_string).collect();
+    let records = rdr.records().collect::<Result<Vec<_>, _>>()?;
+    let kinds = (0..headers.len())
+        .map(|i| column_kind(records.iter().filter_map(|r| r.get(i))))
+        .collect();
+
+    let sampled_bytes = sample.len() - header.len();
+    let estimated_rows = (!whole_file && sampled_bytes > 0).then(|| {
+        size.saturating_sub(header.len() as u64) * records.len() as u64 / sampled_bytes as u64
+    });
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_ascii_lowercase()
+    };
+    let mapping = SYNONYMS
+        .iter()
+        .map(|(column, synonyms)| {
+            let header = headers
+                .iter()
+                .find(|header| synonyms.contains(&normalize(header).as_str()));
+            (*column, header.cloned())
+        })
+        .collect();
+    Ok(Inspection {
+        delimiter,
+        headers,
+        kinds,
+        sampled_rows: records.len(),
+        estimated_rows,
+        mapping,
+    })
+}
+
+// The candidate found the same number of times on every line, the most frequent one first.
+// Quoted fields aren't taken into account, a comma when nothing fits.
+fn detect_delimiter(lines: &[String]) -> u8 {
+    let count = |line: &str, delimiter: u8| line.bytes().filter(|b| *b == delimiter).count();
+    let mut candidates: Vec<(usize, u8)> = DELIMITERS
+        .iter()
+        .map(|&delimiter| (count(&lines[0], delimiter), delimiter))
+        .filter(|&(fields, delimiter)| {
+            fields > 0
+                && lines
+                    .iter()
+                    .filter(|line| !line.trim().is_empty())
+                    .all(|line| count(line, delimiter) == fields)
+        })
+        .collect();
+    candidates.sort_by_key(|&(fields, _)| std::cmp::Reverse(fields));
+    candidates.first().map_or(b',', |&(_, delimiter)| delimiter)
+}
+
+fn column_kind<'a>(values: impl Iterator<Item = &'a str>) -> ColumnKind {
+    let values: Vec<&str> = values.filter(|value| !value.is_empty()).collect();
+    if values.is_empty() {
+        ColumnKind::Empty
+    } else if values.iter().all(|value| value.parse::<i64>().is_ok()) {
+        ColumnKind::Integer
+    } else if values.iter().all(|value| value.parse::<f64>().is_ok()) {
+        ColumnKind::Decimal
+    } else {
+        let mut examples: Vec<String> = Vec::new();
+        for value in values {
+            if examples.len() == 3 {
+                break;
+            }
+            if !examples.iter().any(|example| example == value) {
+                examples.push(value.to_string());
+            }
+        }
+        ColumnKind::Text(examples)
+    }
+}
+
+impl Inspection {
+    // The [input] section of the config reading the file, when it differs from the engine's
+    // layout
+    pub fn config_snippet(&self) -> Option<String> {
+        let quoted = |value: &str| toml::Value::String(value.to_string()).to_string();
+        let columns: Vec<String> = self
+            .mapping
+            .iter()
+            .filter_map(|(column, header)| {
+                let header = header.as_deref().filter(|header| header != column)?;
+                Some(format!("{} = {}", column, quoted(header)))
+            })
+            .collect();
+        if self.delimiter == b',' && columns.is_empty() {
+            return None;
+        }
+        let mut snippet = "[input]\n".to_string();
+        if self.delimiter != b',' {
+            let delimiter = (self.delimiter as char).to_string();
+            snippet += &format!("delimiter = {}\n", quoted(&delimiter));
+        }
+        if !columns.is_empty() {
+            snippet += &format!("columns = {{ {} }}\n", columns.join(", "));
+        }
+        Some(snippet)
+    }
+
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let delimiter = match self.delimiter {
+            b'\t' => "tab".to_string(),
+            delimiter => format!("\"{}\"", delimiter as char),
+        };
+        writeln!(writer, "Delimiter : {}", delimiter)?;
+        match self.estimated_rows {
+            None => writeln!(writer, "Rows : {}", self.sampled_rows)?,
+            Some(rows) => writeln!(
+                writer,
+                "Rows : about {} (estimated from the first {})",
+                rows, self.sampled_rows
+            )?,
+        }
+        writeln!(writer, "Columns :")?;
+        for (header, kind) in self.headers.iter().zip(&self.kinds) {
+            let kind = match kind {
+                ColumnKind::Empty => "empty".to_string(),
+                ColumnKind::Integer => "integer".to_string(),
+                ColumnKind::Decimal => "decimal".to_string(),
+                ColumnKind::Text(examples) => format!("text ({})", examples.join(", ")),
+            };
+            writeln!(writer, "  {} : {}", header, kind)?;
+        }
+        for (column, header) in &self.mapping {
+            if header.is_none() && REQUIRED.contains(column) {
+                writeln!(writer, "No column found for {}", column)?;
+            }
+        }
+        match self.config_snippet() {
+            None => writeln!(writer, "The file has the engine's layout, no config needed"),
+            Some(snippet) => write!(writer, "Proposed config :\n{}", snippet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::read_transactions_with;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    #[test]
+    fn partner_layout() {
+        let path = "src/testSamples/partnerLayout.csv";
+        let size = std::fs::metadata(path).unwrap().len();
+        let inspection = inspect(BufReader::new(File::open(path).unwrap()), size).unwrap();
+
+        let mut output = Vec::new();
+        inspection.write_text(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Delimiter : \";\"
+Rows : 3
+Columns :
+  kind : text (deposit, withdrawal)
+  Customer ID : integer
+  transaction_id : integer
+  value : decimal
+  created_at : integer
+  memo : empty
+Proposed config :
+[input]
+delimiter = \";\"
+columns = { type = \"kind\", client = \"Customer ID\", tx = \"transaction_id\", amount = \"value\", reason = \"memo\", timestamp = \"created_at\" }
+"
+        );
+
+        let config: Config = toml::from_str(&inspection.config_snippet().unwrap()).unwrap();
+        let transactions =
+            read_transactions_with(File::open(path).unwrap(), &config.input).unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(transactions[2].client_id, 2);
+        assert_eq!(transactions[2].amount, Some(7.25));
+        assert_eq!(transactions[2].timestamp, Some(1700000200));
+    }
+}
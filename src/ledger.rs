@@ -0,0 +1,157 @@
+use crate::{Engine, Event, Outcome, Transaction, TransactionCategory};
+
+// Tolerance on the float sums, well below the 4 decimals of the output
+const EPSILON: f64 = 1e-6;
+
+// Double entry view of a run : every change of a client's total is booked against the house
+// account, the client and the house entries of a row summing to zero
+pub struct Entry {
+    pub row: usize,
+    pub client: u16,
+    pub amount: f64,
+}
+
+pub struct LedgerReport {
+    pub entries: Vec<Entry>,
+    // Opposite of the sum of the clients' totals
+    pub house: f64,
+}
+
+// Replays the transactions and checks, after every row, that only the client of the row moved,
+// by the amount the row accounts for, and that the clients and the house still sum to zero.
+// The error names the first row breaking the ledger.
+pub fn verify(transactions: &[Transaction]) -> Result<LedgerReport, String> {
+    let mut engine = Engine::default();
+    let mut report = LedgerReport {
+        entries: Vec::new(),
+        house: 0.0,
+    };
+    for t in transactions {
+        let sum_before = clients_sum(&engine);
+        let before = client_total(&engine, t.client_id);
+        let event = engine.process(t)?;
+        let client_id = event.routed_to.unwrap_or(t.client_id);
+        let fail = |violation: String| {
+            format!(
+                "Row {} ({} of tx {} for client {}) : {}",
+                event.row,
+                t.category.as_str(),
+                t.tx,
+                t.client_id,
+                violation
+            )
+        };
+
+        let moved = clients_sum(&engine) - sum_before;
+        // A routed row moves the client of the referenced transaction instead
+        let movement = match event.routed_to {
+            Some(_) => moved,
+            None => {
+                client_total(&engine, client_id).unwrap_or_default() - before.unwrap_or_default()
+            }
+        };
+        if (moved - movement).abs() > EPSILON {
+            return Err(fail(format!(
+                "the clients moved by {:.4}, the client of the row by {:.4}",
+                moved, movement
+            )));
+        }
+        if let Some(expected) = expected_movement(&event) {
+            if (movement - expected).abs() > EPSILON {
+                return Err(fail(format!(
+                    "the client moved by {:.4} instead of {:.4}",
+                    movement, expected
+                )));
+            }
+        }
+        if let Some(client) = engine.clients().get(&client_id) {
+            if (client.available + client.held - client.total).abs() > EPSILON {
+                return Err(fail(
+                    "available and held don't add up to the total".to_string(),
+                ));
+            }
+        }
+        report.house -= moved;
+        if (report.house + clients_sum(&engine)).abs() > EPSILON {
+            return Err(fail(format!(
+                "the house account ({:.4}) and the clients don't sum to zero",
+                report.house
+            )));
+        }
+        if moved != 0.0 {
+            report.entries.push(Entry {
+                row: event.row,
+                client: client_id,
+                amount: moved,
+            });
+        }
+    }
+    Ok(report)
+}
+
+// None when the movement depends on the state, e.g. the amount of a charged back dispute
+fn expected_movement(event: &Event) -> Option<f64> {
+    let amount = event.transaction.amount.unwrap_or_default();
+    match (&event.outcome, event.transaction.category) {
+        (Outcome::Ignored(_) | Outcome::Deferred, _) => Some(0.0),
+        (
+            _,
+            TransactionCategory::Deposit
+            | TransactionCategory::Adjustment
+            | TransactionCategory::Bonus,
+        ) => Some(amount),
+        (Outcome::Applied, TransactionCategory::Withdrawal) => Some(-amount),
+        // The funds of a held withdrawal stay in the total until it is released
+        (Outcome::Held, TransactionCategory::Withdrawal) => Some(0.0),
+        (
+            _,
+            TransactionCategory::Review
+            | TransactionCategory::Freeze
+            | TransactionCategory::Unfreeze
+            | TransactionCategory::Place
+            | TransactionCategory::Release
+            | TransactionCategory::Transfer
+            | TransactionCategory::AssertBalance,
+        ) => Some(0.0),
+        (
+            _,
+            TransactionCategory::Dispute
+            | TransactionCategory::Resolve
+            | TransactionCategory::Chargeback
+            | TransactionCategory::Capture
+            | TransactionCategory::OpeningBalance
+            | TransactionCategory::Close
+            | TransactionCategory::Custom(_),
+        ) => None,
+    }
+}
+
+fn client_total(engine: &Engine, client_id: u16) -> Option<f64> {
+    engine.clients().get(&client_id).map(|client| client.total)
+}
+
+fn clients_sum(engine: &Engine) -> f64 {
+    engine
+        .clients()
+        .values()
+        .fold(0.0, |sum, client| sum + client.total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, simulation};
+
+    #[test]
+    fn balanced_ledger() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let report = verify(&transactions).unwrap();
+        assert_eq!(report.entries.len(), 4);
+        assert_eq!(report.house, -4.0);
+
+        for seed in 0..3 {
+            assert!(verify(&simulation::workload(seed, 2_000)).is_ok());
+        }
+    }
+}
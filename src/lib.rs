@@ -0,0 +1,2720 @@
+use anomaly::{Anomaly, AnomalyDetector};
+use config::{
+    ClientMismatch, Config, InputSettings, InsufficientFunds, ReusedTx, WithdrawalDisputes,
+};
+use dedup::DedupWindow;
+use diagnostics::ParseDiagnostic;
+use handlers::{History, TransactionHandler};
+use hooks::Hooks;
+use review::Decision;
+use rules::Action;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use tx_filter::TxFilter;
+
+pub mod accounts;
+#[cfg(feature = "amqp")]
+pub mod amqp_input;
+pub mod anomaly;
+#[cfg(feature = "arrow")]
+pub mod arrow_output;
+pub mod concurrent;
+pub mod config;
+pub mod currency;
+pub mod dates;
+pub mod dedup;
+pub mod diagnostics;
+pub mod dry_run;
+pub mod encryption;
+pub mod extract;
+pub mod handlers;
+pub mod hooks;
+pub mod inspect;
+pub mod ledger;
+pub mod logging;
+#[cfg(feature = "object-store")]
+pub mod object_store_io;
+pub mod parallel_parse;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+#[cfg(feature = "postgres")]
+pub mod postgres_state;
+pub mod protobuf;
+pub mod pseudonym;
+#[cfg(feature = "redis")]
+pub mod redis_stream;
+pub mod reference;
+pub mod repl;
+pub mod reports;
+pub mod review;
+pub mod risk;
+pub mod rules;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod sequence;
+pub mod signature;
+pub mod simulation;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_output;
+pub mod state;
+pub mod statements;
+pub mod table_output;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod tx_filter;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring_input;
+pub mod whatif;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Transaction {
+    #[serde(rename = "type")]
+    pub category: TransactionCategory,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub tx: u32,
+    pub amount: Option<f64>,
+    // Optional columns : reason code of a dispute, unix time in seconds and wallet of the
+    // client, the main one when empty
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub wallet: Option<String>,
+    // Sequence number of the producer, only read to put the input back in order (see
+    // sequence.rs) and never written
+    #[serde(default, skip_serializing)]
+    pub seq: Option<u64>,
+}
+
+impl Transaction {
+    // None for the main wallet
+    pub fn wallet(&self) -> Option<&str> {
+        wallet_name(self.wallet.as_deref())
+    }
+}
+
+fn wallet_name(wallet: Option<&str>) -> Option<&str> {
+    wallet.filter(|wallet| !wallet.is_empty() && *wallet != MAIN_WALLET)
+}
+
+pub const MAIN_WALLET: &str = "main";
+pub const BONUS_WALLET: &str = "bonus";
+// Reason of the redelivered deposits and withdrawals ignored by the dedup window
+pub const DUPLICATE: &str = "Duplicate transaction";
+// Reason of the deposits and withdrawals rejected for reusing a recorded tx id, and rule of
+// their events with ReusedTx::RejectAndFlag
+pub const REUSED_TX: &str = "Transaction id already used";
+pub const REUSED_TX_FLAG: &str = "reused tx id";
+// Rule of the events of the disputes holding less than the disputed amount, with
+// InsufficientFunds::Cap
+pub const CAPPED_HOLD_FLAG: &str = "capped hold";
+// Rule of the events of the deposits and withdrawals flagged by the anomaly detector, when no
+// rule of the config is named
+pub const ANOMALY_FLAG: &str = "amount anomaly";
+// Reason of the balance assertions that don't match
+pub const BALANCE_MISMATCH: &str = "Balance assertion failed";
+// Half of the last digit of the output
+const ASSERTION_TOLERANCE: f64 = 0.00005;
+
+// Serialized as its name, see as_str
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransactionCategory {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    // Moves an opened dispute under review, without touching the balances
+    Review,
+    // Admin transactions : a frozen client can't withdraw, but still receives deposits and
+    // goes through the dispute flow. The tx id isn't used.
+    Freeze,
+    Unfreeze,
+    // Admin closure of a client without held funds, locking it. With a house account in the
+    // config, the residual balance is swept to it and the sweep is recorded in the history
+    // under the tx of the row, the house account in the reason column.
+    Close,
+    // Admin correction of the balances, in either direction, with a mandatory reason.
+    // Only applied when the config allows adjustments.
+    Adjustment,
+    // Sets the available funds (and the total) of a client migrated from the legacy system,
+    // rather than adding to them. Only applied when the config allows opening balances, and
+    // to a client without held funds or named wallets.
+    OpeningBalance,
+    // Reconciliation checkpoint of a migration : the available funds of the client should be
+    // the amount, and its total the reason column when given. The balances are left untouched,
+    // a mismatch is ignored and recorded, see Engine::balance_mismatches.
+    AssertBalance,
+    // Escrow of available funds in a named bucket of the client, given in the reason column
+    // (e.g. `order-123`) : placed funds are held until they are released back to available,
+    // or captured out of the account. Without an amount, release and capture the whole bucket.
+    Place,
+    Release,
+    Capture,
+    // Moves available funds from the wallet of the row to the wallet given in the reason column
+    Transfer,
+    // Promotional credit, always in the bonus wallet. It can be clawed back when a deposit of
+    // the client is charged back soon after, see the [bonus] section of the config.
+    Bonus,
+    // A type registered with handlers::register_category, applied by the handler registered
+    // on the engine
+    Custom(&'static str),
+}
+
+impl TransactionCategory {
+    // Every category but the custom ones, in the order the parse errors list them
+    pub const BUILT_IN: [TransactionCategory; 17] = [
+        TransactionCategory::Deposit,
+        TransactionCategory::Withdrawal,
+        TransactionCategory::Dispute,
+        TransactionCategory::Resolve,
+        TransactionCategory::Chargeback,
+        TransactionCategory::Review,
+        TransactionCategory::Freeze,
+        TransactionCategory::Unfreeze,
+        TransactionCategory::Close,
+        TransactionCategory::Adjustment,
+        TransactionCategory::OpeningBalance,
+        TransactionCategory::AssertBalance,
+        TransactionCategory::Place,
+        TransactionCategory::Release,
+        TransactionCategory::Capture,
+        TransactionCategory::Transfer,
+        TransactionCategory::Bonus,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionCategory::Custom(name) => name,
+            TransactionCategory::Deposit => "deposit",
+            TransactionCategory::Withdrawal => "withdrawal",
+            TransactionCategory::Dispute => "dispute",
+            TransactionCategory::Resolve => "resolve",
+            TransactionCategory::Chargeback => "chargeback",
+            TransactionCategory::Review => "review",
+            TransactionCategory::Freeze => "freeze",
+            TransactionCategory::Unfreeze => "unfreeze",
+            TransactionCategory::Close => "close",
+            TransactionCategory::Adjustment => "adjustment",
+            TransactionCategory::OpeningBalance => "opening_balance",
+            TransactionCategory::AssertBalance => "assert_balance",
+            TransactionCategory::Place => "place",
+            TransactionCategory::Release => "release",
+            TransactionCategory::Capture => "capture",
+            TransactionCategory::Transfer => "transfer",
+            TransactionCategory::Bonus => "bonus",
+        }
+    }
+
+    // Valid values of the type column : the built in categories, then the custom ones
+    // registered so far
+    pub fn names() -> Vec<&'static str> {
+        Self::BUILT_IN
+            .iter()
+            .map(TransactionCategory::as_str)
+            .chain(handlers::custom_categories())
+            .collect()
+    }
+
+    pub fn built_in(name: &str) -> Option<Self> {
+        Self::BUILT_IN
+            .into_iter()
+            .find(|category| category.as_str() == name)
+    }
+
+    // The batch path panics on these without an amount, as for a malformed csv row
+    fn needs_amount(&self) -> bool {
+        matches!(
+            self,
+            TransactionCategory::Deposit
+                | TransactionCategory::Withdrawal
+                | TransactionCategory::Adjustment
+                | TransactionCategory::OpeningBalance
+                | TransactionCategory::AssertBalance
+                | TransactionCategory::Transfer
+                | TransactionCategory::Bonus
+        )
+    }
+}
+
+impl Serialize for TransactionCategory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionCategory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        TransactionCategory::built_in(&name)
+            .or_else(|| handlers::custom_category(&name).map(TransactionCategory::Custom))
+            .ok_or_else(|| {
+                serde::de::Error::custom(format!(
+                    "unknown transaction type `{}`, expected one of {}",
+                    name,
+                    TransactionCategory::names().join(", ")
+                ))
+            })
+    }
+}
+
+// What happened to each input transaction, in input order
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub row: usize,
+    pub transaction: Transaction,
+    pub outcome: Outcome,
+    // Name of the rule of the config matching the transaction, if any, or REUSED_TX_FLAG or
+    // CAPPED_HOLD_FLAG
+    pub rule: Option<String>,
+    // Client the dispute flow row was applied to instead of its own, see ClientMismatch
+    pub routed_to: Option<u16>,
+}
+
+// Result of Engine::apply. The client is None when the engine doesn't know it.
+#[derive(Debug, Clone)]
+pub enum ApplyOutcome {
+    // Applied, held by a rule or deferred, as told by the event
+    Applied {
+        event: Event,
+        client: Option<Client>,
+    },
+    Ignored {
+        reason: &'static str,
+        client: Option<Client>,
+    },
+    // An invalid row (e.g. a negative amount or a missing one) the engine was left untouched
+    // by
+    Rejected {
+        reason: String,
+        client: Option<Client>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Applied,
+    // Funds moved to held by a rule, until the transaction is reviewed
+    Held,
+    // Dispute waiting for the available funds of the client, see InsufficientFunds::Defer
+    Deferred,
+    Ignored(&'static str),
+}
+
+// A dispute goes from opened (optionally through under_review) to resolved or charged_back. A
+// deferred one holds nothing yet : it is opened by a later deposit, or resolved.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Dispute {
+    pub tx: u32,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub amount: f64,
+    pub state: DisputeState,
+    pub reason: Option<String>,
+    pub opened_at: Option<u64>,
+    pub closed_at: Option<u64>,
+    // Dispute of a withdrawal, credited back to the client while it is ongoing. Missing from
+    // the states saved before withdrawals could be disputed.
+    #[serde(default)]
+    pub provisional_credit: bool,
+    // Wallet of the disputed transaction, the main one when empty
+    #[serde(default)]
+    pub wallet: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    Deferred,
+    Opened,
+    UnderReview,
+    Resolved,
+    ChargedBack,
+}
+
+impl DisputeState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisputeState::Deferred => "deferred",
+            DisputeState::Opened => "opened",
+            DisputeState::UnderReview => "under_review",
+            DisputeState::Resolved => "resolved",
+            DisputeState::ChargedBack => "charged_back",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+    // Set by an admin, unlike locked which follows a chargeback
+    pub frozen: bool,
+    // Escrowed funds by bucket name, part of the held funds
+    pub escrow: BTreeMap<String, f64>,
+    // Funds of the named wallets, part of the client's funds. The main wallet has the rest.
+    pub wallets: BTreeMap<String, Wallet>,
+}
+
+// What a client did during the run, counted as the transactions are processed. The counts
+// start from zero on each run, they aren't part of the persisted state.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Activity {
+    pub deposits: usize,
+    pub withdrawals: usize,
+    pub chargebacks: usize,
+    // Latest timestamp of its transactions that weren't ignored
+    pub last_activity: Option<u64>,
+}
+
+// Balances of a client differing from an assert_balance row
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceMismatch {
+    pub row: usize,
+    pub client_id: u16,
+    pub expected_available: f64,
+    pub available: f64,
+    pub expected_total: Option<f64>,
+    pub total: f64,
+}
+
+// History entries evicted by the retention policy, see Engine::evict_history
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Eviction {
+    pub expired: usize,
+    pub charged_back: usize,
+    // Entries left in the history
+    pub kept: usize,
+}
+
+// What Engine::erase_client removed, and the funds folded into the anonymized account
+#[derive(Debug, Clone, PartialEq)]
+pub struct Erasure {
+    pub history: usize,
+    pub disputes: usize,
+    pub folded: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wallet {
+    pub available: f64,
+    pub held: f64,
+}
+
+impl Client {
+    // None for the main wallet
+    pub fn wallet(&self, wallet: Option<&str>) -> Wallet {
+        match wallet_name(wallet) {
+            Some(name) => self.wallets.get(name).cloned().unwrap_or_default(),
+            None => Wallet {
+                available: self.available - self.wallets.values().map(|w| w.available).sum::<f64>(),
+                held: self.held - self.wallets.values().map(|w| w.held).sum::<f64>(),
+            },
+        }
+    }
+
+    // Main wallet first, then the named ones by name
+    pub fn wallet_balances(&self) -> Vec<(&str, Wallet)> {
+        let mut balances = vec![(MAIN_WALLET, self.wallet(None))];
+        for (name, wallet) in &self.wallets {
+            balances.push((name.as_str(), wallet.clone()));
+        }
+        balances
+    }
+
+    // Keeps a named wallet along with a change of the client's funds, nothing to do for the
+    // main wallet
+    fn move_wallet(&mut self, wallet: Option<&str>, available: f64, held: f64) {
+        if let Some(name) = wallet_name(wallet) {
+            let wallet = self.wallets.entry(name.to_string()).or_default();
+            wallet.available += available;
+            wallet.held += held;
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client {
+            available: 0.0,
+            held: 0.0,
+            total: 0.0,
+            locked: false,
+            frozen: false,
+            escrow: BTreeMap::new(),
+            wallets: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn get_transactions_from_file(file_path: &str) -> Result<Vec<Transaction>, ParseDiagnostic> {
+    read_transactions(File::open(file_path)?)
+}
+
+pub fn read_transactions<R: Read>(reader: R) -> Result<Vec<Transaction>, ParseDiagnostic> {
+    read_transactions_with(reader, &InputSettings::default())
+}
+
+// Reads a csv file with another delimiter or other column names, see InputSettings
+pub fn read_transactions_with<R: Read>(
+    reader: R,
+    input: &InputSettings,
+) -> Result<Vec<Transaction>, ParseDiagnostic> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(input.delimiter())
+        .from_reader(reader);
+
+    let headers = input.rename_headers(rdr.headers()?);
+    let mut record = csv::StringRecord::new();
+    let mut transactions = Vec::new();
+    while rdr.read_record(&mut record)? {
+        transactions.push(
+            record
+                .deserialize(Some(&headers))
+                .map_err(|e| ParseDiagnostic::new(&e, &headers, &record))?,
+        );
+    }
+    Ok(transactions)
+}
+
+// For partners delivering unsorted files, as the rules depend on the order. The sort is
+// stable : rows sharing a timestamp keep their order.
+pub fn sort_by_timestamp(transactions: &mut [Transaction]) -> Result<(), String> {
+    if let Some(row) = transactions.iter().position(|t| t.timestamp.is_none()) {
+        return Err(format!(
+            "Row {} has no timestamp, the input can't be sorted by timestamp",
+            row + 1
+        ));
+    }
+    transactions.sort_by_key(|t| t.timestamp);
+    Ok(())
+}
+
+pub fn process_transactions(
+    transactions: &[Transaction],
+    clients: &mut HashMap<u16, Client>,
+) -> Result<Vec<Event>, String> {
+    let mut engine = Engine {
+        clients: std::mem::take(clients),
+        ..Default::default()
+    };
+    let events = transactions
+        .iter()
+        .map(|t| engine.process(t))
+        .collect::<Result<Vec<Event>, String>>();
+    *clients = engine.clients;
+    events
+}
+
+// Processing state kept between transactions, so they can also be fed one at a time
+#[derive(Default)]
+pub struct Engine {
+    clients: HashMap<u16, Client>,
+    transactions_history: HashMap<u32, Transaction>,
+    // Client and tx id of the entries dropped by evict_history, for the stores writing the
+    // history row by row
+    evicted_history: Vec<(u16, u32)>,
+    // Clients removed by erase_client, for the stores saving only the changed clients
+    erased_clients: Vec<u16>,
+    // Opened or under review, by disputed tx id
+    ongoing_disputes: HashMap<u32, Dispute>,
+    // Resolved or charged back, in closing order
+    closed_disputes: Vec<Dispute>,
+    processed: usize,
+    // Most recent last, never longer than rollback_capacity
+    rollback_log: VecDeque<Inverse>,
+    rollback_capacity: usize,
+    config: Config,
+    // Timestamps of each client's deposits and withdrawals within the longest velocity window
+    recent: HashMap<u16, VecDeque<u64>>,
+    // Deposits and withdrawals held by a rule, by tx id. They aren't in the history until released.
+    held_transactions: HashMap<u32, Transaction>,
+    // Reads the history persisted by previous runs, the first time a dispute needs it
+    history_loader: Option<HistoryLoader>,
+    // Account of the members of joint accounts, the clients are keyed by account
+    accounts: HashMap<u16, u16>,
+    // Tx ids of the history, to skip the history lookups of unknown transactions
+    tx_filter: TxFilter,
+    // Deposits and withdrawals recently recorded, when deduplication is configured
+    dedup_window: DedupWindow,
+    // Recent amounts of each client, when anomaly detection is configured
+    anomaly_detector: AnomalyDetector,
+    // By client, as the clients are keyed
+    activity: HashMap<u16, Activity>,
+    // In row order
+    balance_mismatches: Vec<BalanceMismatch>,
+    hooks: Option<Box<dyn Hooks>>,
+    // By custom category name
+    handlers: HashMap<&'static str, Box<dyn TransactionHandler>>,
+    #[cfg(feature = "scripting")]
+    script: Option<scripting::Script>,
+    #[cfg(feature = "plugins")]
+    plugin: Option<plugins::Plugin>,
+}
+
+pub type HistoryLoader = Box<dyn FnOnce() -> Result<Vec<Transaction>, String> + Send>;
+
+// Everything a transaction can modify, as it was before the transaction was processed
+struct Inverse {
+    transaction: Transaction,
+    // Client modified by the transaction, not the one of the row when it was routed
+    client_id: u16,
+    client: Option<Client>,
+    history: Option<Transaction>,
+    dispute: Option<Dispute>,
+    closed_disputes: usize,
+    balance_mismatches: usize,
+    // House account a close may sweep to, and what it was before
+    house: Option<(u16, Option<Client>)>,
+    recent: Option<VecDeque<u64>>,
+    held: Option<Transaction>,
+    // Whether the transaction was added to the dedup window
+    deduplicated: bool,
+    // Whether the amount was added to the anomaly detector
+    observed: bool,
+    activity: Option<Activity>,
+    // Deferred disputes of the client, which a deposit can open
+    deferred: Vec<Dispute>,
+}
+
+impl Engine {
+    pub fn clients(&self) -> &HashMap<u16, Client> {
+        &self.clients
+    }
+
+    // Failed balance assertions, in row order
+    pub fn balance_mismatches(&self) -> &[BalanceMismatch] {
+        &self.balance_mismatches
+    }
+
+    pub fn activity(&self) -> &HashMap<u16, Activity> {
+        &self.activity
+    }
+
+    // Opened, under review or deferred, by client
+    pub fn open_disputes(&self) -> HashMap<u16, usize> {
+        let mut open = HashMap::new();
+        for dispute in self.ongoing_disputes.values() {
+            *open.entry(dispute.client_id).or_default() += 1;
+        }
+        open
+    }
+
+    // Deposits and withdrawals flagged by the anomaly detector during this run, in row order
+    pub fn anomalies(&self) -> &[Anomaly] {
+        self.anomaly_detector.anomalies()
+    }
+
+    // Closed disputes first, in closing order, then the ongoing ones
+    pub fn disputes(&self) -> impl Iterator<Item = &Dispute> {
+        self.closed_disputes
+            .iter()
+            .chain(self.ongoing_disputes.values())
+    }
+
+    // Transactions held by a rule, waiting for a review
+    pub fn held_transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.held_transactions.values()
+    }
+
+    // The history of previous runs is only read when needed : until then, the history only
+    // holds the transactions of this run
+    pub fn set_history_loader(&mut self, loader: HistoryLoader) {
+        self.history_loader = Some(loader);
+    }
+
+    // Approximate bytes allocated by the history, disputes, held transactions and clients,
+    // from the capacity of their maps. The heap of the strings (reasons, wallets, escrow
+    // buckets) isn't counted, it would take a pass over every entry.
+    pub fn approximate_memory(&self) -> usize {
+        // A slot of a HashMap also has a control byte
+        fn map<K, V>(map: &HashMap<K, V>) -> usize {
+            map.capacity() * (std::mem::size_of::<(K, V)>() + 1)
+        }
+        map(&self.clients)
+            + map(&self.transactions_history)
+            + map(&self.ongoing_disputes)
+            + self.closed_disputes.capacity() * std::mem::size_of::<Dispute>()
+            + map(&self.held_transactions)
+            + self.rollback_log.capacity() * std::mem::size_of::<Inverse>()
+    }
+
+    // `name` becomes a valid `type` of the input, see handlers::register_category. Without a
+    // handler on the engine, its transactions are ignored.
+    pub fn register_handler(
+        &mut self,
+        name: &str,
+        handler: Box<dyn TransactionHandler>,
+    ) -> Result<(), String> {
+        let name = handlers::register_category(name)?;
+        self.handlers.insert(name, handler);
+        Ok(())
+    }
+
+    pub fn set_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        self.hooks = Some(hooks);
+    }
+
+    pub fn history_loaded(&self) -> bool {
+        self.history_loader.is_none()
+    }
+
+    // The loaded rows come in recording order, so a later row of a reused tx id replaces an
+    // earlier one. The transactions of this run are more recent than all of them.
+    pub fn load_history(&mut self) -> Result<(), String> {
+        if let Some(loader) = self.history_loader.take() {
+            let mut loaded = HashMap::new();
+            for t in loader()? {
+                loaded.insert(t.tx, t);
+            }
+            for (tx, t) in loaded {
+                self.transactions_history.entry(tx).or_insert(t);
+                self.remember_tx(tx);
+            }
+        }
+        Ok(())
+    }
+
+    // Drops the history entries that can't be disputed anymore, to keep the history bounded
+    // in a long running deployment : the ones older than the retention of the config and the
+    // charged back ones, matched on the client too since a tx id can be reused by another
+    // client after the chargeback. Entries with an ongoing dispute and undated ones are kept.
+    // The history is loaded first, so that the next save rewrites it without them.
+    pub fn evict_history(&mut self) -> Result<Eviction, String> {
+        let Some(retention) = self.config.history.retention_seconds else {
+            return Ok(Eviction::default());
+        };
+        self.load_history()?;
+        let charged_back: HashSet<(u16, u32)> = self
+            .closed_disputes
+            .iter()
+            .filter(|d| d.state == DisputeState::ChargedBack)
+            .map(|d| (d.client_id, d.tx))
+            .collect();
+        let latest = self
+            .transactions_history
+            .values()
+            .filter_map(|t| t.timestamp)
+            .max();
+        let since = latest.unwrap_or_default().saturating_sub(retention);
+        let mut eviction = Eviction::default();
+        let ongoing_disputes = &self.ongoing_disputes;
+        let evicted = &mut self.evicted_history;
+        self.transactions_history.retain(|tx, t| {
+            let kept = if ongoing_disputes.contains_key(tx) {
+                true
+            } else if charged_back.contains(&(t.client_id, *tx)) {
+                eviction.charged_back += 1;
+                false
+            } else if t.timestamp.is_some_and(|timestamp| timestamp < since) {
+                eviction.expired += 1;
+                false
+            } else {
+                true
+            };
+            if !kept {
+                evicted.push((t.client_id, *tx));
+            }
+            kept
+        });
+        eviction.kept = self.transactions_history.len();
+        Ok(eviction)
+    }
+
+    // Removes a client and every trace of it (history entries, closed disputes, activity)
+    // for a right to erasure request. Its funds are folded into the anonymized account of the
+    // config, so that the clients still sum to the same total. A client with held funds, an
+    // ongoing dispute or a held transaction is refused : they would lose their counterpart.
+    // So is a joint account with other members, its balances being theirs too. The client
+    // leaves the joint account it was a member of. Erasures can't be rolled back, so the
+    // rollback log is cleared.
+    pub fn erase_client(&mut self, client_id: u16) -> Result<Erasure, String> {
+        let Some(into) = self.config.admin.anonymized_account else {
+            return Err(
+                "Erasing a client needs an anonymized_account in the [admin] section of the config"
+                    .to_string(),
+            );
+        };
+        if into == client_id {
+            return Err("The anonymized account can't be erased".to_string());
+        }
+        // A member of a joint account may have no balances of its own
+        let held = self.clients.get(&client_id).map(|client| client.held);
+        if held.is_none() && !self.accounts.contains_key(&client_id) {
+            return Err(format!("Unknown client {}", client_id));
+        }
+        if held.is_some_and(|held| held != 0.0)
+            || self
+                .ongoing_disputes
+                .values()
+                .any(|d| d.client_id == client_id)
+            || self
+                .held_transactions
+                .values()
+                .any(|t| t.client_id == client_id)
+        {
+            return Err(format!(
+                "Client {} has held funds or ongoing disputes, settle them first",
+                client_id
+            ));
+        }
+        if self
+            .accounts
+            .iter()
+            .any(|(member, account)| *account == client_id && *member != client_id)
+        {
+            return Err(format!(
+                "Client {} is the joint account of other members, remove them from it first",
+                client_id
+            ));
+        }
+        self.load_history()?;
+        let client = self.clients.remove(&client_id).unwrap_or_default();
+        if held.is_some() {
+            let anonymized = self.clients.entry(into).or_default();
+            anonymized.available += client.available;
+            anonymized.total += client.total;
+        }
+        let history = self.transactions_history.len();
+        self.transactions_history
+            .retain(|_, t| t.client_id != client_id);
+        let disputes = self.closed_disputes.len();
+        self.closed_disputes.retain(|d| d.client_id != client_id);
+        self.activity.remove(&client_id);
+        self.recent.remove(&client_id);
+        self.accounts.remove(&client_id);
+        self.erased_clients.push(client_id);
+        self.rollback_log.clear();
+        Ok(Erasure {
+            history: history - self.transactions_history.len(),
+            disputes: disputes - self.closed_disputes.len(),
+            folded: client.total,
+        })
+    }
+
+    // Members of a joint account transact on the shared balances of the account. The history
+    // and disputes record the account, the events keep the client of the row.
+    pub fn set_accounts(&mut self, accounts: HashMap<u16, u16>) {
+        self.accounts = accounts;
+    }
+
+    pub fn accounts(&self) -> &HashMap<u16, u16> {
+        &self.accounts
+    }
+
+    // Rules of the config are evaluated on every following deposit and withdrawal
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn set_script(&mut self, script: scripting::Script) {
+        self.script = Some(script);
+    }
+
+    #[cfg(feature = "plugins")]
+    pub fn set_plugin(&mut self, plugin: plugins::Plugin) {
+        self.plugin = Some(plugin);
+    }
+
+    // Releases or reverses a held transaction. Decisions aren't transactions of the input and
+    // can't be rolled back, so the rollback log is cleared.
+    pub fn decide(&mut self, tx: u32, decision: Decision) -> Result<(), &'static str> {
+        let Some(t) = self.held_transactions.remove(&tx) else {
+            return Err("Transaction is not held");
+        };
+        let amount = t.amount.unwrap_or_default();
+        let client = self.clients.entry(t.client_id).or_default();
+        client.held -= amount;
+        match (&t.category, decision) {
+            (TransactionCategory::Deposit, Decision::Approve)
+            | (TransactionCategory::Withdrawal, Decision::Deny) => {
+                client.available += amount;
+                client.move_wallet(t.wallet(), amount, -amount);
+            }
+            _ => {
+                client.total -= amount;
+                client.move_wallet(t.wallet(), 0.0, -amount);
+            }
+        }
+        if decision == Decision::Approve {
+            self.transactions_history.insert(tx, t);
+            self.remember_tx(tx);
+        }
+        self.rollback_log.clear();
+        Ok(())
+    }
+
+    // Keeps what is needed to revert the last `rollback_capacity` transactions
+    pub fn with_rollback_capacity(rollback_capacity: usize) -> Self {
+        Engine {
+            rollback_capacity,
+            ..Default::default()
+        }
+    }
+
+    // One step for the host applications, with the balances of the client the transaction
+    // applied to (its account, or the client of the disputed transaction when routed). A row
+    // missing its amount is rejected rather than panicking as in a batch.
+    pub fn apply(&mut self, row: &Transaction) -> ApplyOutcome {
+        let account = self
+            .accounts
+            .get(&row.client_id)
+            .copied()
+            .unwrap_or(row.client_id);
+        match self.process_row(row, true) {
+            Ok(event) => {
+                let client_id = event.routed_to.unwrap_or(account);
+                let client = self.clients.get(&client_id).cloned();
+                match event.outcome {
+                    Outcome::Ignored(reason) => ApplyOutcome::Ignored { reason, client },
+                    _ => ApplyOutcome::Applied { event, client },
+                }
+            }
+            Err(reason) => ApplyOutcome::Rejected {
+                reason,
+                client: self.clients.get(&account).cloned(),
+            },
+        }
+    }
+
+    // A transaction returning an error leaves the engine untouched
+    pub fn process(&mut self, row: &Transaction) -> Result<Event, String> {
+        self.process_row(row, false)
+    }
+
+    // With `checked_amounts`, a row missing a needed amount is an error instead of a panic
+    fn process_row(&mut self, row: &Transaction, checked_amounts: bool) -> Result<Event, String> {
+        let enriched;
+        let mut veto = None;
+        let row = match self.hooks.as_mut() {
+            Some(hooks) => {
+                let mut t = row.clone();
+                let account = self.accounts.get(&t.client_id).copied();
+                let client = self.clients.get(&account.unwrap_or(t.client_id));
+                veto = hooks.on_before_apply(&mut t, client).err();
+                enriched = t;
+                &enriched
+            }
+            None => row,
+        };
+        let account;
+        let t = match self.accounts.get(&row.client_id) {
+            Some(&client_id) if client_id != row.client_id => {
+                account = Transaction {
+                    client_id,
+                    ..row.clone()
+                };
+                &account
+            }
+            _ => row,
+        };
+        if checked_amounts && t.amount.is_none() && t.category.needs_amount() {
+            return Err(format!(
+                "A {} transaction needs an amount",
+                t.category.as_str()
+            ));
+        }
+        let clawback = t.category == TransactionCategory::Chargeback
+            && self.config.bonus.clawback_window_seconds.is_some();
+        let reuse_checked = matches!(
+            t.category,
+            TransactionCategory::Deposit | TransactionCategory::Withdrawal
+        ) && self.config.history.reused_tx != ReusedTx::LastWins;
+        // A handler may look up the transactions of previous runs
+        let handled = match t.category {
+            TransactionCategory::Custom(name) => self.handlers.contains_key(name),
+            _ => false,
+        };
+        if t.category == TransactionCategory::Dispute || clawback || reuse_checked || handled {
+            self.load_history()?;
+        }
+        let owner = self.other_owner(t);
+        let client_id = match (owner, self.config.disputes.client_mismatch) {
+            (Some(owner), ClientMismatch::Route) => owner,
+            _ => t.client_id,
+        };
+        let mut inverse = Inverse {
+            transaction: t.to_owned(),
+            client_id,
+            client: self.clients.get(&client_id).cloned(),
+            history: self.transactions_history.get(&t.tx).cloned(),
+            dispute: self.ongoing_disputes.get(&t.tx).cloned(),
+            closed_disputes: self.closed_disputes.len(),
+            balance_mismatches: self.balance_mismatches.len(),
+            house: match t.category {
+                TransactionCategory::Close => self
+                    .config
+                    .admin
+                    .house_account
+                    .map(|house| (house, self.clients.get(&house).cloned())),
+                _ => None,
+            },
+            recent: self.recent.get(&t.client_id).cloned(),
+            held: self.held_transactions.get(&t.tx).cloned(),
+            deduplicated: false,
+            observed: false,
+            activity: self.activity.get(&client_id).cloned(),
+            deferred: match t.category {
+                TransactionCategory::Deposit => self
+                    .ongoing_disputes
+                    .values()
+                    .filter(|d| d.client_id == client_id && d.state == DisputeState::Deferred)
+                    .cloned()
+                    .collect(),
+                _ => Vec::new(),
+            },
+        };
+        match self.apply_row(t, client_id, owner, veto) {
+            Ok(mut event) => {
+                event.transaction = row.to_owned();
+                let recorded = matches!(
+                    t.category,
+                    TransactionCategory::Deposit | TransactionCategory::Withdrawal
+                ) && matches!(event.outcome, Outcome::Applied | Outcome::Held);
+                if let (true, Some(settings)) = (recorded, &self.config.anomalies) {
+                    let anomaly = self
+                        .anomaly_detector
+                        .observe(event.row, t, client_id, settings);
+                    if anomaly.is_some() && event.rule.is_none() {
+                        event.rule = Some(ANOMALY_FLAG.to_string());
+                    }
+                    inverse.observed = true;
+                }
+                if !matches!(event.outcome, Outcome::Ignored(_)) {
+                    let activity = self.activity.entry(client_id).or_default();
+                    match t.category {
+                        TransactionCategory::Deposit => activity.deposits += 1,
+                        TransactionCategory::Withdrawal => activity.withdrawals += 1,
+                        TransactionCategory::Chargeback => activity.chargebacks += 1,
+                        _ => {}
+                    }
+                    activity.last_activity = activity.last_activity.max(t.timestamp);
+                }
+                if let Some(hooks) = self.hooks.as_mut() {
+                    let client_id = event.routed_to.unwrap_or(t.client_id);
+                    hooks.on_after_apply(&event, self.clients.get(&client_id));
+                }
+                if recorded && self.config.dedup.enabled() {
+                    self.dedup_window.push(t.tx, t.timestamp);
+                    inverse.deduplicated = true;
+                }
+                // Only a newly recorded tx id : disputes and replaced entries add nothing
+                if inverse.history.is_none() && self.transactions_history.contains_key(&t.tx) {
+                    self.remember_tx(t.tx);
+                }
+                if self.rollback_capacity > 0 {
+                    if self.rollback_log.len() == self.rollback_capacity {
+                        self.rollback_log.pop_front();
+                    }
+                    self.rollback_log.push_back(inverse);
+                }
+                Ok(event)
+            }
+            Err(e) => {
+                self.revert(inverse);
+                Err(e)
+            }
+        }
+    }
+
+    // Reverts up to the last n processed transactions, and returns them, most recent first.
+    // Stops early when the rollback log is exhausted.
+    pub fn rollback(&mut self, n: usize) -> Vec<Transaction> {
+        let mut reverted = Vec::new();
+        while reverted.len() < n {
+            let Some(inverse) = self.rollback_log.pop_back() else {
+                break;
+            };
+            reverted.push(inverse.transaction.clone());
+            self.revert(inverse);
+        }
+        reverted
+    }
+
+    fn revert(&mut self, inverse: Inverse) {
+        let t = inverse.transaction;
+        match inverse.house {
+            Some((house, Some(client))) => self.clients.insert(house, client),
+            Some((house, None)) => self.clients.remove(&house),
+            None => None,
+        };
+        match inverse.client {
+            Some(client) => self.clients.insert(inverse.client_id, client),
+            None => self.clients.remove(&inverse.client_id),
+        };
+        match inverse.history {
+            Some(history) => self.transactions_history.insert(t.tx, history),
+            None => self.transactions_history.remove(&t.tx),
+        };
+        match inverse.dispute {
+            Some(dispute) => self.ongoing_disputes.insert(t.tx, dispute),
+            None => self.ongoing_disputes.remove(&t.tx),
+        };
+        self.closed_disputes.truncate(inverse.closed_disputes);
+        self.balance_mismatches.truncate(inverse.balance_mismatches);
+        match inverse.recent {
+            Some(recent) => self.recent.insert(t.client_id, recent),
+            None => self.recent.remove(&t.client_id),
+        };
+        match inverse.held {
+            Some(held) => self.held_transactions.insert(t.tx, held),
+            None => self.held_transactions.remove(&t.tx),
+        };
+        if inverse.deduplicated {
+            self.dedup_window.pop();
+        }
+        match inverse.activity {
+            Some(activity) => self.activity.insert(inverse.client_id, activity),
+            None => self.activity.remove(&inverse.client_id),
+        };
+        if inverse.observed {
+            self.anomaly_detector
+                .pop(self.processed, &t, inverse.client_id);
+        }
+        for dispute in inverse.deferred {
+            self.ongoing_disputes.insert(dispute.tx, dispute);
+        }
+        self.processed -= 1;
+    }
+
+    fn remember_tx(&mut self, tx: u32) {
+        let recorded = self.transactions_history.keys().copied();
+        self.tx_filter.insert(tx, recorded);
+    }
+
+    // Client of the transaction referenced by a dispute flow row, when it isn't the client
+    // of the row. Resolves, reviews and chargebacks are checked against the dispute.
+    fn other_owner(&self, t: &Transaction) -> Option<u16> {
+        let owner = match t.category {
+            TransactionCategory::Deposit
+            | TransactionCategory::Withdrawal
+            | TransactionCategory::Freeze
+            | TransactionCategory::Close
+            | TransactionCategory::Adjustment
+            | TransactionCategory::OpeningBalance
+            | TransactionCategory::AssertBalance
+            | TransactionCategory::Place
+            | TransactionCategory::Release
+            | TransactionCategory::Capture
+            | TransactionCategory::Transfer
+            | TransactionCategory::Bonus
+            | TransactionCategory::Custom(_)
+            | TransactionCategory::Unfreeze => None,
+            TransactionCategory::Dispute if !self.tx_filter.may_contain(t.tx) => None,
+            TransactionCategory::Dispute => {
+                self.transactions_history.get(&t.tx).map(|h| h.client_id)
+            }
+            TransactionCategory::Review
+            | TransactionCategory::Resolve
+            | TransactionCategory::Chargeback => {
+                self.ongoing_disputes.get(&t.tx).map(|d| d.client_id)
+            }
+        };
+        owner.filter(|owner| *owner != t.client_id)
+    }
+
+    // `client_id` is the client the transaction applies to, `owner` the client of the
+    // transaction it references when it isn't the client of the row, `veto` the reason of
+    // the hooks ignoring it
+    fn apply_row(
+        &mut self,
+        t: &Transaction,
+        client_id: u16,
+        owner: Option<u16>,
+        veto: Option<&'static str>,
+    ) -> Result<Event, String> {
+        self.processed += 1;
+        let csv_line = self.processed;
+        let duplicate = matches!(
+            t.category,
+            TransactionCategory::Deposit | TransactionCategory::Withdrawal
+        ) && self.config.dedup.enabled()
+            && self
+                .dedup_window
+                .contains(t.tx, t.timestamp, &self.config.dedup);
+        let reused_tx = self.config.history.reused_tx;
+        let reused = matches!(
+            t.category,
+            TransactionCategory::Deposit | TransactionCategory::Withdrawal
+        ) && (self.transactions_history.contains_key(&t.tx)
+            || self.held_transactions.contains_key(&t.tx));
+        let rejected_reuse =
+            reused && matches!(reused_tx, ReusedTx::Reject | ReusedTx::RejectAndFlag);
+        let keep_first = reused && reused_tx == ReusedTx::FirstWins;
+        // Get client of the transaction, or initialize if it doesn't exists
+        let client = self.clients.entry(client_id).or_default();
+        let transactions_history = &mut self.transactions_history;
+        let ongoing_disputes = &mut self.ongoing_disputes;
+        let closed_disputes = &mut self.closed_disputes;
+        let held_transactions = &mut self.held_transactions;
+
+        let rule = match t.category {
+            TransactionCategory::Deposit | TransactionCategory::Withdrawal
+                if !client.locked && !duplicate && !rejected_reuse && veto.is_none() =>
+            {
+                let window = rules::velocity_window(&self.config);
+                if let (true, Some(now)) = (window > 0, t.timestamp) {
+                    let recent = self.recent.entry(t.client_id).or_default();
+                    recent.push_back(now);
+                    while recent
+                        .front()
+                        .is_some_and(|&at| at < now.saturating_sub(window))
+                    {
+                        recent.pop_front();
+                    }
+                }
+                let none = VecDeque::new();
+                let recent = self.recent.get(&t.client_id).unwrap_or(&none);
+                let rule = rules::evaluate(&self.config, t, recent).map(Cow::Borrowed);
+                #[cfg(feature = "scripting")]
+                let rule = match (rule, &self.script) {
+                    (None, Some(script)) => script.decide(t, client).map(Cow::Owned),
+                    (rule, _) => rule,
+                };
+                #[cfg(feature = "plugins")]
+                let rule = match (rule, &mut self.plugin) {
+                    (None, Some(plugin)) => plugin.decide(t, client).map(Cow::Owned),
+                    (rule, _) => rule,
+                };
+                rule
+            }
+            _ => None,
+        };
+        let hold = rule
+            .as_ref()
+            .is_some_and(|rule| rule.action == Action::Hold);
+        // Set when the event names a policy of the config rather than a rule
+        let mut flag = None;
+        // House account and residual balance of a closed client
+        let mut sweep = None;
+
+        let outcome = if let Some(reason) = veto {
+            Outcome::Ignored(reason)
+        } else if client.locked && t.category != TransactionCategory::AssertBalance {
+            Outcome::Ignored("Client account is locked")
+        } else if duplicate {
+            Outcome::Ignored(DUPLICATE)
+        } else if rejected_reuse {
+            Outcome::Ignored(REUSED_TX)
+        } else if owner.is_some() && client_id == t.client_id {
+            Outcome::Ignored("Transaction belongs to another client")
+        } else if rule
+            .as_ref()
+            .is_some_and(|rule| rule.action == Action::Reject)
+        {
+            Outcome::Ignored("Rejected by a rule")
+        } else {
+            match t.category {
+                TransactionCategory::Deposit => {
+                    let amount = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for a deposit transaction", csv_line));
+                    deposit(amount, client)?;
+                    if hold {
+                        client.available -= amount;
+                        client.held += amount;
+                        client.move_wallet(t.wallet(), 0.0, amount);
+                        held_transactions.insert(t.tx, t.to_owned());
+                        Outcome::Held
+                    } else {
+                        client.move_wallet(t.wallet(), amount, 0.0);
+                        if !keep_first {
+                            transactions_history.insert(t.tx, t.to_owned());
+                        }
+                        open_deferred_disputes(t, client_id, ongoing_disputes, client);
+                        Outcome::Applied
+                    }
+                }
+                TransactionCategory::Withdrawal => {
+                    let amount = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for a withdraw transaction", csv_line));
+                    if client.frozen {
+                        Outcome::Ignored("Client account is frozen")
+                    } else if !withdraw(amount, t.wallet(), client)? {
+                        Outcome::Ignored("Insufficient available funds")
+                    } else if hold {
+                        // The funds stay in the total until the withdrawal is released
+                        client.total += amount;
+                        client.held += amount;
+                        client.move_wallet(t.wallet(), -amount, amount);
+                        held_transactions.insert(t.tx, t.to_owned());
+                        Outcome::Held
+                    } else {
+                        client.move_wallet(t.wallet(), -amount, 0.0);
+                        if !keep_first {
+                            transactions_history.insert(t.tx, t.to_owned());
+                        }
+                        Outcome::Applied
+                    }
+                }
+                TransactionCategory::Dispute => {
+                    let settings = &self.config.disputes;
+                    let outcome = dispute(
+                        t,
+                        &self.tx_filter,
+                        transactions_history,
+                        ongoing_disputes,
+                        client,
+                        settings.withdrawals,
+                        settings.insufficient_funds,
+                    );
+                    let held = ongoing_disputes.get(&t.tx).map(|d| d.amount);
+                    let disputed = transactions_history.get(&t.tx).and_then(|h| h.amount);
+                    if outcome == Outcome::Applied && held < disputed {
+                        flag = Some(CAPPED_HOLD_FLAG);
+                    }
+                    outcome
+                }
+                TransactionCategory::Review => review(t, ongoing_disputes),
+                TransactionCategory::Adjustment => {
+                    let amount = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for an adjustment transaction", csv_line));
+                    if !self.config.admin.adjustments {
+                        Outcome::Ignored("Adjustments are not allowed")
+                    } else if t.reason.as_deref().is_none_or(str::is_empty) {
+                        Outcome::Ignored("An adjustment needs a reason")
+                    } else {
+                        adjust(amount, client)?;
+                        client.move_wallet(t.wallet(), amount, 0.0);
+                        // Recorded like deposits and withdrawals, so reruns and backfills see it
+                        transactions_history.insert(t.tx, t.to_owned());
+                        Outcome::Applied
+                    }
+                }
+                TransactionCategory::OpeningBalance => {
+                    let amount = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for an opening balance transaction", csv_line));
+                    if !self.config.admin.opening_balances {
+                        Outcome::Ignored("Opening balances are not allowed")
+                    } else if client.held != 0.0 || !client.wallets.is_empty() {
+                        Outcome::Ignored("The client already has held or wallet funds")
+                    } else {
+                        open_balance(amount, client)?;
+                        // Recorded like adjustments, so the balances can be rebuilt
+                        transactions_history.insert(t.tx, t.to_owned());
+                        Outcome::Applied
+                    }
+                }
+                TransactionCategory::AssertBalance => {
+                    let expected_available = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for a balance assertion", csv_line));
+                    let expected_total = match t.reason.as_deref().filter(|r| !r.is_empty()) {
+                        Some(total) => Some(total.parse::<f64>().map_err(|_| {
+                            "The expected total of a balance assertion isn't a number"
+                        })?),
+                        None => None,
+                    };
+                    let matches = |expected: f64, actual: f64| {
+                        (expected - actual).abs() < ASSERTION_TOLERANCE
+                    };
+                    if matches(expected_available, client.available)
+                        && expected_total.is_none_or(|total| matches(total, client.total))
+                    {
+                        Outcome::Applied
+                    } else {
+                        self.balance_mismatches.push(BalanceMismatch {
+                            row: csv_line,
+                            client_id,
+                            expected_available,
+                            available: client.available,
+                            expected_total,
+                            total: client.total,
+                        });
+                        Outcome::Ignored(BALANCE_MISMATCH)
+                    }
+                }
+                TransactionCategory::Capture if client.frozen => {
+                    Outcome::Ignored("Client account is frozen")
+                }
+                TransactionCategory::Place
+                | TransactionCategory::Release
+                | TransactionCategory::Capture => {
+                    let outcome = escrow(t, client)?;
+                    if outcome == Outcome::Applied {
+                        // Recorded like deposits and withdrawals, so reruns and backfills see it
+                        transactions_history.insert(t.tx, t.to_owned());
+                    }
+                    outcome
+                }
+                TransactionCategory::Transfer => {
+                    let amount = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for a transfer transaction", csv_line));
+                    let to = wallet_name(t.reason.as_deref());
+                    if amount < f64::MIN_POSITIVE {
+                        return Err("Cannot transfer a negative amount".to_string());
+                    }
+                    if to == t.wallet() {
+                        Outcome::Ignored("Cannot transfer to the same wallet")
+                    } else if amount > client.wallet(t.wallet()).available {
+                        Outcome::Ignored("Insufficient available funds")
+                    } else {
+                        client.move_wallet(t.wallet(), -amount, 0.0);
+                        client.move_wallet(to, amount, 0.0);
+                        transactions_history.insert(t.tx, t.to_owned());
+                        Outcome::Applied
+                    }
+                }
+                TransactionCategory::Freeze if client.frozen => {
+                    Outcome::Ignored("Client account is already frozen")
+                }
+                TransactionCategory::Unfreeze if !client.frozen => {
+                    Outcome::Ignored("Client account is not frozen")
+                }
+                TransactionCategory::Freeze | TransactionCategory::Unfreeze => {
+                    client.frozen = t.category == TransactionCategory::Freeze;
+                    Outcome::Applied
+                }
+                TransactionCategory::Close if client.held != 0.0 => {
+                    Outcome::Ignored("The client still has held funds")
+                }
+                TransactionCategory::Close => {
+                    let residual = client.total != 0.0;
+                    let house = self.config.admin.house_account;
+                    let house = house.filter(|house| *house != client_id && residual);
+                    match house {
+                        Some(_) if transactions_history.contains_key(&t.tx) => {
+                            Outcome::Ignored("The tx of the sweep is already used")
+                        }
+                        Some(house) => {
+                            transactions_history.insert(
+                                t.tx,
+                                Transaction {
+                                    amount: Some(client.total),
+                                    reason: Some(house.to_string()),
+                                    ..t.to_owned()
+                                },
+                            );
+                            sweep = Some((house, client.total));
+                            client.available = 0.0;
+                            client.total = 0.0;
+                            client.wallets.clear();
+                            client.locked = true;
+                            Outcome::Applied
+                        }
+                        None => {
+                            client.locked = true;
+                            Outcome::Applied
+                        }
+                    }
+                }
+                TransactionCategory::Resolve => {
+                    resolve(t, ongoing_disputes, closed_disputes, client)
+                }
+                TransactionCategory::Chargeback => {
+                    let deposit = ongoing_disputes
+                        .get(&t.tx)
+                        .is_some_and(|dispute| !dispute.provisional_credit);
+                    let outcome = charge_back(t, ongoing_disputes, closed_disputes, client);
+                    if let (true, Some(window)) = (
+                        deposit && outcome == Outcome::Applied,
+                        self.config.bonus.clawback_window_seconds,
+                    ) {
+                        claw_back_bonuses(t, client_id, window, transactions_history, client);
+                    }
+                    outcome
+                }
+                TransactionCategory::Bonus => {
+                    let amount = t.amount.unwrap_or_else(|| panic!("Incorrect csv row : {}. You should provide an amount for a bonus transaction", csv_line));
+                    deposit(amount, client)?;
+                    client.move_wallet(Some(BONUS_WALLET), amount, 0.0);
+                    transactions_history.insert(t.tx, t.to_owned());
+                    Outcome::Applied
+                }
+                TransactionCategory::Custom(name) => match self.handlers.get_mut(name) {
+                    Some(handler) => {
+                        let history = History {
+                            transactions: transactions_history,
+                        };
+                        handler.apply(t, client, history)?
+                    }
+                    None => Outcome::Ignored("No handler for this transaction type"),
+                },
+            }
+        };
+        if let Some((house, residual)) = sweep {
+            let house = self.clients.entry(house).or_default();
+            house.available += residual;
+            house.total += residual;
+        }
+        if reused_tx == ReusedTx::RejectAndFlag && outcome == Outcome::Ignored(REUSED_TX) {
+            flag = Some(REUSED_TX_FLAG);
+        }
+        Ok(Event {
+            row: csv_line,
+            transaction: t.to_owned(),
+            rule: match flag {
+                Some(flag) => Some(flag.to_string()),
+                None => rule.map(|rule| rule.name.clone()),
+            },
+            outcome,
+            routed_to: (client_id != t.client_id).then_some(client_id),
+        })
+    }
+}
+
+fn deposit(amount: f64, client: &mut Client) -> Result<(), &str> {
+    if amount < f64::MIN_POSITIVE {
+        return Err("Cannot deposit a negative amount");
+    }
+    client.available += amount;
+    client.total += amount;
+    if client.total > f64::MAX {
+        return Err("You are getting way too rich");
+    }
+    Ok(())
+}
+
+// Can leave the available funds negative : it corrects them, whatever they are
+fn adjust(amount: f64, client: &mut Client) -> Result<(), &str> {
+    if amount == 0.0 || !amount.is_finite() {
+        return Err("An adjustment needs a non zero, finite amount");
+    }
+    client.available += amount;
+    client.total += amount;
+    if client.total > f64::MAX {
+        return Err("You are getting way too rich");
+    }
+    Ok(())
+}
+
+fn open_balance(amount: f64, client: &mut Client) -> Result<(), &str> {
+    if !amount.is_finite() {
+        return Err("An opening balance needs a finite amount");
+    }
+    client.available = amount;
+    client.total = amount;
+    Ok(())
+}
+
+// Takes back the bonuses granted to the client within the window before the chargeback, as
+// far as the bonus wallet still has them. Without timestamps, nothing is clawed back.
+fn claw_back_bonuses(
+    t: &Transaction,
+    client_id: u16,
+    window: u64,
+    transactions_history: &HashMap<u32, Transaction>,
+    client: &mut Client,
+) {
+    let Some(now) = t.timestamp else {
+        return;
+    };
+    let granted: f64 = transactions_history
+        .values()
+        .filter(|b| b.category == TransactionCategory::Bonus && b.client_id == client_id)
+        .filter(|b| {
+            b.timestamp
+                .is_some_and(|at| at <= now && now - at <= window)
+        })
+        .filter_map(|b| b.amount)
+        .sum();
+    let clawed_back = granted.min(client.wallet(Some(BONUS_WALLET)).available);
+    if clawed_back > 0.0 {
+        client.available -= clawed_back;
+        client.total -= clawed_back;
+        client.move_wallet(Some(BONUS_WALLET), -clawed_back, 0.0);
+    }
+}
+
+fn escrow<'a>(t: &Transaction, client: &mut Client) -> Result<Outcome, &'a str> {
+    let Some(bucket) = t.reason.as_deref().filter(|bucket| !bucket.is_empty()) else {
+        return Ok(Outcome::Ignored("An escrow operation needs a bucket"));
+    };
+    if t.amount.is_some_and(|amount| amount < f64::MIN_POSITIVE) {
+        return Err("Cannot escrow a negative amount");
+    }
+    if t.category == TransactionCategory::Place {
+        let amount = t
+            .amount
+            .ok_or("Cannot place funds in escrow without an amount")?;
+        if amount > client.available {
+            return Ok(Outcome::Ignored("Insufficient available funds"));
+        }
+        client.available -= amount;
+        client.held += amount;
+        *client.escrow.entry(bucket.to_string()).or_default() += amount;
+        return Ok(Outcome::Applied);
+    }
+
+    let Some(escrowed) = client.escrow.get_mut(bucket) else {
+        return Ok(Outcome::Ignored("Unknown escrow bucket"));
+    };
+    let amount = t.amount.unwrap_or(*escrowed);
+    if amount > *escrowed {
+        return Ok(Outcome::Ignored("Insufficient escrowed funds"));
+    }
+    *escrowed -= amount;
+    if *escrowed <= 0.0 {
+        client.escrow.remove(bucket);
+    }
+    client.held -= amount;
+    match t.category {
+        TransactionCategory::Release => client.available += amount,
+        _ => client.total -= amount,
+    }
+    Ok(Outcome::Applied)
+}
+
+fn withdraw<'a>(amount: f64, wallet: Option<&str>, client: &mut Client) -> Result<bool, &'a str> {
+    if amount < f64::MIN_POSITIVE {
+        return Err("Cannot withdraw a negative amount");
+    }
+    if amount < client.wallet(wallet).available {
+        client.available -= amount;
+        client.total -= amount;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn dispute(
+    t: &Transaction,
+    tx_filter: &TxFilter,
+    transactions_history: &HashMap<u32, Transaction>,
+    ongoing_disputes: &mut HashMap<u32, Dispute>,
+    client: &mut Client,
+    withdrawals: WithdrawalDisputes,
+    insufficient_funds: InsufficientFunds,
+) -> Outcome {
+    let transaction_disputed_id = t.tx;
+    // Can't dispute twice the same transaction
+    if ongoing_disputes.contains_key(&transaction_disputed_id) {
+        return Outcome::Ignored("Transaction is already under dispute");
+    }
+    // Can't dispute a transaction that doesn't exists
+    if !tx_filter.may_contain(transaction_disputed_id) {
+        return Outcome::Ignored("Unknown transaction");
+    }
+    let Some(disputed) = transactions_history.get(&transaction_disputed_id) else {
+        return Outcome::Ignored("Unknown transaction");
+    };
+    let provisional_credit = match (disputed.category, withdrawals) {
+        (TransactionCategory::Deposit, _) => false,
+        (TransactionCategory::Withdrawal, WithdrawalDisputes::ProvisionalCredit) => true,
+        _ => return Outcome::Ignored("Only deposits can be disputed"),
+    };
+    let amount = disputed.amount.unwrap_or_else(|| {
+        panic!(
+            "The amount of the disputed transaction number {} was not provided",
+            disputed.tx
+        )
+    });
+    let (amount, state) = match insufficient_funds {
+        _ if provisional_credit || client.available >= amount => (amount, DisputeState::Opened),
+        InsufficientFunds::AllowNegative => (amount, DisputeState::Opened),
+        InsufficientFunds::Cap => (client.available.max(0.0), DisputeState::Opened),
+        InsufficientFunds::Defer => (amount, DisputeState::Deferred),
+    };
+    if provisional_credit {
+        // The withdrawn funds come back as held, never as available until the chargeback
+        client.held += amount;
+        client.total += amount;
+        client.move_wallet(disputed.wallet(), 0.0, amount);
+    } else if state == DisputeState::Opened {
+        client.available -= amount;
+        client.held += amount;
+        client.move_wallet(disputed.wallet(), -amount, amount);
+    }
+    ongoing_disputes.insert(
+        disputed.tx,
+        Dispute {
+            tx: disputed.tx,
+            client_id: disputed.client_id,
+            amount,
+            state,
+            reason: t.reason.clone(),
+            opened_at: t.timestamp,
+            closed_at: None,
+            provisional_credit,
+            wallet: disputed.wallet().map(str::to_string),
+        },
+    );
+    match state {
+        DisputeState::Deferred => Outcome::Deferred,
+        _ => Outcome::Applied,
+    }
+}
+
+// Holds the funds of the client's deferred disputes now covered by its available funds, the
+// oldest first. They are opened at the time of the deposit.
+fn open_deferred_disputes(
+    t: &Transaction,
+    client_id: u16,
+    ongoing_disputes: &mut HashMap<u32, Dispute>,
+    client: &mut Client,
+) {
+    let mut deferred: Vec<&mut Dispute> = ongoing_disputes
+        .values_mut()
+        .filter(|d| d.client_id == client_id && d.state == DisputeState::Deferred)
+        .collect();
+    deferred.sort_by_key(|d| (d.opened_at, d.tx));
+    for dispute in deferred {
+        if client.available >= dispute.amount {
+            client.available -= dispute.amount;
+            client.held += dispute.amount;
+            client.move_wallet(dispute.wallet.as_deref(), -dispute.amount, dispute.amount);
+            dispute.state = DisputeState::Opened;
+            dispute.opened_at = t.timestamp.or(dispute.opened_at);
+        }
+    }
+}
+
+// Reason of the reviews and chargebacks of a deferred dispute
+const NOT_HELD_YET: &str = "Disputed funds are not held yet";
+
+fn review(t: &Transaction, ongoing_disputes: &mut HashMap<u32, Dispute>) -> Outcome {
+    let Some(dispute) = ongoing_disputes.get_mut(&t.tx) else {
+        return Outcome::Ignored("Transaction is not under dispute");
+    };
+    match dispute.state {
+        DisputeState::UnderReview => return Outcome::Ignored("Dispute is already under review"),
+        DisputeState::Deferred => return Outcome::Ignored(NOT_HELD_YET),
+        _ => (),
+    }
+    dispute.state = DisputeState::UnderReview;
+    Outcome::Applied
+}
+
+// Moves an ongoing dispute to the closed ones
+fn close_dispute(
+    t: &Transaction,
+    state: DisputeState,
+    ongoing_disputes: &mut HashMap<u32, Dispute>,
+    closed_disputes: &mut Vec<Dispute>,
+) {
+    if let Some(mut dispute) = ongoing_disputes.remove(&t.tx) {
+        dispute.state = state;
+        dispute.closed_at = t.timestamp;
+        closed_disputes.push(dispute);
+    }
+}
+
+// Resolves and chargebacks move the amount recorded with the dispute : the history entry
+// may have been overwritten since by a transaction reusing the tx id
+fn resolve(
+    t: &Transaction,
+    ongoing_disputes: &mut HashMap<u32, Dispute>,
+    closed_disputes: &mut Vec<Dispute>,
+    client: &mut Client,
+) -> Outcome {
+    // Can't resolve a transaction that isn't under dispute
+    let Some(dispute) = ongoing_disputes.get(&t.tx) else {
+        return Outcome::Ignored("Transaction is not under dispute");
+    };
+    // Resolved in the favor of the merchant : a provisional credit is taken back. A deferred
+    // dispute held nothing, its record is left with a zero amount.
+    if dispute.state == DisputeState::Deferred {
+        if let Some(dispute) = ongoing_disputes.get_mut(&t.tx) {
+            dispute.amount = 0.0;
+        }
+    } else {
+        client.held -= dispute.amount;
+        if dispute.provisional_credit {
+            client.total -= dispute.amount;
+            client.move_wallet(dispute.wallet.as_deref(), 0.0, -dispute.amount);
+        } else {
+            client.available += dispute.amount;
+            client.move_wallet(dispute.wallet.as_deref(), dispute.amount, -dispute.amount);
+        }
+    }
+    close_dispute(t, DisputeState::Resolved, ongoing_disputes, closed_disputes);
+    Outcome::Applied
+}
+
+fn charge_back(
+    t: &Transaction,
+    ongoing_disputes: &mut HashMap<u32, Dispute>,
+    closed_disputes: &mut Vec<Dispute>,
+    client: &mut Client,
+) -> Outcome {
+    let Some(dispute) = ongoing_disputes.get(&t.tx) else {
+        return Outcome::Ignored("Transaction is not under dispute");
+    };
+    if dispute.state == DisputeState::Deferred {
+        return Outcome::Ignored(NOT_HELD_YET);
+    }
+    client.held -= dispute.amount;
+    // The client was right to dispute the withdrawal, the account stays open
+    if dispute.provisional_credit {
+        client.available += dispute.amount;
+        client.move_wallet(dispute.wallet.as_deref(), dispute.amount, -dispute.amount);
+    } else {
+        client.total -= dispute.amount;
+        client.locked = true;
+        client.move_wallet(dispute.wallet.as_deref(), 0.0, -dispute.amount);
+    }
+    close_dispute(
+        t,
+        DisputeState::ChargedBack,
+        ongoing_disputes,
+        closed_disputes,
+    );
+    Outcome::Applied
+}
+
+#[cfg(test)]
+// The original tests spell out the expected locked flag
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_by_timestamp() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut unsorted = transactions.clone();
+        unsorted.reverse();
+        sort_by_timestamp(&mut unsorted).unwrap();
+        let order = |transactions: &[Transaction]| {
+            transactions
+                .iter()
+                .map(|t| (t.category, t.tx))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(&unsorted), order(&transactions));
+
+        let mut undated =
+            get_transactions_from_file("src/testSamples/providedExample.csv").unwrap();
+        assert!(sort_by_timestamp(&mut undated).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_input_amount_type() {
+        get_transactions_from_file("src/testSamples/invalidAmountType.csv").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_input_client_id() {
+        get_transactions_from_file("src/testSamples/invalidClientID.csv").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_input_transaction_id() {
+        get_transactions_from_file("src/testSamples/invalidTransactionID.csv").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_rich_client() {
+        let transactions = get_transactions_from_file("src/testSamples/tooRichClient.csv").unwrap();
+        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_input_unprovided_deposit_amount() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/unprovidedAmount.csv").unwrap();
+        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_input_negative_deposit() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/negativeDeposit.csv").unwrap();
+        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_input_negative_withdraw() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/negativeWithdraw.csv").unwrap();
+        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+    }
+
+    #[test]
+    fn provided_example() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/providedExample.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        process_transactions(&transactions, &mut clients).unwrap();
+
+        assert_eq!(clients.get(&1).unwrap().available, 1.5);
+        assert_eq!(clients.get(&1).unwrap().held, 0.0);
+        assert_eq!(clients.get(&1).unwrap().total, 1.5);
+        assert_eq!(clients.get(&1).unwrap().locked, false);
+
+        assert_eq!(clients.get(&2).unwrap().available, 2.0);
+        assert_eq!(clients.get(&2).unwrap().held, 0.0);
+        assert_eq!(clients.get(&2).unwrap().total, 2.0);
+        assert_eq!(clients.get(&2).unwrap().locked, false);
+    }
+
+    #[test]
+    fn handle_dispute() {
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        process_transactions(&transactions, &mut clients).unwrap();
+
+        assert_eq!(clients.get(&1).unwrap().available, 0.5);
+        assert_eq!(clients.get(&1).unwrap().held, 1.0);
+        assert_eq!(clients.get(&1).unwrap().total, 1.5);
+        assert_eq!(clients.get(&1).unwrap().locked, false);
+
+        assert_eq!(clients.get(&2).unwrap().available, 2.0);
+        assert_eq!(clients.get(&2).unwrap().held, 0.0);
+        assert_eq!(clients.get(&2).unwrap().total, 2.0);
+        assert_eq!(clients.get(&2).unwrap().locked, false);
+    }
+
+    #[test]
+    // Multiple dispute on the same transaction + dispute on a transaction that doesn't exists
+    fn handle_tricky_disputes() {
+        let transactions = get_transactions_from_file("src/testSamples/trickyDispute.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        process_transactions(&transactions, &mut clients).unwrap();
+
+        assert_eq!(clients.get(&1).unwrap().available, 0.5);
+        assert_eq!(clients.get(&1).unwrap().held, 1.0);
+        assert_eq!(clients.get(&1).unwrap().total, 1.5);
+        assert_eq!(clients.get(&1).unwrap().locked, false);
+
+        assert_eq!(clients.get(&2).unwrap().available, 2.0);
+        assert_eq!(clients.get(&2).unwrap().held, 0.0);
+        assert_eq!(clients.get(&2).unwrap().total, 2.0);
+        assert_eq!(clients.get(&2).unwrap().locked, false);
+    }
+
+    #[test]
+    // Multiple resolves on the same dispute + resolve on a dispute that doesn't exists
+    fn handle_tricky_resolves() {
+        let transactions = get_transactions_from_file("src/testSamples/trickyResolve.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        process_transactions(&transactions, &mut clients).unwrap();
+
+        assert_eq!(clients.get(&1).unwrap().available, 1.5);
+        assert_eq!(clients.get(&1).unwrap().held, 0.0);
+        assert_eq!(clients.get(&1).unwrap().total, 1.5);
+        assert_eq!(clients.get(&1).unwrap().locked, false);
+
+        assert_eq!(clients.get(&2).unwrap().available, 2.0);
+        assert_eq!(clients.get(&2).unwrap().held, 0.0);
+        assert_eq!(clients.get(&2).unwrap().total, 2.0);
+        assert_eq!(clients.get(&2).unwrap().locked, false);
+    }
+
+    #[test]
+    fn events_record_ignored_transactions() {
+        let transactions = get_transactions_from_file("src/testSamples/trickyResolve.csv").unwrap();
+        let events = process_transactions(&transactions, &mut HashMap::new()).unwrap();
+
+        assert_eq!(events.len(), transactions.len());
+        assert_eq!(events[0].row, 1);
+        assert_eq!(events[0].outcome, Outcome::Applied);
+        assert_eq!(
+            events[4].outcome,
+            Outcome::Ignored("Insufficient available funds")
+        );
+        assert!(events
+            .iter()
+            .any(|e| e.outcome == Outcome::Ignored("Transaction is not under dispute")));
+    }
+
+    #[test]
+    fn rules_reject_flag_and_hold() {
+        let transactions = get_transactions_from_file("src/testSamples/rules.csv").unwrap();
+        let mut engine = Engine::with_rollback_capacity(transactions.len());
+        engine.set_config(
+            toml::from_str(
+                r#"
+                [tiers]
+                new = [2]
+
+                [[rules]]
+                name = "burst"
+                velocity = { count = 2, window_seconds = 60 }
+                action = "reject"
+
+                [[rules]]
+                name = "large deposits"
+                category = "deposit"
+                min_amount = 100.0
+                action = "hold"
+
+                [[rules]]
+                name = "new clients"
+                tier = "new"
+                action = "flag"
+                "#,
+            )
+            .unwrap(),
+        );
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+
+        assert_eq!(events[0].outcome, Outcome::Held);
+        assert_eq!(events[0].rule.as_deref(), Some("large deposits"));
+        assert_eq!(events[1].outcome, Outcome::Applied);
+        assert_eq!(events[2].outcome, Outcome::Ignored("Rejected by a rule"));
+        assert_eq!(events[3].outcome, Outcome::Applied);
+        assert_eq!(events[4].outcome, Outcome::Applied);
+        assert_eq!(events[4].rule.as_deref(), Some("new clients"));
+        // Held deposits can't be disputed before they are released
+        assert_eq!(events[5].outcome, Outcome::Ignored("Unknown transaction"));
+
+        assert_eq!(engine.clients[&1].available, 5.0);
+        assert_eq!(engine.clients[&1].held, 150.0);
+        assert_eq!(engine.clients[&1].total, 155.0);
+        assert_eq!(engine.held_transactions().count(), 1);
+
+        engine.rollback(transactions.len());
+        assert!(engine.clients.is_empty());
+        assert!(engine.held_transactions.is_empty());
+        assert!(engine.recent.is_empty());
+    }
+
+    #[test]
+    fn frozen_client_cannot_withdraw() {
+        let transactions = get_transactions_from_file("src/testSamples/freeze.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[2].outcome,
+            Outcome::Ignored("Client account is frozen")
+        );
+        // Deposits and the dispute flow still go through
+        assert_eq!(events[3].outcome, Outcome::Applied);
+        assert_eq!(events[4].outcome, Outcome::Applied);
+        assert_eq!(
+            events[5].outcome,
+            Outcome::Ignored("Client account is already frozen")
+        );
+        assert_eq!(events[6].outcome, Outcome::Applied);
+        assert_eq!(
+            events[8].outcome,
+            Outcome::Ignored("Client account is not frozen")
+        );
+        assert_eq!(events[9].outcome, Outcome::Applied);
+        assert_eq!(engine.clients[&1].available, 9.0);
+        assert!(!engine.clients[&1].frozen);
+        assert!(!engine.clients[&1].locked);
+    }
+
+    #[test]
+    fn opening_balances() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/openingBalance.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[1].outcome,
+            Outcome::Ignored("Opening balances are not allowed")
+        );
+
+        let mut engine = Engine::default();
+        engine.set_config(toml::from_str("[admin]\nopening_balances = true").unwrap());
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[1].outcome, Outcome::Applied);
+        assert_eq!(events[2].outcome, Outcome::Applied);
+        assert_eq!(
+            events[4].outcome,
+            Outcome::Ignored("The client already has held or wallet funds")
+        );
+        // Set rather than added, the disputed deposit now held on top
+        assert_eq!(engine.clients[&1].available, 240.0);
+        assert_eq!(engine.clients[&1].held, 10.0);
+        assert_eq!(engine.clients[&1].total, 250.0);
+        assert_eq!(engine.clients[&2].total, -4.0);
+        assert!(engine.transactions_history.contains_key(&2));
+    }
+
+    #[test]
+    fn balance_assertions() {
+        let transactions = get_transactions_from_file("src/testSamples/assertBalance.csv").unwrap();
+        let mut engine = Engine::with_rollback_capacity(1);
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[2].outcome, Outcome::Applied);
+        assert_eq!(events[4].outcome, Outcome::Applied);
+        assert_eq!(events[5].outcome, Outcome::Ignored(BALANCE_MISMATCH));
+        // Still checked once the client is locked
+        assert_eq!(events[7].outcome, Outcome::Ignored(BALANCE_MISMATCH));
+        assert_eq!(engine.clients[&1].available, -4.0);
+        assert_eq!(engine.clients[&1].total, -4.0);
+        assert_eq!(
+            engine.balance_mismatches()[1],
+            BalanceMismatch {
+                row: 8,
+                client_id: 1,
+                expected_available: -4.0,
+                available: -4.0,
+                expected_total: Some(-5.0),
+                total: -4.0,
+            }
+        );
+
+        // Rolled back along with the row
+        engine.rollback(1);
+        assert_eq!(engine.balance_mismatches().len(), 1);
+    }
+
+    #[test]
+    fn closures() {
+        let transactions = get_transactions_from_file("src/testSamples/close.csv").unwrap();
+        let mut engine = Engine::with_rollback_capacity(1);
+        engine.set_config(toml::from_str("[admin]\nhouse_account = 0").unwrap());
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[3].outcome,
+            Outcome::Ignored("The client still has held funds")
+        );
+        assert_eq!(events[4].outcome, Outcome::Applied);
+        assert_eq!(
+            events[5].outcome,
+            Outcome::Ignored("Client account is locked")
+        );
+        assert_eq!(
+            events[7].outcome,
+            Outcome::Ignored("The tx of the sweep is already used")
+        );
+        assert_eq!(events[8].outcome, Outcome::Applied);
+        assert!(engine.clients[&1].locked);
+        assert_eq!(engine.clients[&1].total, 0.0);
+        assert_eq!(engine.clients[&0].available, 15.0);
+        assert_eq!(engine.clients[&0].total, 15.0);
+        // The sweep, audited in the history
+        assert_eq!(engine.transactions_history[&4].amount, Some(10.0));
+        assert_eq!(engine.transactions_history[&4].reason.as_deref(), Some("0"));
+
+        engine.rollback(1);
+        assert_eq!(engine.clients[&0].total, 10.0);
+        assert_eq!(engine.clients[&2].total, 5.0);
+        assert!(!engine.clients[&2].locked);
+    }
+
+    #[test]
+    fn history_retention() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/historyRetention.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        assert_eq!(engine.evict_history(), Ok(Eviction::default()));
+        engine.set_config(toml::from_str("[history]\nretention_seconds = 900").unwrap());
+        assert_eq!(
+            engine.evict_history(),
+            Ok(Eviction {
+                expired: 1,
+                charged_back: 1,
+                kept: 3,
+            })
+        );
+        // The disputed deposit and the undated one are kept
+        let mut kept: Vec<u32> = engine.transactions_history.keys().copied().collect();
+        kept.sort_unstable();
+        assert_eq!(kept, vec![2, 4, 5]);
+        let mut evicted = engine.evicted_history.clone();
+        evicted.sort_unstable();
+        assert_eq!(evicted, vec![(1, 1), (2, 3)]);
+
+        // Another client reusing the charged back tx id keeps its entry
+        engine
+            .process(&Transaction {
+                category: TransactionCategory::Deposit,
+                client_id: 1,
+                tx: 3,
+                amount: Some(4.0),
+                reason: None,
+                timestamp: Some(1200),
+                wallet: None,
+                seq: None,
+            })
+            .unwrap();
+        assert_eq!(
+            engine.evict_history(),
+            Ok(Eviction {
+                expired: 0,
+                charged_back: 0,
+                kept: 4,
+            })
+        );
+        assert_eq!(engine.transactions_history[&3].client_id, 1);
+    }
+
+    #[test]
+    fn client_erasure() {
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        assert!(engine.erase_client(2).is_err());
+        engine.set_config(toml::from_str("[admin]\nanonymized_account = 0").unwrap());
+        // Its ongoing dispute would be left without a client
+        assert!(engine.erase_client(1).is_err());
+        assert_eq!(
+            engine.erase_client(2),
+            Ok(Erasure {
+                history: 1,
+                disputes: 0,
+                folded: 2.0,
+            })
+        );
+        assert!(!engine.clients.contains_key(&2));
+        assert!(engine
+            .transactions_history
+            .values()
+            .all(|t| t.client_id != 2));
+        assert_eq!(engine.clients[&0].total, 2.0);
+        assert!(engine.erase_client(2).is_err());
+
+        // A member leaves its joint account, which can't be erased while it has members
+        let mut engine = Engine::default();
+        engine.set_config(toml::from_str("[admin]\nanonymized_account = 0").unwrap());
+        engine.set_accounts(HashMap::from([(3, 10), (4, 10)]));
+        engine
+            .process(&Transaction {
+                category: TransactionCategory::Deposit,
+                client_id: 3,
+                tx: 10,
+                amount: Some(1.0),
+                reason: None,
+                timestamp: None,
+                wallet: None,
+                seq: None,
+            })
+            .unwrap();
+        assert!(engine.erase_client(10).is_err());
+        assert_eq!(
+            engine.erase_client(3),
+            Ok(Erasure {
+                history: 0,
+                disputes: 0,
+                folded: 0.0,
+            })
+        );
+        assert_eq!(engine.accounts, HashMap::from([(4, 10)]));
+    }
+
+    #[test]
+    fn adjustments() {
+        let transactions = get_transactions_from_file("src/testSamples/adjustment.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[1].outcome,
+            Outcome::Ignored("Adjustments are not allowed")
+        );
+
+        let mut engine = Engine::default();
+        engine.set_config(toml::from_str("[admin]\nadjustments = true").unwrap());
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[1].outcome, Outcome::Applied);
+        assert_eq!(
+            events[2].outcome,
+            Outcome::Ignored("An adjustment needs a reason")
+        );
+        assert_eq!(events[3].outcome, Outcome::Applied);
+        assert_eq!(
+            events[4].outcome,
+            Outcome::Ignored("Only deposits can be disputed")
+        );
+        assert_eq!(engine.clients[&1].available, 7.5);
+        assert_eq!(engine.clients[&2].total, 3.0);
+    }
+
+    #[test]
+    fn wallets() {
+        let transactions = get_transactions_from_file("src/testSamples/wallets.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        // The savings can't be withdrawn from the main wallet
+        assert_eq!(
+            events[3].outcome,
+            Outcome::Ignored("Insufficient available funds")
+        );
+        assert_eq!(events[4].outcome, Outcome::Applied);
+        // The disputed deposit is held in its wallet
+        assert_eq!(
+            events[6].outcome,
+            Outcome::Ignored("Insufficient available funds")
+        );
+        let client = &engine.clients[&1];
+        assert_eq!(
+            (client.available, client.held, client.total),
+            (13.0, 0.0, 13.0)
+        );
+        assert_eq!(
+            client.wallet_balances(),
+            vec![
+                (
+                    "main",
+                    Wallet {
+                        available: 7.0,
+                        held: 0.0
+                    }
+                ),
+                (
+                    "savings",
+                    Wallet {
+                        available: 6.0,
+                        held: 0.0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn approximate_memory_grows_with_the_history() {
+        let mut engine = Engine::default();
+        let empty = engine.approximate_memory();
+        for t in &simulation::workload(1, 1000) {
+            engine.process(t).unwrap();
+        }
+        assert!(
+            engine.approximate_memory()
+                > empty + engine.transactions_history.len() * std::mem::size_of::<Transaction>()
+        );
+    }
+
+    #[test]
+    fn bonus_clawback() {
+        let transactions = get_transactions_from_file("src/testSamples/bonus.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        assert_eq!(engine.clients[&1].total, 25.0);
+
+        let mut engine = Engine::default();
+        engine.set_config(toml::from_str("[bonus]\nclawback_window_seconds = 1000").unwrap());
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        // Only the bonus granted within the window is clawed back
+        let client = &engine.clients[&1];
+        assert_eq!(
+            (client.available, client.held, client.total),
+            (5.0, 0.0, 5.0)
+        );
+        assert_eq!(client.wallet(Some(BONUS_WALLET)).available, 5.0);
+        assert!(client.locked);
+    }
+
+    #[test]
+    fn escrow_buckets() {
+        let transactions = get_transactions_from_file("src/testSamples/escrow.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[3].outcome,
+            Outcome::Ignored("Insufficient available funds")
+        );
+        // The escrowed funds can't be withdrawn
+        assert_eq!(
+            events[4].outcome,
+            Outcome::Ignored("Insufficient available funds")
+        );
+        assert_eq!(events[6].outcome, Outcome::Applied);
+        assert_eq!(
+            events[7].outcome,
+            Outcome::Ignored("Insufficient escrowed funds")
+        );
+        assert_eq!(events[8].outcome, Outcome::Applied);
+        assert_eq!(events[9].outcome, Outcome::Ignored("Unknown escrow bucket"));
+        let client = &engine.clients[&1];
+        assert_eq!(
+            (client.available, client.held, client.total),
+            (6.0, 0.0, 6.0)
+        );
+        assert!(client.escrow.is_empty());
+    }
+
+    #[test]
+    fn withdrawal_dispute_as_provisional_credit() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/withdrawalDispute.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[2].outcome,
+            Outcome::Ignored("Only deposits can be disputed")
+        );
+
+        let mut engine = Engine::default();
+        engine.set_config(
+            toml::from_str("[disputes]\nwithdrawals = \"provisional_credit\"").unwrap(),
+        );
+        for (rows, balance) in [
+            // Disputed : the withdrawn amount comes back as held
+            (0..3, (6.0, 4.0, 10.0)),
+            // Resolved : the credit is taken back
+            (3..4, (6.0, 0.0, 6.0)),
+            // Charged back : the credit becomes available and the account stays open
+            (4..7, (6.0, 0.0, 6.0)),
+        ] {
+            for t in &transactions[rows] {
+                assert_eq!(engine.process(t).unwrap().outcome, Outcome::Applied);
+            }
+            let client = &engine.clients[&1];
+            assert_eq!((client.available, client.held, client.total), balance);
+        }
+        assert!(!engine.clients[&1].locked);
+    }
+
+    #[test]
+    fn dispute_of_another_clients_transaction() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeOtherClient.csv").unwrap();
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(
+            events[2].outcome,
+            Outcome::Ignored("Transaction belongs to another client")
+        );
+        assert_eq!(
+            events[4].outcome,
+            Outcome::Ignored("Transaction belongs to another client")
+        );
+        assert_eq!(events[5].outcome, Outcome::Applied);
+        assert_eq!(engine.clients[&1].available, 5.0);
+        assert_eq!(engine.clients[&2].total, 3.0);
+
+        // Routed, the first dispute and the chargeback apply to client 1
+        let mut engine = Engine::with_rollback_capacity(transactions.len());
+        engine.set_config(toml::from_str("[disputes]\nclient_mismatch = \"route\"").unwrap());
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[2].routed_to, Some(1));
+        assert_eq!(
+            events[3].outcome,
+            Outcome::Ignored("Transaction is already under dispute")
+        );
+        assert_eq!(events[4].routed_to, Some(1));
+        assert_eq!(
+            events[5].outcome,
+            Outcome::Ignored("Client account is locked")
+        );
+        assert_eq!(engine.clients[&1].total, 0.0);
+        assert!(engine.clients[&1].locked);
+        assert_eq!(engine.clients[&2].total, 3.0);
+        assert!(!engine.clients[&2].locked);
+
+        engine.rollback(2);
+        assert_eq!(engine.clients[&1].held, 5.0);
+        assert!(!engine.clients[&1].locked);
+    }
+
+    #[test]
+    fn rollback_last_transactions() {
+        let transactions = get_transactions_from_file("src/testSamples/chargeback.csv").unwrap();
+        let mut engine = Engine::with_rollback_capacity(3);
+        for t in &transactions[..transactions.len() - 1] {
+            engine.process(t).unwrap();
+        }
+        let before_chargeback = engine.clients[&1].clone();
+        engine
+            .process(&transactions[transactions.len() - 1])
+            .unwrap();
+        assert!(engine.clients[&1].locked);
+
+        let reverted = engine.rollback(1);
+        assert_eq!(reverted.len(), 1);
+        assert!(matches!(
+            reverted[0].category,
+            TransactionCategory::Chargeback
+        ));
+        assert!(!engine.clients[&1].locked);
+        assert_eq!(engine.clients[&1].held, before_chargeback.held);
+        assert!(engine.ongoing_disputes.contains_key(&1));
+        assert!(engine
+            .disputes()
+            .all(|d| d.state != DisputeState::ChargedBack));
+
+        // Only 2 more transactions are left in the rollback log
+        assert_eq!(engine.rollback(10).len(), 2);
+        assert_eq!(engine.processed, transactions.len() - 3);
+    }
+
+    #[test]
+    fn redelivered_deposits() {
+        let input = "type, client, tx, amount, reason, timestamp
+deposit, 1, 1, 5.0, , 100
+deposit, 1, 2, 3.0, , 110
+deposit, 1, 1, 5.0, , 120
+withdrawal, 1, 2, 3.0, , 200
+";
+        let transactions = read_transactions(input.as_bytes()).unwrap();
+        let mut engine = Engine::with_rollback_capacity(1);
+        engine.set_config(toml::from_str("[dedup]\nseconds = 60").unwrap());
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[2].outcome, Outcome::Ignored(DUPLICATE));
+        // Tx 2 left the window 90 seconds earlier
+        assert_eq!(events[3].outcome, Outcome::Applied);
+        assert_eq!(engine.clients[&1].available, 5.0);
+
+        // Once rolled back, the withdrawal isn't a duplicate of itself
+        engine.rollback(1);
+        assert_eq!(
+            engine.process(&transactions[3]).unwrap().outcome,
+            Outcome::Applied
+        );
+
+        let mut engine = Engine::default();
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| engine.process(t).unwrap())
+            .collect();
+        assert_eq!(events[2].outcome, Outcome::Applied);
+    }
+
+    #[test]
+    // A deposit reusing the tx id of a disputed one replaces its history entry : settling the
+    // dispute with that entry would release 3.0 and leave 7.0 held for good
+    fn disputes_settle_the_recorded_amount() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/reusedDisputedTx.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        assert_eq!(engine.clients[&1].held, 0.0);
+        assert_eq!(engine.clients[&1].available, 13.0);
+        assert_eq!(engine.clients[&1].total, 13.0);
+    }
+
+    #[test]
+    fn reused_tx_ids() {
+        let transactions = get_transactions_from_file("src/testSamples/reusedTx.csv").unwrap();
+        let run = |policy: &str| {
+            let mut engine = Engine::default();
+            let config = format!("[history]\nreused_tx = \"{}\"", policy);
+            engine.set_config(toml::from_str(&config).unwrap());
+            let events: Vec<Event> = transactions
+                .iter()
+                .map(|t| engine.process(t).unwrap())
+                .collect();
+            (events, engine.clients[&1].clone())
+        };
+
+        // The dispute references the withdrawal
+        let (events, client) = run("last_wins");
+        assert_eq!(events[1].outcome, Outcome::Applied);
+        assert_eq!(
+            events[2].outcome,
+            Outcome::Ignored("Only deposits can be disputed")
+        );
+        assert_eq!((client.available, client.held), (3.0, 0.0));
+
+        // The dispute references the deposit, the withdrawn funds are still gone
+        let (events, client) = run("first_wins");
+        assert_eq!(events[1].outcome, Outcome::Applied);
+        assert_eq!(events[2].outcome, Outcome::Applied);
+        assert_eq!((client.available, client.held), (-2.0, 5.0));
+
+        let (events, client) = run("reject");
+        assert_eq!(events[1].outcome, Outcome::Ignored(REUSED_TX));
+        assert_eq!(events[1].rule, None);
+        assert_eq!(events[2].outcome, Outcome::Applied);
+        assert_eq!((client.available, client.held), (0.0, 5.0));
+
+        let (events, client) = run("reject_and_flag");
+        assert_eq!(events[1].outcome, Outcome::Ignored(REUSED_TX));
+        assert_eq!(events[1].rule.as_deref(), Some(REUSED_TX_FLAG));
+        assert_eq!((client.available, client.held), (0.0, 5.0));
+    }
+
+    #[test]
+    fn dispute_of_withdrawn_funds() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeInsufficientFunds.csv").unwrap();
+        let engine = |policy: &str| {
+            let mut engine = Engine::with_rollback_capacity(1);
+            let config = format!("[disputes]\ninsufficient_funds = \"{}\"", policy);
+            engine.set_config(toml::from_str(&config).unwrap());
+            engine
+        };
+        let balances = |engine: &Engine| {
+            let client = &engine.clients[&1];
+            (client.available, client.held, client.total)
+        };
+
+        let mut negative = engine("allow_negative");
+        for t in &transactions[..3] {
+            negative.process(t).unwrap();
+        }
+        assert_eq!(balances(&negative), (-8.0, 10.0, 2.0));
+
+        let mut capped = engine("cap");
+        let events: Vec<Event> = transactions
+            .iter()
+            .map(|t| capped.process(t).unwrap())
+            .collect();
+        assert_eq!(events[2].outcome, Outcome::Applied);
+        assert_eq!(events[2].rule.as_deref(), Some(CAPPED_HOLD_FLAG));
+        // Only the 2.0 left were held, and charged back
+        assert_eq!(balances(&capped), (9.0, 0.0, 9.0));
+        assert!(capped.clients[&1].locked);
+
+        let mut deferred = engine("defer");
+        let events: Vec<Event> = transactions[..4]
+            .iter()
+            .map(|t| deferred.process(t).unwrap())
+            .collect();
+        assert_eq!(events[2].outcome, Outcome::Deferred);
+        // The deposit of 9.0 covers the dispute
+        assert_eq!(balances(&deferred), (1.0, 10.0, 11.0));
+        assert_eq!(deferred.ongoing_disputes[&1].opened_at, Some(130));
+        deferred.rollback(1);
+        assert_eq!(balances(&deferred), (2.0, 0.0, 2.0));
+        assert_eq!(
+            deferred.process(&transactions[4]).unwrap().outcome,
+            Outcome::Ignored("Disputed funds are not held yet")
+        );
+        let resolve = Transaction {
+            category: TransactionCategory::Resolve,
+            ..transactions[4].clone()
+        };
+        assert_eq!(
+            deferred.process(&resolve).unwrap().outcome,
+            Outcome::Applied
+        );
+        assert_eq!(balances(&deferred), (2.0, 0.0, 2.0));
+        assert_eq!(deferred.closed_disputes[0].amount, 0.0);
+    }
+
+    #[test]
+    fn step_by_step() {
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut engine = Engine::default();
+        let ApplyOutcome::Applied { event, client } = engine.apply(&transactions[0]) else {
+            panic!("The first deposit is applied");
+        };
+        assert_eq!(event.outcome, Outcome::Applied);
+        assert_eq!(client.unwrap().total, transactions[0].amount.unwrap());
+
+        let unknown = Transaction {
+            category: TransactionCategory::Resolve,
+            tx: 999,
+            ..transactions[0].clone()
+        };
+        assert!(matches!(
+            engine.apply(&unknown),
+            ApplyOutcome::Ignored {
+                reason: "Transaction is not under dispute",
+                ..
+            }
+        ));
+
+        let negative = Transaction {
+            amount: Some(-1.0),
+            ..transactions[0].clone()
+        };
+        let ApplyOutcome::Rejected { reason, client } = engine.apply(&negative) else {
+            panic!("A negative deposit is rejected");
+        };
+        assert_eq!(reason, "Cannot deposit a negative amount");
+        assert_eq!(client.unwrap().total, transactions[0].amount.unwrap());
+
+        // A missing amount is rejected too, for a client the engine doesn't know
+        let missing = Transaction {
+            category: TransactionCategory::Withdrawal,
+            client_id: 99,
+            amount: None,
+            ..transactions[0].clone()
+        };
+        let ApplyOutcome::Rejected { reason, client } = engine.apply(&missing) else {
+            panic!("A withdrawal without an amount is rejected");
+        };
+        assert_eq!(reason, "A withdrawal transaction needs an amount");
+        assert!(client.is_none());
+        assert!(!engine.clients.contains_key(&99));
+    }
+
+    #[test]
+    fn activity_counts() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::with_rollback_capacity(1);
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        let charged_back = Activity {
+            deposits: 1,
+            withdrawals: 0,
+            chargebacks: 1,
+            last_activity: Some(1700000500),
+        };
+        assert_eq!(engine.activity()[&2], charged_back);
+        assert_eq!(engine.open_disputes()[&1], 1);
+
+        engine.rollback(1);
+        assert_eq!(engine.activity()[&2].chargebacks, 0);
+        assert_eq!(engine.activity()[&2].last_activity, Some(1700000450));
+    }
+
+    #[test]
+    fn rejected_transaction_leaves_engine_untouched() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/negativeDeposit.csv").unwrap();
+        let mut engine = Engine::default();
+        assert!(engine.process(&transactions[0]).is_err());
+        assert!(engine.clients.is_empty());
+        assert_eq!(engine.processed, 0);
+    }
+
+    #[test]
+    fn handle_charge_back() {
+        let transactions = get_transactions_from_file("src/testSamples/chargeback.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        process_transactions(&transactions, &mut clients).unwrap();
+
+        assert_eq!(clients.get(&1).unwrap().available, 0.5);
+        assert_eq!(clients.get(&1).unwrap().held, 0.0);
+        assert_eq!(clients.get(&1).unwrap().total, 0.5);
+        assert_eq!(clients.get(&1).unwrap().locked, true);
+
+        assert_eq!(clients.get(&2).unwrap().available, 2.0);
+        assert_eq!(clients.get(&2).unwrap().held, 0.0);
+        assert_eq!(clients.get(&2).unwrap().total, 2.0);
+        assert_eq!(clients.get(&2).unwrap().locked, false);
+    }
+}
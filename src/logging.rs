@@ -0,0 +1,126 @@
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+// -q, default, -v and -vv. Errors are written at every level.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    // Nothing but the output and the errors
+    Quiet,
+    // Warnings and a summary of the run
+    Normal,
+    // Every rejected transaction with its reason
+    Verbose,
+    // Every transaction with its outcome
+    Trace,
+}
+
+// Records go to stderr as the plain messages by default. With --log-format json, each record
+// is a json object on its own line with stable field names : timestamp (unix milliseconds),
+// level, event, message when there is one, then the fields of the event.
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+// Also hides the progress bar
+pub fn is_quiet() -> bool {
+    !enabled(Verbosity::Normal)
+}
+
+// Structured record only : the text output stays terse, without a line per file
+pub fn event(event: &str, fields: Value) {
+    if is_json() && enabled(Verbosity::Normal) {
+        eprintln!("{}", record("info", event, None, fields));
+    }
+}
+
+pub fn info(event: &str, message: &str, fields: Value) {
+    log(Verbosity::Normal, "info", event, message, fields)
+}
+
+pub fn warn(event: &str, message: &str, fields: Value) {
+    log(Verbosity::Normal, "warn", event, message, fields)
+}
+
+pub fn error(event: &str, message: &str, fields: Value) {
+    log(Verbosity::Quiet, "error", event, message, fields)
+}
+
+// A record per rejection : written from -v in text, by default in json where the consumer
+// filters the records itself
+pub fn detail(event: &str, message: &str, fields: Value) {
+    let verbosity = if is_json() {
+        Verbosity::Normal
+    } else {
+        Verbosity::Verbose
+    };
+    log(verbosity, "info", event, message, fields)
+}
+
+// A record per transaction, from -vv
+pub fn trace(event: &str, message: &str, fields: Value) {
+    log(Verbosity::Trace, "debug", event, message, fields)
+}
+
+fn log(verbosity: Verbosity, level: &str, event: &str, message: &str, fields: Value) {
+    if !enabled(verbosity) {
+        return;
+    }
+    if is_json() {
+        eprintln!("{}", record(level, event, Some(message), fields));
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+fn record(level: &str, event: &str, message: Option<&str>, fields: Value) -> Value {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64);
+    let mut record = Map::new();
+    record.insert("timestamp".to_string(), json!(timestamp));
+    record.insert("level".to_string(), json!(level));
+    record.insert("event".to_string(), json!(event));
+    if let Some(message) = message {
+        record.insert("message".to_string(), json!(message));
+    }
+    if let Value::Object(fields) = fields {
+        record.extend(fields);
+    }
+    Value::Object(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_fields() {
+        let record = record(
+            "warn",
+            "decision_skipped",
+            Some("Decision on tx 3 skipped"),
+            json!({"tx": 3}),
+        );
+        assert_eq!(record["level"], "warn");
+        assert_eq!(record["event"], "decision_skipped");
+        assert_eq!(record["message"], "Decision on tx 3 skipped");
+        assert_eq!(record["tx"], 3);
+        assert!(record["timestamp"].as_u64().unwrap() > 0);
+    }
+}
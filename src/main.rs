@@ -1,366 +1,1624 @@
-use serde::Deserialize;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use payments_engine::config::Config;
+use payments_engine::diagnostics::ParseDiagnostic;
+use payments_engine::encryption::StateKey;
+use payments_engine::logging::Verbosity;
+use payments_engine::state::{DirectoryStore, ErasureRecord, StateStore};
+use payments_engine::{
+    accounts, config, dates, dry_run, extract, inspect, ledger, logging, parallel_parse, protobuf,
+    pseudonym, read_transactions_with, reference, repl, reports, review, risk, sequence, signature,
+    simulation, state, statements, table_output, whatif, Activity, Client, Engine, Event, Outcome,
+    Transaction,
+};
+use serde_json::json;
 use std::collections::HashMap;
-use std::collections::HashSet;
 use std::env;
 use std::error::Error;
-use std::io::Write;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Deserialize, Clone, Debug)]
-struct Transaction {
-    #[serde(rename = "type")]
-    category: TransactionCategory,
-    #[serde(rename = "client")]
-    client_id: u16,
-    tx: u32,
-    amount: Option<f64>,
+#[cfg(all(feature = "jemalloc", feature = "mimalloc"))]
+compile_error!("The jemalloc and mimalloc features can't be enabled together");
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static ALLOCATOR: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+const DEFAULT_TOP_CLIENTS: usize = 10;
+const DEFAULT_SIMULATED_TRANSACTIONS: usize = 100_000;
+// Runs of the input when the save conflicts with another instance sharing the state
+const SAVE_ATTEMPTS: usize = 3;
+// Tolerance of the balance check, the amounts being floats
+const BALANCE_EPSILON: f64 = 1e-6;
+const MEMORY_CHECK_INTERVAL: usize = 4096;
+
+struct Args {
+    input: Option<String>,
+    input_format: Option<String>,
+    output: Option<String>,
+    format: Option<String>,
+    no_color: bool,
+    state: Option<String>,
+    state_key: Option<String>,
+    initial_state: Option<String>,
+    dry_run: bool,
+    as_of: Option<u64>,
+    top: Option<usize>,
+    // Metric of the top report
+    by: Option<String>,
+    config: Option<String>,
+    // Config compared with --config by the whatif command
+    variant: Option<String>,
+    decisions: Option<String>,
+    review_queue: Option<String>,
+    sign_key: Option<String>,
+    signature: Option<String>,
+    pseudonymize_key: Option<String>,
+    pseudonym_map: Option<String>,
+    seed: Option<u64>,
+    transactions: Option<usize>,
+    diagnostics: Option<String>,
+    accounts: Option<String>,
+    report_by: Option<String>,
+    io_backend: Option<String>,
+    parse_threads: Option<usize>,
+    max_memory: Option<usize>,
+    client: Option<u16>,
+    period: Option<String>,
+    display_currency: Option<String>,
+    category: Option<String>,
+    sort_by_timestamp: bool,
+    extended_output: bool,
+    reorder_window: Option<usize>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
-#[serde(rename_all = "lowercase")]
-enum TransactionCategory {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
+fn main() {
+    if let Err(e) = run() {
+        if logging::is_json() {
+            logging::error("run_failed", &e.to_string(), json!({}));
+        } else {
+            eprintln!("Error: {:?}", e);
+        }
+        std::process::exit(1);
+    }
 }
 
-#[derive(Debug)]
-struct Client {
-    available: f64,
-    held: f64,
-    total: f64,
-    locked: bool,
+fn run() -> Result<(), Box<dyn Error>> {
+    match env::args().nth(1).as_deref() {
+        Some("repl") => return Ok(repl::run(std::io::stdin().lock(), &mut std::io::stdout())?),
+        Some("backfill") => return backfill(&parse_args(env::args().skip(2))),
+        Some("erase-client") => return erase_client(&parse_args(env::args().skip(2))),
+        Some("verify") => return verify(&parse_args(env::args().skip(2))),
+        Some("verify-ledger") => return verify_ledger(&parse_args(env::args().skip(2))),
+        Some("simulate") => return simulate(&parse_args(env::args().skip(2))),
+        Some("compare") => return compare(&parse_args(env::args().skip(2))),
+        Some("whatif") => return whatif(&parse_args(env::args().skip(2))),
+        Some("filter") => {
+            // The output file comes last, after the input file
+            let mut args: Vec<String> = env::args().skip(2).collect();
+            let output = args.pop();
+            return filter(&parse_args(args.into_iter()), output);
+        }
+        Some("inspect") => return inspect_file(&parse_args(env::args().skip(2))),
+        Some("balance") => return balance(&parse_args(env::args().skip(2))),
+        Some("query") => return query(&parse_args(env::args().skip(2))),
+        Some("statements") => return write_statements(&parse_args(env::args().skip(2))),
+        Some("report") => {
+            let kind = env::args().nth(2).unwrap_or_default();
+            return report(&kind, &parse_args(env::args().skip(3)));
+        }
+        _ => (),
+    }
+    let args = parse_args(env::args().skip(1));
+    match args.input.as_deref() {
+        Some(input) if input.starts_with("redis://") => return consume_redis_stream(&args),
+        Some(input) if input.starts_with("amqp://") || input.starts_with("amqps://") => {
+            return consume_amqp_queue(&args)
+        }
+        _ => (),
+    }
+    let transactions = get_transactions_from_args(&args)?;
+    if args.dry_run {
+        let report = dry_run::dry_run(load_engine(&args)?, &transactions);
+        return Ok(report.write(&mut std::io::stdout().lock())?);
+    }
+    let (engine, events) = process_and_save(&args, &transactions, None)?;
+    write_results(&args, &engine, &events)
 }
 
-impl Default for Client {
-    fn default() -> Self {
-        Client {
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
-            locked: false,
+// Processes the input and saves the state, with the review queue if asked for. When another
+// instance sharing the state saved some of the same clients in the meantime, nothing is saved
+// and the input is processed again from a fresh load of the state. A row the engine refuses
+// aborts the run, unless `rejected` is given : the index and the reason of the row are then
+// pushed to it and the row gets no event.
+fn process_and_save(
+    args: &Args,
+    transactions: &[Transaction],
+    mut rejected: Option<&mut Vec<(usize, String)>>,
+) -> Result<(Engine, Vec<Event>), Box<dyn Error>> {
+    let store = state_store(args)?;
+    let started = Instant::now();
+    let mut attempt = 1;
+    loop {
+        let mut engine = load_engine_from(args, store.as_deref())?;
+        let mut events = Vec::with_capacity(transactions.len());
+        if let Some(rejected) = rejected.as_deref_mut() {
+            rejected.clear();
+        }
+        for (i, t) in transactions.iter().enumerate() {
+            match (engine.process(t), rejected.as_deref_mut()) {
+                (Ok(event), _) => events.push(event),
+                (Err(e), Some(rejected)) => rejected.push((i, e)),
+                (Err(e), None) => return Err(e.into()),
+            }
+            if i % MEMORY_CHECK_INTERVAL == 0 {
+                check_memory_budget(args, &engine, transactions, i + 1)?;
+            }
+        }
+        let eviction = engine.evict_history()?;
+        if eviction.expired + eviction.charged_back > 0 {
+            logging::info(
+                "history_evicted",
+                &format!(
+                    "{} expired and {} charged back history entries evicted, {} kept",
+                    eviction.expired, eviction.charged_back, eviction.kept
+                ),
+                json!({
+                    "expired": eviction.expired,
+                    "charged_back": eviction.charged_back,
+                    "kept": eviction.kept,
+                }),
+            );
+        }
+        if let Some(store) = &store {
+            match store.save(&engine) {
+                Err(e) if e.is::<state::Conflict>() && attempt < SAVE_ATTEMPTS => {
+                    logging::warn(
+                        "save_conflict",
+                        &format!("{}, processing the input again", e),
+                        json!({ "attempt": attempt }),
+                    );
+                    attempt += 1;
+                    continue;
+                }
+                result => result?,
+            }
+        }
+        report_client_mismatches(&events);
+        report_events(&events);
+        check_balances(&engine);
+        report_balance_mismatches(&engine);
+        write_review_queue(args, &engine)?;
+        let count =
+            |outcome: fn(&Outcome) -> bool| events.iter().filter(|e| outcome(&e.outcome)).count();
+        let (applied, held, deferred, ignored) = (
+            count(|o| *o == Outcome::Applied),
+            count(|o| *o == Outcome::Held),
+            count(|o| *o == Outcome::Deferred),
+            count(|o| matches!(o, Outcome::Ignored(_))),
+        );
+        let duplicates = count(|o| *o == Outcome::Ignored(payments_engine::DUPLICATE));
+        if duplicates > 0 {
+            logging::info(
+                "duplicates_suppressed",
+                &format!("{} redelivered rows ignored as duplicates", duplicates),
+                json!({ "rows": duplicates }),
+            );
         }
+        logging::info(
+            "batch_finished",
+            &format!(
+                "{} rows : {} applied, {} held, {} deferred, {} ignored, {} clients",
+                events.len(),
+                applied,
+                held,
+                deferred,
+                ignored,
+                engine.clients().len()
+            ),
+            json!({
+                "rows": events.len(),
+                "applied": applied,
+                "held": held,
+                "deferred": deferred,
+                "ignored": ignored,
+                "duplicates": duplicates,
+                "clients": engine.clients().len(),
+                "attempts": attempt,
+                "duration_ms": started.elapsed().as_millis() as u64,
+            }),
+        );
+        return Ok((engine, events));
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let transactions = get_transactions_from_args()?;
-    let mut clients: HashMap<u16, Client> = HashMap::new();
-    process_transactions(&transactions, &mut clients)?;
-    write_clients_state(&clients)?;
+fn write_results(args: &Args, engine: &Engine, events: &[Event]) -> Result<(), Box<dyn Error>> {
+    if args.extended_output {
+        return write_extended_output(args, engine);
+    }
+    let members;
+    let clients = match args.report_by.as_deref() {
+        None | Some("account") => engine.clients(),
+        Some("wallet") => return write_wallets_output(args, engine.clients()),
+        Some("member") => {
+            members = accounts::members(engine.clients(), engine.accounts());
+            &members
+        }
+        Some(report_by) => return Err(format!("Unknown report by : {}", report_by).into()),
+    };
+    write_output(args, clients, events)
+}
 
-    Ok(())
+// For the stream and queue inputs : `acknowledge` runs once the state is saved, a run stopping
+// before that gets the messages back on restart. A dry run leaves them unacknowledged. The rows
+// the engine refuses don't abort the run, `acknowledge` gets their index and reason to settle
+// them so they aren't redelivered forever.
+#[cfg(any(feature = "redis", feature = "amqp"))]
+fn process_acknowledged(
+    args: &Args,
+    transactions: &[Transaction],
+    acknowledge: impl FnOnce(&[Event], &[(usize, String)]) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    if args.dry_run {
+        let report = dry_run::dry_run(load_engine(args)?, transactions);
+        return Ok(report.write(&mut std::io::stdout().lock())?);
+    }
+    let mut rejected = Vec::new();
+    let (engine, events) = process_and_save(args, transactions, Some(&mut rejected))?;
+    acknowledge(&events, &rejected)?;
+    write_results(args, &engine, &events)
 }
 
-fn get_transactions_from_args() -> Result<Vec<Transaction>, csv::Error> {
-    let file_path = env::args()
-        .nth(1)
-        .expect("Please provide the csv file path as the first argument");
-    get_transactions_from_file(&file_path)
+// The events are published before the entries are acknowledged
+#[cfg(feature = "redis")]
+fn consume_redis_stream(args: &Args) -> Result<(), Box<dyn Error>> {
+    let input = args.input.as_deref().unwrap_or_default();
+    let mut stream = payments_engine::redis_stream::connect(input)?;
+    let (transactions, order) = sequence_input(args, stream.read_transactions()?)?;
+    stream.reorder(&order);
+    process_acknowledged(args, &transactions, |events, rejected| {
+        stream.skip_rejected(rejected)?;
+        stream.publish_events(events)?;
+        stream.acknowledge()
+    })
 }
 
-fn get_transactions_from_file(file_path: &str) -> Result<Vec<Transaction>, csv::Error> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_path(file_path)?;
+#[cfg(not(feature = "redis"))]
+fn consume_redis_stream(_args: &Args) -> Result<(), Box<dyn Error>> {
+    Err("Redis Streams input requires building with the `redis` feature".into())
+}
 
-    rdr.deserialize()
-        .collect::<Result<Vec<Transaction>, csv::Error>>()
+#[cfg(feature = "amqp")]
+fn consume_amqp_queue(args: &Args) -> Result<(), Box<dyn Error>> {
+    let input = args.input.as_deref().unwrap_or_default();
+    let mut queue = payments_engine::amqp_input::connect(input)?;
+    let (transactions, order) = sequence_input(args, queue.read_transactions()?)?;
+    queue.reorder(&order);
+    process_acknowledged(args, &transactions, |_, rejected| {
+        queue.dead_letter(rejected)?;
+        queue.acknowledge()
+    })
 }
 
-fn write_clients_state(clients: &HashMap<u16, Client>) -> Result<(), std::io::Error> {
-    // See https://nnethercote.github.io/perf-book/io.html
-    let stdout = std::io::stdout();
-    let mut lock = stdout.lock();
-    writeln!(lock, "client,available,held,total,locked")?;
-    for (client_id, client) in clients {
-        writeln!(
-            lock,
-            "{},{:.4},{:.4},{:.4},{}",
-            client_id, client.available, client.held, client.total, client.locked
-        )?;
+#[cfg(not(feature = "amqp"))]
+fn consume_amqp_queue(_args: &Args) -> Result<(), Box<dyn Error>> {
+    Err("RabbitMQ input requires building with the `amqp` feature".into())
+}
+
+// There is no disk backed history to fall back to yet, so going over the --max-memory budget
+// aborts the run before the container runtime kills it, and nothing is saved
+fn check_memory_budget(
+    args: &Args,
+    engine: &Engine,
+    transactions: &[Transaction],
+    rows: usize,
+) -> Result<(), Box<dyn Error>> {
+    let Some(budget) = args.max_memory else {
+        return Ok(());
+    };
+    // The events are allocated for the whole input upfront
+    let used = engine.approximate_memory()
+        + transactions.len() * (std::mem::size_of::<Transaction>() + std::mem::size_of::<Event>());
+    if used > budget * 1024 * 1024 {
+        return Err(format!(
+            "Memory budget of {} MB exceeded after {} rows (about {} MB used by the input, the events and the engine state). Raise --max-memory or split the input.",
+            budget,
+            rows,
+            used / (1024 * 1024)
+        )
+        .into());
     }
     Ok(())
 }
 
-fn process_transactions(
-    transactions: &Vec<Transaction>,
-    clients: &mut HashMap<u16, Client>,
-) -> Result<(), String> {
-    let mut transactions_history: HashMap<u32, Transaction> = HashMap::new();
-    let mut ongoing_disputes: HashSet<u32> = HashSet::new();
-    for (csv_line, t) in transactions.iter().enumerate() {
-        // Get client of the transaction, or initialize if it doesn't exists
-        let client = clients.entry(t.client_id).or_default();
-
-        if !client.locked {
-            match t.category {
-                TransactionCategory::Deposit => {
-                    let amount = t.amount.expect(&format!("Incorrect csv row : {}. You should provide an amount for a deposit transaction", csv_line + 1));
-                    deposit(amount, client)?;
-                    transactions_history.insert(t.tx, t.to_owned());
-                }
-                TransactionCategory::Withdrawal => {
-                    let amount = t.amount.expect(&format!("Incorrect csv row : {}. You should provide an amount for a withdraw transaction", csv_line + 1));
-                    if withdraw(amount, client)? {
-                        transactions_history.insert(t.tx, t.to_owned());
-                    }
-                }
-                TransactionCategory::Dispute => {
-                    dispute(t.tx, &transactions_history, &mut ongoing_disputes, client)
-                }
-                TransactionCategory::Resolve => {
-                    resolve(t.tx, &transactions_history, &mut ongoing_disputes, client)
-                }
-                TransactionCategory::Chargeback => {
-                    charge_back(t.tx, &transactions_history, &mut ongoing_disputes, client)
-                }
+// Dispute flow rows referencing a transaction of another client, on stderr
+fn report_client_mismatches(events: &[Event]) {
+    for event in events {
+        let t = &event.transaction;
+        let fields = json!({
+            "row": event.row,
+            "type": t.category.as_str(),
+            "client": t.client_id,
+            "tx": t.tx,
+            "owner": event.routed_to,
+        });
+        if let Some(owner) = event.routed_to {
+            let message = format!(
+                "Row {} : {} of tx {} by client {} routed to client {}",
+                event.row,
+                t.category.as_str(),
+                t.tx,
+                t.client_id,
+                owner
+            );
+            logging::warn("client_mismatch", &message, fields);
+        } else if event.outcome == Outcome::Ignored("Transaction belongs to another client") {
+            let message = format!(
+                "Row {} : {} of tx {} by client {} rejected, the transaction belongs to another client",
+                event.row,
+                t.category.as_str(),
+                t.tx,
+                t.client_id
+            );
+            logging::warn("client_mismatch", &message, fields);
+        }
+    }
+}
+
+// A record per ignored transaction with its reason from -v, and per transaction from -vv
+fn report_events(events: &[Event]) {
+    for event in events {
+        let t = &event.transaction;
+        let fields = json!({
+            "row": event.row,
+            "type": t.category.as_str(),
+            "client": t.client_id,
+            "tx": t.tx,
+            "amount": t.amount,
+            "rule": event.rule,
+        });
+        let transaction = format!(
+            "Row {} : {} of tx {} by client {}",
+            event.row,
+            t.category.as_str(),
+            t.tx,
+            t.client_id
+        );
+        match event.outcome {
+            Outcome::Ignored(reason) => {
+                let mut fields = fields;
+                fields["reason"] = json!(reason);
+                let message = format!("{} ignored : {}", transaction, reason);
+                logging::detail("transaction_rejected", &message, fields)
             }
+            Outcome::Applied => logging::trace(
+                "transaction_applied",
+                &format!("{} applied", transaction),
+                fields,
+            ),
+            Outcome::Held => logging::trace(
+                "transaction_held",
+                &format!("{} held for review", transaction),
+                fields,
+            ),
+            Outcome::Deferred => logging::trace(
+                "dispute_deferred",
+                &format!("{} deferred until the funds are available", transaction),
+                fields,
+            ),
+        }
+    }
+}
+
+// The funds of each client should always add up, a client where they don't points at a bug
+// rather than at the input
+fn check_balances(engine: &Engine) {
+    for (client_id, client) in engine.clients() {
+        if (client.available + client.held - client.total).abs() > BALANCE_EPSILON {
+            logging::warn(
+                "invariant_warning",
+                &format!(
+                    "Client {} : available {} + held {} != total {}",
+                    client_id, client.available, client.held, client.total
+                ),
+                json!({
+                    "client": client_id,
+                    "available": client.available,
+                    "held": client.held,
+                    "total": client.total,
+                }),
+            );
         }
     }
+}
+
+// Unlike the invariant, a failed assertion points at the input or at the system it was
+// migrated from
+fn report_balance_mismatches(engine: &Engine) {
+    for mismatch in engine.balance_mismatches() {
+        logging::warn(
+            "balance_mismatch",
+            &format!(
+                "Row {} : client {} has available {} total {}, expected available {}{}",
+                mismatch.row,
+                mismatch.client_id,
+                mismatch.available,
+                mismatch.total,
+                mismatch.expected_available,
+                mismatch
+                    .expected_total
+                    .map(|total| format!(" total {}", total))
+                    .unwrap_or_default()
+            ),
+            json!({
+                "row": mismatch.row,
+                "client": mismatch.client_id,
+                "available": mismatch.available,
+                "total": mismatch.total,
+                "expected_available": mismatch.expected_available,
+                "expected_total": mismatch.expected_total,
+            }),
+        );
+    }
+}
+
+// Right to erasure : the client leaves the pseudonym map, the accounts file and the state,
+// its funds folded into the anonymized account of the config. The files linking the client
+// to a person are rewritten first, so a failure never leaves them pointing at an erased
+// state. Each erasure is recorded by the store, on top of the log : in erasures.csv of the
+// state directory, encrypted like the other files, or in the erasures table of Postgres.
+fn erase_client(args: &Args) -> Result<(), Box<dyn Error>> {
+    let (Some(client_id), Some(_)) = (&args.input, &args.state) else {
+        return Err("Usage : payments-engine erase-client --state <directory> --config <file> [--pseudonym-map <file>] [--accounts <file>] <client id>".into());
+    };
+    let client_id: u16 = client_id
+        .parse()
+        .map_err(|e| format!("Invalid client id : {}", e))?;
+    let store = state_store(args)?.ok_or("erase-client needs the persisted state")?;
+    let mut engine = load_engine_from(args, Some(store.as_ref()))?;
+    let erasure = engine.erase_client(client_id)?;
+    let mut pseudonyms = 0;
+    if let Some(path) = &args.pseudonym_map {
+        let mut mapping = pseudonym::read_mapping(File::open(path)?)?;
+        let before = mapping.len();
+        mapping.retain(|(_, mapped)| *mapped != client_id);
+        pseudonyms = before - mapping.len();
+        replace_file(path, |file| pseudonym::write_mapping(file, &mapping))?;
+    }
+    if let Some(path) = &args.accounts {
+        replace_file(path, |file| {
+            accounts::write_accounts(file, engine.accounts())
+        })?;
+    }
+    store.save(&engine)?;
+    store.record_erasure(&ErasureRecord {
+        erased_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        client: client_id,
+        history: erasure.history,
+        disputes: erasure.disputes,
+        pseudonyms,
+        folded: erasure.folded,
+    })?;
+    logging::info(
+        "client_erased",
+        &format!(
+            "Client {} erased : {} history entries, {} disputes and {} pseudonyms removed, {:.4} folded into the anonymized account",
+            client_id, erasure.history, erasure.disputes, pseudonyms, erasure.folded
+        ),
+        json!({
+            "client": client_id,
+            "history": erasure.history,
+            "disputes": erasure.disputes,
+            "pseudonyms": pseudonyms,
+            "folded": erasure.folded,
+        }),
+    );
+    Ok(())
+}
+
+// Writes the file next to its path first and renames it over, so a failure leaves the
+// previous content whole
+fn replace_file(
+    path: &str,
+    write: impl FnOnce(File) -> Result<(), csv::Error>,
+) -> Result<(), Box<dyn Error>> {
+    let temporary = format!("{}.tmp", path);
+    write(File::create(&temporary)?)?;
+    Ok(fs::rename(temporary, path)?)
+}
 
+// Prints the newly applied transactions as csv, and a summary on stderr
+fn backfill(args: &Args) -> Result<(), Box<dyn Error>> {
+    let store = state_store(args)?
+        .ok_or("backfill needs the persisted state, please provide --state <directory>")?;
+    let transactions = get_transactions_from_args(args)?;
+    let mut engine = load_engine_from(args, Some(store.as_ref()))?;
+    let report = state::backfill(&mut engine, &transactions)?;
+    store.save(&engine)?;
+    write_review_queue(args, &engine)?;
+
+    let mut wtr = csv::Writer::from_writer(std::io::stdout().lock());
+    for event in report.applied() {
+        wtr.serialize(&event.transaction)?;
+    }
+    wtr.flush()?;
+    let applied = report.applied().count();
+    let ignored = report.events.len() - applied;
+    logging::info(
+        "backfill_finished",
+        &format!(
+            "{} transactions newly applied, {} ignored by the rules, {} already seen",
+            applied, ignored, report.skipped
+        ),
+        json!({ "applied": applied, "ignored": ignored, "skipped": report.skipped }),
+    );
     Ok(())
 }
 
-fn deposit(amount: f64, client: &mut Client) -> Result<(), &str> {
-    if amount < f64::MIN_POSITIVE {
-        return Err("Cannot deposit a negative amount");
+// Checks that the file is the output signed by the engine with the same key
+fn verify(args: &Args) -> Result<(), Box<dyn Error>> {
+    let (Some(input), Some(key), Some(signature)) = (&args.input, &args.sign_key, &args.signature)
+    else {
+        return Err("Usage : payments-engine verify --sign-key <key file> --signature <signature file> <file>".into());
+    };
+    let key = signature::read_key(key)?;
+    if !signature::verify(&key, &fs::read(input)?, &fs::read_to_string(signature)?) {
+        return Err(format!("The signature doesn't match {}", input).into());
     }
-    client.available += amount;
-    client.total += amount;
-    if client.total > f64::MAX {
-        return Err("You are getting way too rich");
+    println!("Signature OK");
+    Ok(())
+}
+
+// Checks that processing the input file keeps the ledger balanced
+fn verify_ledger(args: &Args) -> Result<(), Box<dyn Error>> {
+    if args.input.is_none() {
+        return Err("Usage : payments-engine verify-ledger <file path>".into());
     }
+    let transactions = get_transactions_from_args(args)?;
+    let report = ledger::verify(&transactions)?;
+    println!(
+        "{} transactions, {} ledger entries, house account {:.4}, balanced",
+        transactions.len(),
+        report.entries.len(),
+        report.house
+    );
     Ok(())
 }
 
-fn withdraw(amount: f64, client: &mut Client) -> Result<bool, &str> {
-    if amount < f64::MIN_POSITIVE {
-        return Err("Cannot withdraw a negative amount");
-    }
-    if amount < client.available {
-        client.available -= amount;
-        client.total -= amount;
-        return Ok(true);
-    }
-    Ok(false)
-}
-
-fn dispute(
-    transaction_disputed_id: u32,
-    transactions_history: &HashMap<u32, Transaction>,
-    ongoing_disputes: &mut HashSet<u32>,
-    client: &mut Client,
-) {
-    // Can't dispute twice the same transaction
-    if !ongoing_disputes.contains(&transaction_disputed_id) {
-        // Can't dispute a transaction that doesn't exists
-        if let Some(disputed) = transactions_history.get(&transaction_disputed_id) {
-            match disputed.category {
-                TransactionCategory::Deposit => {
-                    let amount = disputed.amount.expect(&format!(
-                        "The amount of the disputed transaction number {} was not provided",
-                        disputed.tx
-                    ));
-                    client.available -= amount;
-                    client.held += amount;
-                    ongoing_disputes.insert(disputed.tx);
-                }
-                _ => (), // Not sure how to handle dispute on the other kind of transactions
-            }
+// Random seed unless one is given, printed first so a failure can be replayed
+fn simulate(args: &Args) -> Result<(), Box<dyn Error>> {
+    let seed = match args.seed {
+        Some(seed) => seed,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+    };
+    let transactions = args.transactions.unwrap_or(DEFAULT_SIMULATED_TRANSACTIONS);
+    println!("Seed {}", seed);
+    let report = simulation::simulate(seed, transactions)?;
+    println!(
+        "{} transactions, {} applied, {} ignored, invariants hold",
+        transactions, report.applied, report.ignored
+    );
+    Ok(())
+}
+
+// Differential test of the engines against the reference implementation of the rules,
+// on a file or on a seeded simulation workload
+fn compare(args: &Args) -> Result<(), Box<dyn Error>> {
+    let transactions = match (&args.input, args.seed) {
+        (Some(_), _) => get_transactions_from_args(args)?,
+        (None, Some(seed)) => simulation::workload(
+            seed,
+            args.transactions.unwrap_or(DEFAULT_SIMULATED_TRANSACTIONS),
+        ),
+        (None, None) => return Err("Please provide a file, or a simulation --seed".into()),
+    };
+    let differences = reference::compare(&transactions);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if !differences.is_empty() {
+        return Err(format!("{} differences with the reference", differences.len()).into());
+    }
+    println!("{} transactions, no difference", transactions.len());
+    Ok(())
+}
+
+// Processes the input under the --config (the defaults when not given) and under the --variant
+// config, and writes the final balances of each client under both. The state isn't loaded,
+// both runs start from empty balances, and nothing is saved.
+fn whatif(args: &Args) -> Result<(), Box<dyn Error>> {
+    let Some(variant_config) = &args.variant else {
+        return Err("Please provide the --variant config to compare with".into());
+    };
+    let transactions = get_transactions_from_args(args)?;
+    let engine = |config: Option<&str>| -> Result<Engine, Box<dyn Error>> {
+        let mut engine = Engine::default();
+        if let Some(path) = config {
+            set_config_file(&mut engine, path)?;
+        }
+        if let Some(path) = &args.accounts {
+            engine.set_accounts(accounts::read_accounts(File::open(path)?)?);
         }
+        Ok(engine)
+    };
+    let mut base = engine(args.config.as_deref())?;
+    let mut variant = engine(Some(variant_config))?;
+    let comparisons = whatif::compare(&mut base, &mut variant, &transactions)?;
+    let changed = comparisons.iter().filter(|c| c.differs()).count();
+    logging::info(
+        "whatif_compared",
+        &format!(
+            "{} clients, {} with different balances",
+            comparisons.len(),
+            changed
+        ),
+        json!({ "clients": comparisons.len(), "changed": changed }),
+    );
+    match &args.output {
+        Some(path) => whatif::write_comparison(File::create(path)?, &comparisons)?,
+        None => whatif::write_comparison(std::io::stdout().lock(), &comparisons)?,
     }
+    Ok(())
 }
 
-fn resolve(
-    transaction_resolved_id: u32,
-    transactions_history: &HashMap<u32, Transaction>,
-    ongoing_disputes: &mut HashSet<u32>,
-    client: &mut Client,
-) {
-    // Can't resolve a transaction that isn't under dispute
-    if ongoing_disputes.contains(&transaction_resolved_id) {
-        if let Some(resolved) = transactions_history.get(&transaction_resolved_id) {
-            match resolved.category {
-                TransactionCategory::Deposit => {
-                    let amount = resolved.amount.expect(&format!(
-                        "The amount of the resolved transaction number {} was not provided",
-                        resolved.tx
-                    ));
-                    client.available += amount;
-                    client.held -= amount;
-                    ongoing_disputes.remove(&resolved.tx);
-                }
-                _ => (),
+// Reports on the persisted state, after processing the input file if one is provided.
+// Nothing is saved.
+fn report(kind: &str, args: &Args) -> Result<(), Box<dyn Error>> {
+    if args.pseudonymize_key.is_some() {
+        return Err("Reports can't be pseudonymized yet".into());
+    }
+    let mut engine = load_engine(args)?;
+    engine.load_history()?;
+    if args.input.is_some() {
+        for t in &get_transactions_from_args(args)? {
+            engine.process(t)?;
+        }
+    }
+    // Reports going to an object store are uploaded once written
+    let mut content = Vec::new();
+    let mut writer: Box<dyn Write> = match args.output.as_deref() {
+        None => Box::new(std::io::stdout().lock()),
+        Some(output) if is_object_url(output) => Box::new(&mut content),
+        Some(output) => return Err(format!("Unknown output : {}", output).into()),
+    };
+    let stdout = &mut writer;
+    // Reference time of the aging and exposure reports
+    let now = match args.as_of {
+        Some(as_of) => as_of,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    match kind {
+        "disputes" => reports::write_disputes_report(stdout, &engine)?,
+        "locks" => reports::write_locks_report(stdout, &engine)?,
+        "aging" => reports::write_aging_report(stdout, &engine, now)?,
+        "exposure" => reports::write_exposure_report(stdout, &engine, now)?,
+        "top" => reports::write_top_report(
+            stdout,
+            &engine,
+            args.by.as_deref().unwrap_or("held"),
+            args.top.unwrap_or(DEFAULT_TOP_CLIENTS),
+        )?,
+        "anomalies" => reports::write_anomalies_report(stdout, &engine)?,
+        "risk" => reports::write_risk_report(stdout, &engine, &risk::DefaultRiskModel, now)?,
+        "stats" => {
+            let stats = reports::stats(&engine, args.top.unwrap_or(DEFAULT_TOP_CLIENTS));
+            match args.format.as_deref() {
+                None | Some("text") => stats.write_text(stdout)?,
+                Some("json") => stats.write_json(stdout)?,
+                Some(format) => return Err(format!("Unknown format : {}", format).into()),
             }
         }
+        other => return Err(format!("Unknown report : {}", other).into()),
+    }
+    drop(writer);
+    if let Some(output) = args.output.as_deref() {
+        put_object(output, content)?;
     }
+    Ok(())
 }
 
-fn charge_back(
-    transaction_charged_back_id: u32,
-    transactions_history: &HashMap<u32, Transaction>,
-    ongoing_disputes: &mut HashSet<u32>,
-    client: &mut Client,
-) {
-    if ongoing_disputes.contains(&transaction_charged_back_id) {
-        if let Some(charged_back) = transactions_history.get(&transaction_charged_back_id) {
-            match charged_back.category {
-                TransactionCategory::Deposit => {
-                    let amount = charged_back.amount.expect(&format!(
-                        "The amount of the charged back transaction number {} was not provided",
-                        charged_back.tx
-                    ));
-                    client.held -= amount;
-                    client.total -= amount;
-                    client.locked = true;
-                    ongoing_disputes.remove(&charged_back.tx);
-                }
-                _ => (),
+// Raw rows of a client for a minimal reproduction, in the layout of the [input] config
+fn filter(args: &Args, output: Option<String>) -> Result<(), Box<dyn Error>> {
+    let (Some(client), Some(input), Some(output)) = (args.client, &args.input, output) else {
+        return Err("Usage : payments-engine filter --client <id> [--category <type>] <file path> <output file>".into());
+    };
+    let mut writer = std::io::BufWriter::new(File::create(&output)?);
+    let extracted = extract::extract_rows(
+        &fs::read(input)?,
+        &config_file(args)?.input,
+        client,
+        args.category.as_deref(),
+        &mut writer,
+    )?;
+    writer.flush()?;
+    logging::info(
+        "rows_extracted",
+        &format!("{} rows written to {}", extracted, output),
+        json!({ "rows": extracted, "output": output }),
+    );
+    Ok(())
+}
+
+fn inspect_file(args: &Args) -> Result<(), Box<dyn Error>> {
+    let Some(input) = &args.input else {
+        return Err("Usage : payments-engine inspect <file path>".into());
+    };
+    let file = File::open(input)?;
+    let size = file.metadata()?.len();
+    let inspection = inspect::inspect(std::io::BufReader::new(file), size)?;
+    Ok(inspection.write_text(&mut std::io::stdout().lock())?)
+}
+
+// Balances of a client at a point in time, rebuilt from the persisted history and disputes
+fn balance(args: &Args) -> Result<(), Box<dyn Error>> {
+    let (Some(client_id), Some(at), Some(_)) = (args.client, args.as_of, &args.state) else {
+        return Err(
+            "Usage : payments-engine balance --client <id> --at <time> --state <directory>".into(),
+        );
+    };
+    let mut engine = load_engine(args)?;
+    engine.load_history()?;
+    let reports::PointInTime { client, undated } = reports::balance_at(&engine, client_id, at);
+    if undated > 0 {
+        logging::warn(
+            "undated_history",
+            &format!(
+                "{} history entries or disputes of client {} have no timestamp and were left out",
+                undated, client_id
+            ),
+            json!({ "client": client_id, "undated": undated }),
+        );
+    }
+    let writer = &mut std::io::stdout().lock();
+    writeln!(writer, "client,available,held,total,locked")?;
+    writeln!(
+        writer,
+        "{},{:.4},{:.4},{:.4},{}",
+        client_id, client.available, client.held, client.total, client.locked
+    )?;
+    Ok(())
+}
+
+// Lookups for support staff : the state is loaded but never saved, and the prompt only has
+// the commands reading it
+fn query(args: &Args) -> Result<(), Box<dyn Error>> {
+    if args.state.is_none() {
+        return Err("Usage : payments-engine query --state <directory>".into());
+    }
+    let mut engine = load_engine(args)?;
+    engine.load_history()?;
+    Ok(repl::query(
+        &engine,
+        std::io::stdin().lock(),
+        &mut std::io::stdout(),
+    )?)
+}
+
+// Month-end statements of the clients active in the period, client-<id>.csv and
+// client-<id>.txt in the output directory
+fn write_statements(args: &Args) -> Result<(), Box<dyn Error>> {
+    let (Some(period), Some(_), Some(output)) = (&args.period, &args.state, &args.output) else {
+        return Err("Usage : payments-engine statements --period 2024-03 --state <directory> --output <directory>".into());
+    };
+    let (start, end) = dates::parse_month(period)?;
+    let mut engine = load_engine(args)?;
+    engine.load_history()?;
+    fs::create_dir_all(output)?;
+    let statements = statements::for_period(&engine, start, end);
+    for statement in &statements {
+        let path = Path::new(output).join(format!("client-{}", statement.client_id));
+        statement.write_csv(File::create(path.with_extension("csv"))?)?;
+        let text = &mut File::create(path.with_extension("txt"))?;
+        statement.write_text(text, period, args.display_currency.as_deref())?;
+    }
+    let undated: usize = statements.iter().map(|s| s.undated).sum();
+    if undated > 0 {
+        logging::warn(
+            "undated_history",
+            &format!(
+                "{} history entries or disputes have no timestamp and were left out of the statements",
+                undated
+            ),
+            json!({ "undated": undated }),
+        );
+    }
+    logging::info(
+        "statements_written",
+        &format!("{} statements written to {}", statements.len(), output),
+        json!({ "statements": statements.len(), "period": period, "output": output }),
+    );
+    Ok(())
+}
+
+// For the settings read before the engine is loaded : the layout of the csv input and the
+// grace of the early disputes
+fn config_file(args: &Args) -> Result<Config, Box<dyn Error>> {
+    match &args.config {
+        Some(path) => config::load_config(path),
+        None => Ok(Config::default()),
+    }
+}
+
+// Persisted state if --state is provided, with the rules of the --config file and the
+// --decisions of the analysts applied
+fn load_engine(args: &Args) -> Result<Engine, Box<dyn Error>> {
+    load_engine_from(args, state_store(args)?.as_deref())
+}
+
+#[cfg(feature = "scripting")]
+fn set_script(
+    engine: &mut Engine,
+    settings: &config::ScriptSettings,
+) -> Result<(), Box<dyn Error>> {
+    engine.set_script(payments_engine::scripting::Script::load(settings)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "scripting"))]
+fn set_script(
+    _engine: &mut Engine,
+    _settings: &config::ScriptSettings,
+) -> Result<(), Box<dyn Error>> {
+    Err("A rule script requires building with the `scripting` feature".into())
+}
+
+#[cfg(feature = "plugins")]
+fn set_plugin(
+    engine: &mut Engine,
+    settings: &config::PluginSettings,
+) -> Result<(), Box<dyn Error>> {
+    engine.set_plugin(payments_engine::plugins::Plugin::load(settings)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "plugins"))]
+fn set_plugin(
+    _engine: &mut Engine,
+    _settings: &config::PluginSettings,
+) -> Result<(), Box<dyn Error>> {
+    Err("A validation plugin requires building with the `plugins` feature".into())
+}
+
+fn set_config_file(engine: &mut Engine, path: &str) -> Result<(), Box<dyn Error>> {
+    let config = config::load_config(path)?;
+    if let Some(settings) = &config.scripting {
+        set_script(engine, settings)?;
+    }
+    if let Some(settings) = &config.plugin {
+        set_plugin(engine, settings)?;
+    }
+    engine.set_config(config);
+    Ok(())
+}
+
+// A store keeps what it loaded to check it on save, so the same store has to save the engine
+fn load_engine_from(args: &Args, store: Option<&dyn StateStore>) -> Result<Engine, Box<dyn Error>> {
+    let mut engine = match store {
+        Some(store) => store.load()?,
+        None => Engine::default(),
+    };
+    if let Some(path) = &args.initial_state {
+        if store.is_some() {
+            return Err("--initial-state opens balances without a --state, not both".into());
+        }
+        state::set_initial_state(&mut engine, File::open(path)?)?;
+        let held = engine.clients().values().filter(|c| c.held > 0.0).count();
+        if held > 0 {
+            logging::warn(
+                "initial_held_funds",
+                &format!(
+                    "{} clients of the initial state have held funds, which no dispute of this run can release",
+                    held
+                ),
+                json!({ "clients": held }),
+            );
+        }
+    }
+    if let Some(path) = &args.config {
+        set_config_file(&mut engine, path)?;
+    }
+    if let Some(path) = &args.accounts {
+        engine.set_accounts(accounts::read_accounts(File::open(path)?)?);
+    }
+    if let Some(path) = &args.decisions {
+        for decision in review::read_decisions(File::open(path)?)? {
+            if let Err(e) = engine.decide(decision.tx, decision.decision) {
+                logging::warn(
+                    "decision_skipped",
+                    &format!("Decision on tx {} skipped : {}", decision.tx, e),
+                    json!({ "tx": decision.tx, "reason": e }),
+                );
             }
         }
     }
+    Ok(engine)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    #[should_panic]
-    fn invalid_input_amount_type() {
-        get_transactions_from_file("src/testSamples/invalidAmountType.csv").unwrap();
+// --state is a directory, or a postgres:// database with the postgres feature
+fn state_store(args: &Args) -> Result<Option<Box<dyn StateStore>>, Box<dyn Error>> {
+    let Some(state) = &args.state else {
+        return Ok(None);
+    };
+    if state.starts_with("postgres://") || state.starts_with("postgresql://") {
+        if state_key(args)?.is_some() {
+            return Err(
+                "A Postgres state isn't encrypted by the engine, please drop the state key".into(),
+            );
+        }
+        return Ok(Some(postgres_store(state)?));
     }
+    Ok(Some(Box::new(DirectoryStore {
+        directory: state.clone(),
+        key: state_key(args)?,
+    })))
+}
+
+#[cfg(feature = "postgres")]
+fn postgres_store(url: &str) -> Result<Box<dyn StateStore>, Box<dyn Error>> {
+    Ok(Box::new(payments_engine::postgres_state::connect(url)?))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn postgres_store(_url: &str) -> Result<Box<dyn StateStore>, Box<dyn Error>> {
+    Err("Postgres state requires building with the `postgres` feature".into())
+}
 
-    #[test]
-    #[should_panic]
-    fn invalid_input_client_id() {
-        get_transactions_from_file("src/testSamples/invalidClientID.csv").unwrap();
+// The state is encrypted when a key is given, with --state-key <key file> or in the
+// PAYMENTS_ENGINE_STATE_KEY environment variable
+fn state_key(args: &Args) -> Result<Option<StateKey>, Box<dyn Error>> {
+    if let Some(path) = &args.state_key {
+        return Ok(Some(StateKey::new(&signature::read_key(path)?)));
     }
+    Ok(env::var("PAYMENTS_ENGINE_STATE_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .map(|key| StateKey::new(key.as_bytes())))
+}
 
-    #[test]
-    #[should_panic]
-    fn invalid_input_transaction_id() {
-        get_transactions_from_file("src/testSamples/invalidTransactionID.csv").unwrap();
+// Usage : payments-engine [options] <file path | tcp://host:port | redis://host:port/stream | amqp://host:port>
+//         payments-engine repl
+//         payments-engine backfill --state <directory> <file path>
+//         payments-engine erase-client --state <directory> --config <file> [--pseudonym-map <file>] [--accounts <file>] <client id>
+//         payments-engine verify --sign-key <key file> --signature <signature file> <file>
+//         payments-engine verify-ledger <file path> (replays the file, checking the ledger balances)
+//         payments-engine simulate [--seed <n>] [--transactions <n>]
+//         payments-engine compare <file path> | --seed <n> [--transactions <n>]
+//         payments-engine whatif [--config <file>] --variant <file> <file path>
+//         payments-engine report disputes|locks|aging|exposure|risk|anomalies|top|stats [--state <directory>] [<file path>]
+//         payments-engine filter --client <id> [--category <type>] <file path> <output file>
+//         payments-engine inspect <file path> (layout of a partner file and the config reading it)
+//         payments-engine balance --client <id> --at <time> --state <directory>
+//         payments-engine query --state <directory> (read-only prompt over the persisted state)
+//         payments-engine statements --period 2024-03 --state <directory> --output <directory>
+//   --input-format csv|protobuf
+//   s3://<bucket>/<key>, gs:// or az:// input files are read by ranges, with the object-store feature
+//   redis://host:port/<stream>?group=<group>&consumer=<name>&events=<stream> (consumes the stream
+//     in a consumer group, the events published to the events stream, needs the redis feature)
+//   amqp://host:port/<vhost>?queue=<queue> (consumes the json messages of a RabbitMQ queue until
+//     it is empty, needs the amqp feature)
+//   --io-backend std|uring (how input files are read, uring needs Linux and the uring feature)
+//   --output arrow://<directory>|sqlite://<database file>|s3://<bucket>/<key> (also gs:// and
+//     az://, for the stdout output and the reports, needs the object-store feature)
+//   --format csv|table (only for the default stdout output), text|json for report stats
+//   --no-color
+//   --display-currency <code> (amounts of the table and of the text statements with the currency
+//     symbol and grouping, e.g. $1,234.5000 for USD)
+//   --diagnostics text|json (how a row failing to parse is reported on stderr)
+//   -q|-v|-vv (quiet : only the output and the errors, default : warnings and a summary of the
+//     run on stderr, -v : every rejected transaction with its reason, -vv : every transaction)
+//   --log-format text|json (json : one record per line on stderr, with the files opened, the
+//     rejected transactions and a summary of the run on top of the warnings)
+//   --accounts <file> (client,account csv grouping clients onto joint accounts)
+//   --report-by account|member|wallet (joint accounts output once, or once per member, or
+//     a row per wallet of each client)
+//   --config <file> (toml settings : client tiers and rules rejecting, flagging or holding transactions)
+//   --decisions <file> (tx,decision csv of approve or deny on held transactions, applied first)
+//   --review-queue <file> (csv of the held transactions waiting for a decision)
+//   --sign-key <key file> --signature <file> (writes the HMAC-SHA256 of the stdout output)
+//   --pseudonymize-key <key file> [--pseudonym-map <file>] (client ids of the stdout output
+//     replaced by keyed pseudonyms, the map file giving back the client of each pseudonym)
+//   --state <directory> (engine state loaded before and saved after processing)
+//     or --state postgres://<user>:<password>@<host>/<database> with the postgres feature
+//   --initial-state <file> (opening balances from the csv output of a previous run, without
+//     its history)
+//   --state-key <key file> (encrypts the state, PAYMENTS_ENGINE_STATE_KEY can hold the key instead)
+//   --max-memory <MB> (aborts when the input, events and engine state get larger, checked every
+//     4096 rows)
+//   --sort-by-timestamp (processes the input in timestamp order, every row needs a timestamp)
+//   --parse-threads <n>|auto (csv input read in memory and its rows parsed on n threads, or
+//     one per CPU)
+//   --reorder-window <rows> (applies the rows in the order of their seq column, buffering up to
+//     that many rows, and reports the gaps in the sequence)
+//   --dry-run (prints the changes the input would make to the state, saves nothing)
+//   --as-of <unix seconds | 2024-03-31T23:59:59Z> (reference time of the reports, now by
+//     default), or --at
+//   --client <id> (client of the balance or of the filter)
+//   --category <type> (transaction type kept by the filter)
+//   --period <year-month> (month of the statements, in UTC)
+//   --top <n> (number of clients listed by report stats and report top, 10 by default), or --k
+//   --by held|total|available (metric of report top, held by default)
+//   --extended-output (activity columns of each client in the csv stdout output)
+fn parse_args(mut args: impl Iterator<Item = String>) -> Args {
+    let mut input_format = None;
+    let mut output = None;
+    let mut format = None;
+    let mut no_color = false;
+    let mut state = None;
+    let mut state_key = None;
+    let mut initial_state = None;
+    let mut dry_run = false;
+    let mut as_of = None;
+    let mut top = None;
+    let mut by = None;
+    let mut config = None;
+    let mut variant = None;
+    let mut decisions = None;
+    let mut review_queue = None;
+    let mut sign_key = None;
+    let mut signature = None;
+    let mut pseudonymize_key = None;
+    let mut pseudonym_map = None;
+    let mut seed = None;
+    let mut transactions = None;
+    let mut diagnostics = None;
+    let mut accounts = None;
+    let mut report_by = None;
+    let mut io_backend = None;
+    let mut parse_threads = None;
+    let mut max_memory = None;
+    let mut log_format = None;
+    let mut client = None;
+    let mut period = None;
+    let mut display_currency = None;
+    let mut category = None;
+    let mut sort_by_timestamp = false;
+    let mut extended_output = false;
+    let mut reorder_window = None;
+    let mut verbosity = Verbosity::Normal;
+    let mut input = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input-format" => input_format = args.next(),
+            "--output" => output = args.next(),
+            "--format" => format = args.next(),
+            "--no-color" => no_color = true,
+            "--config" => config = args.next(),
+            "--by" => by = args.next(),
+            "--variant" => variant = args.next(),
+            "--diagnostics" => diagnostics = args.next(),
+            "--accounts" => accounts = args.next(),
+            "--report-by" => report_by = args.next(),
+            "--io-backend" => io_backend = args.next(),
+            "--log-format" => log_format = args.next(),
+            "--period" => period = args.next(),
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+            "-vv" => verbosity = Verbosity::Trace,
+            "--display-currency" => display_currency = args.next(),
+            "--category" => category = args.next(),
+            "--decisions" => decisions = args.next(),
+            "--review-queue" => review_queue = args.next(),
+            "--sign-key" => sign_key = args.next(),
+            "--signature" => signature = args.next(),
+            "--pseudonymize-key" => pseudonymize_key = args.next(),
+            "--pseudonym-map" => pseudonym_map = args.next(),
+            "--seed" => {
+                seed = Some(
+                    args.next()
+                        .and_then(|seed| seed.parse().ok())
+                        .expect("--seed expects a number"),
+                )
+            }
+            "--transactions" => {
+                transactions = Some(
+                    args.next()
+                        .and_then(|transactions| transactions.parse().ok())
+                        .expect("--transactions expects a number of transactions"),
+                )
+            }
+            "--state" => state = args.next(),
+            "--state-key" => state_key = args.next(),
+            "--initial-state" => initial_state = args.next(),
+            "--dry-run" => dry_run = true,
+            "--sort-by-timestamp" => sort_by_timestamp = true,
+            "--extended-output" => extended_output = true,
+            "--as-of" | "--at" => {
+                as_of = Some(
+                    args.next()
+                        .and_then(|as_of| dates::parse_time(&as_of).ok())
+                        .expect("--as-of expects a unix time in seconds or a UTC date time"),
+                )
+            }
+            "--client" => {
+                client = Some(
+                    args.next()
+                        .and_then(|client| client.parse().ok())
+                        .expect("--client expects a client id"),
+                )
+            }
+            "--max-memory" => {
+                max_memory = Some(
+                    args.next()
+                        .and_then(|max_memory| max_memory.parse().ok())
+                        .expect("--max-memory expects a number of MB"),
+                )
+            }
+            "--parse-threads" => {
+                parse_threads = Some(
+                    args.next()
+                        .and_then(|threads| match threads.as_str() {
+                            // One per CPU, as far as the system can tell
+                            "auto" => std::thread::available_parallelism().ok().map(usize::from),
+                            _ => threads.parse().ok(),
+                        })
+                        .expect("--parse-threads expects a number of threads or auto"),
+                )
+            }
+            "--reorder-window" => {
+                reorder_window = Some(
+                    args.next()
+                        .and_then(|window| window.parse().ok())
+                        .expect("--reorder-window expects a number of rows"),
+                )
+            }
+            "--top" | "--k" => {
+                top = Some(
+                    args.next()
+                        .and_then(|top| top.parse().ok())
+                        .expect("--top expects a number of clients"),
+                )
+            }
+            _ => input = Some(arg),
+        }
     }
+    // Applied as soon as the arguments are read, so the whole run logs in the same format
+    logging::set_json(match log_format.as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => panic!("--log-format expects text or json, not {}", other),
+    });
+    logging::set_verbosity(verbosity);
+    Args {
+        input,
+        input_format,
+        output,
+        format,
+        no_color,
+        state,
+        state_key,
+        initial_state,
+        dry_run,
+        as_of,
+        top,
+        by,
+        config,
+        variant,
+        decisions,
+        review_queue,
+        sign_key,
+        signature,
+        pseudonymize_key,
+        pseudonym_map,
+        seed,
+        transactions,
+        diagnostics,
+        accounts,
+        report_by,
+        io_backend,
+        parse_threads,
+        max_memory,
+        client,
+        period,
+        display_currency,
+        category,
+        sort_by_timestamp,
+        extended_output,
+        reorder_window,
+    }
+}
 
-    #[test]
-    #[should_panic]
-    fn too_rich_client() {
-        let transactions = get_transactions_from_file("src/testSamples/tooRichClient.csv").unwrap();
-        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+fn write_review_queue(args: &Args, engine: &Engine) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &args.review_queue {
+        review::write_review_queue(File::create(path)?, engine)?;
     }
+    Ok(())
+}
 
-    #[test]
-    #[should_panic]
-    fn invalid_input_unprovided_deposit_amount() {
-        let transactions =
-            get_transactions_from_file("src/testSamples/unprovidedAmount.csv").unwrap();
-        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+// The io_uring backend needs Linux and the `uring` feature, std I/O is used otherwise
+fn input_reader(args: &Args, file: File) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    match args.io_backend.as_deref() {
+        None | Some("std") => Ok(Box::new(file)),
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        Some("uring") => Ok(Box::new(payments_engine::uring_input::UringReader::new(
+            file,
+        )?)),
+        #[cfg(not(all(feature = "uring", target_os = "linux")))]
+        Some("uring") => {
+            logging::warn(
+                "io_backend_unavailable",
+                "io_uring isn't available in this build, reading with std I/O",
+                json!({ "io_backend": "uring" }),
+            );
+            Ok(Box::new(file))
+        }
+        Some(backend) => Err(format!("Unknown io backend : {}", backend).into()),
     }
+}
 
-    #[test]
-    #[should_panic]
-    fn invalid_input_negative_deposit() {
-        let transactions =
-            get_transactions_from_file("src/testSamples/negativeDeposit.csv").unwrap();
-        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+fn get_transactions_from_args(args: &Args) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let mut transactions = read_input(args)?;
+    if args.sort_by_timestamp {
+        payments_engine::sort_by_timestamp(&mut transactions)?;
     }
+    let (transactions, _) = sequence_input(args, transactions)?;
+    Ok(transactions)
+}
 
-    #[test]
-    #[should_panic]
-    fn invalid_input_negative_withdraw() {
-        let transactions =
-            get_transactions_from_file("src/testSamples/negativeWithdraw.csv").unwrap();
-        process_transactions(&transactions, &mut HashMap::new()).unwrap();
+// Shared by the files and the stream inputs, along with the index in the input of each row so
+// that the streams keep the id of each entry with its row
+fn sequence_input(
+    args: &Args,
+    mut transactions: Vec<Transaction>,
+) -> Result<(Vec<Transaction>, Vec<usize>), Box<dyn Error>> {
+    let mut order: Vec<usize> = (0..transactions.len()).collect();
+    if let Some(window) = args.reorder_window {
+        let sequenced = sequence::reorder(transactions, window)?;
+        for (first, last) in &sequenced.gaps {
+            logging::warn(
+                "sequence_gap",
+                &format!("Sequence numbers {} to {} are missing", first, last),
+                json!({ "first": first, "last": last }),
+            );
+        }
+        for seq in &sequenced.late {
+            logging::warn(
+                "sequence_late",
+                &format!(
+                    "Sequence number {} came too late for the reordering window, applied out of order",
+                    seq
+                ),
+                json!({ "seq": seq, "window": window }),
+            );
+        }
+        transactions = sequenced.transactions;
+        order = sequenced.order;
     }
+    let (parking, parked) = sequence::parking_order(&transactions, &config_file(args)?.disputes);
+    if parked > 0 {
+        logging::info(
+            "disputes_parked",
+            &format!(
+                "{} dispute flow rows came before their transaction and were applied after it",
+                parked
+            ),
+            json!({ "rows": parked }),
+        );
+        sequence::permute(&mut transactions, &parking);
+        order = parking.iter().map(|index| order[*index]).collect();
+    }
+    Ok((transactions, order))
+}
 
-    #[test]
-    fn provided_example() {
-        let transactions =
-            get_transactions_from_file("src/testSamples/providedExample.csv").unwrap();
-        let mut clients: HashMap<u16, Client> = HashMap::new();
-        process_transactions(&transactions, &mut clients).unwrap();
+// Files ending in .pb and tcp:// sockets are read as length-delimited protobuf streams
+fn read_input(args: &Args) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let input = args
+        .input
+        .as_ref()
+        .ok_or("Please provide the input file path as the last argument")?;
+    if let Some(address) = input.strip_prefix("tcp://") {
+        return protobuf::get_transactions_from_socket(address);
+    }
+    let input_format = args.input_format.clone().unwrap_or_else(|| {
+        if input.ends_with(".pb") {
+            "protobuf".to_string()
+        } else {
+            "csv".to_string()
+        }
+    });
+    let (reader, size) = if is_object_url(input) {
+        open_object(input)?
+    } else {
+        let file = File::open(input)?;
+        let size = file.metadata()?.len();
+        (input_reader(args, file)?, size)
+    };
+    logging::event(
+        "file_opened",
+        json!({ "path": input, "size": size, "input_format": input_format }),
+    );
+    let progress = input_progress_bar(args, size);
+    let mut reader = progress.wrap_read(reader);
+    let transactions = match input_format.as_str() {
+        "csv" => match read_csv(args, &mut reader)? {
+            Ok(transactions) => transactions,
+            // For ingestion tools, a single json line on stderr and a failure exit code
+            Err(diagnostic) if logging::is_json() => {
+                progress.finish_and_clear();
+                logging::error(
+                    "parse_failed",
+                    &diagnostic.to_string(),
+                    serde_json::to_value(&diagnostic)?,
+                );
+                std::process::exit(1);
+            }
+            Err(diagnostic) if args.diagnostics.as_deref() == Some("json") => {
+                progress.finish_and_clear();
+                eprintln!("{}", diagnostic.to_json());
+                std::process::exit(1);
+            }
+            Err(diagnostic) => return Err(diagnostic.to_string().into()),
+        },
+        "protobuf" => protobuf::read_transactions(reader)?,
+        other => return Err(format!("Unknown input format : {}", other).into()),
+    };
+    progress.finish_and_clear();
+    Ok(transactions)
+}
 
-        assert_eq!(clients.get(&1).unwrap().available, 1.5);
-        assert_eq!(clients.get(&1).unwrap().held, 0.0);
-        assert_eq!(clients.get(&1).unwrap().total, 1.5);
-        assert_eq!(clients.get(&1).unwrap().locked, false);
+// With --parse-threads, the file is read in memory first and its rows parsed in parallel
+fn read_csv(
+    args: &Args,
+    reader: &mut impl Read,
+) -> Result<Result<Vec<Transaction>, ParseDiagnostic>, Box<dyn Error>> {
+    let input = config_file(args)?.input;
+    let Some(threads) = args.parse_threads else {
+        return Ok(read_transactions_with(reader, &input));
+    };
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(parallel_parse::read_transactions_parallel(
+        &data, &input, threads,
+    ))
+}
 
-        assert_eq!(clients.get(&2).unwrap().available, 2.0);
-        assert_eq!(clients.get(&2).unwrap().held, 0.0);
-        assert_eq!(clients.get(&2).unwrap().total, 2.0);
-        assert_eq!(clients.get(&2).unwrap().locked, false);
+// Only drawn when someone is watching the terminal while the results go elsewhere,
+// so it never mixes with the csv or table printed on stdout
+fn input_progress_bar(args: &Args, file_size: u64) -> ProgressBar {
+    let output_redirected = args.output.is_some() || !std::io::stdout().is_terminal();
+    if !std::io::stderr().is_terminal() || !output_redirected || logging::is_quiet() {
+        return ProgressBar::hidden();
     }
+    ProgressBar::with_draw_target(Some(file_size), ProgressDrawTarget::stderr()).with_style(
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})")
+            .expect("Invalid progress bar template"),
+    )
+}
 
-    #[test]
-    fn handle_dispute() {
-        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
-        let mut clients: HashMap<u16, Client> = HashMap::new();
-        process_transactions(&transactions, &mut clients).unwrap();
+// The csv client state on stdout stays the default output
+fn write_output(
+    args: &Args,
+    clients: &HashMap<u16, Client>,
+    events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    if args.sign_key.is_some() && args.output.is_some() {
+        return Err("Only the stdout output can be signed".into());
+    }
+    if args.pseudonymize_key.is_some() && args.output.is_some() {
+        return Err("Only the stdout output can be pseudonymized".into());
+    }
+    match args.output.as_deref() {
+        None => {
+            if let Some(key) = &args.pseudonymize_key {
+                let key = signature::read_key(key)?;
+                let (clients, mapping) = pseudonym::pseudonymize(&key, clients);
+                if let Some(path) = &args.pseudonym_map {
+                    pseudonym::write_mapping(File::create(path)?, &mapping)?;
+                }
+                return write_signed_stdout_output(args, &clients);
+            }
+            write_signed_stdout_output(args, clients)
+        }
+        Some(output) if output.starts_with("arrow://") => {
+            write_arrow_output(&output["arrow://".len()..], clients, events)
+        }
+        Some(output) if output.starts_with("sqlite://") => {
+            write_sqlite_output(&output["sqlite://".len()..], clients, events)
+        }
+        Some(output) if is_object_url(output) => {
+            let mut content = Vec::new();
+            write_stdout_output(args, clients, &mut content)?;
+            put_object(output, content)
+        }
+        Some(output) => Err(format!("Unknown output : {}", output).into()),
+    }
+}
 
-        assert_eq!(clients.get(&1).unwrap().available, 0.5);
-        assert_eq!(clients.get(&1).unwrap().held, 1.0);
-        assert_eq!(clients.get(&1).unwrap().total, 1.5);
-        assert_eq!(clients.get(&1).unwrap().locked, false);
+// Clients keyed by id, or by pseudonym
+fn write_signed_stdout_output<K: Display + Ord + Hash>(
+    args: &Args,
+    clients: &HashMap<K, Client>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(key) = &args.sign_key else {
+        return write_stdout_output(args, clients, &mut std::io::stdout().lock());
+    };
+    let signature_path = args
+        .signature
+        .as_ref()
+        .ok_or("Please provide the signature file with --signature <file>")?;
+    // Signed as a whole, so the output is kept in memory until its signature is written
+    let mut output = Vec::new();
+    write_stdout_output(args, clients, &mut output)?;
+    let key = signature::read_key(key)?;
+    fs::write(
+        signature_path,
+        format!("{}\n", signature::sign(&key, &output)),
+    )?;
+    Ok(std::io::stdout().lock().write_all(&output)?)
+}
 
-        assert_eq!(clients.get(&2).unwrap().available, 2.0);
-        assert_eq!(clients.get(&2).unwrap().held, 0.0);
-        assert_eq!(clients.get(&2).unwrap().total, 2.0);
-        assert_eq!(clients.get(&2).unwrap().locked, false);
+fn write_stdout_output<W: Write, K: Display + Ord + Hash>(
+    args: &Args,
+    clients: &HashMap<K, Client>,
+    writer: &mut W,
+) -> Result<(), Box<dyn Error>> {
+    match args.format.as_deref() {
+        None | Some("csv") => Ok(write_clients_state(writer, clients)?),
+        Some("table") => {
+            // Colors only make sense in a terminal, see https://no-color.org
+            let color = !args.no_color
+                && env::var_os("NO_COLOR").is_none()
+                && std::io::stdout().is_terminal();
+            let currency = args.display_currency.as_deref();
+            Ok(table_output::write_clients_table(
+                writer, clients, color, currency,
+            )?)
+        }
+        Some(format) => Err(format!("Unknown format : {}", format).into()),
     }
+}
+
+#[cfg(feature = "arrow")]
+fn write_arrow_output(
+    directory: &str,
+    clients: &HashMap<u16, Client>,
+    events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    payments_engine::arrow_output::write_arrow_output(directory, clients, events)
+}
+
+#[cfg(not(feature = "arrow"))]
+fn write_arrow_output(
+    _directory: &str,
+    _clients: &HashMap<u16, Client>,
+    _events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    Err("Arrow output requires building with the `arrow` feature".into())
+}
+
+#[cfg(feature = "sqlite")]
+fn write_sqlite_output(
+    database_path: &str,
+    clients: &HashMap<u16, Client>,
+    events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    payments_engine::sqlite_output::write_sqlite_output(database_path, clients, events)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn write_sqlite_output(
+    _database_path: &str,
+    _clients: &HashMap<u16, Client>,
+    _events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    Err("SQLite output requires building with the `sqlite` feature".into())
+}
+
+const OBJECT_STORE_SCHEMES: [&str; 3] = ["s3://", "gs://", "az://"];
 
-    #[test]
-    // Multiple dispute on the same transaction + dispute on a transaction that doesn't exists
-    fn handle_tricky_disputes() {
-        let transactions = get_transactions_from_file("src/testSamples/trickyDispute.csv").unwrap();
-        let mut clients: HashMap<u16, Client> = HashMap::new();
-        process_transactions(&transactions, &mut clients).unwrap();
+fn is_object_url(path: &str) -> bool {
+    OBJECT_STORE_SCHEMES
+        .iter()
+        .any(|scheme| path.starts_with(scheme))
+}
+
+#[cfg(feature = "object-store")]
+fn open_object(url: &str) -> Result<(Box<dyn Read>, u64), Box<dyn Error>> {
+    let reader = payments_engine::object_store_io::open(url)?;
+    let size = reader.size();
+    Ok((Box::new(reader), size))
+}
 
-        assert_eq!(clients.get(&1).unwrap().available, 0.5);
-        assert_eq!(clients.get(&1).unwrap().held, 1.0);
-        assert_eq!(clients.get(&1).unwrap().total, 1.5);
-        assert_eq!(clients.get(&1).unwrap().locked, false);
+#[cfg(not(feature = "object-store"))]
+fn open_object(_url: &str) -> Result<(Box<dyn Read>, u64), Box<dyn Error>> {
+    Err("Object store input requires building with the `object-store` feature".into())
+}
 
-        assert_eq!(clients.get(&2).unwrap().available, 2.0);
-        assert_eq!(clients.get(&2).unwrap().held, 0.0);
-        assert_eq!(clients.get(&2).unwrap().total, 2.0);
-        assert_eq!(clients.get(&2).unwrap().locked, false);
-    }
+#[cfg(feature = "object-store")]
+fn put_object(url: &str, content: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    payments_engine::object_store_io::put(url, content)
+}
+
+#[cfg(not(feature = "object-store"))]
+fn put_object(_url: &str, _content: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    Err("Object store output requires building with the `object-store` feature".into())
+}
 
-    #[test]
-    // Multiple resolves on the same dispute + resolve on a dispute that doesn't exists
-    fn handle_tricky_resolves() {
-        let transactions = get_transactions_from_file("src/testSamples/trickyResolve.csv").unwrap();
-        let mut clients: HashMap<u16, Client> = HashMap::new();
-        process_transactions(&transactions, &mut clients).unwrap();
-
-        assert_eq!(clients.get(&1).unwrap().available, 1.5);
-        assert_eq!(clients.get(&1).unwrap().held, 0.0);
-        assert_eq!(clients.get(&1).unwrap().total, 1.5);
-        assert_eq!(clients.get(&1).unwrap().locked, false);
-
-        assert_eq!(clients.get(&2).unwrap().available, 2.0);
-        assert_eq!(clients.get(&2).unwrap().held, 0.0);
-        assert_eq!(clients.get(&2).unwrap().total, 2.0);
-        assert_eq!(clients.get(&2).unwrap().locked, false);
-    }
-
-    #[test]
-    fn handle_charge_back() {
-        let transactions = get_transactions_from_file("src/testSamples/chargeback.csv").unwrap();
-        let mut clients: HashMap<u16, Client> = HashMap::new();
-        process_transactions(&transactions, &mut clients).unwrap();
-
-        assert_eq!(clients.get(&1).unwrap().available, 0.5);
-        assert_eq!(clients.get(&1).unwrap().held, 0.0);
-        assert_eq!(clients.get(&1).unwrap().total, 0.5);
-        assert_eq!(clients.get(&1).unwrap().locked, true);
-
-        assert_eq!(clients.get(&2).unwrap().available, 2.0);
-        assert_eq!(clients.get(&2).unwrap().held, 0.0);
-        assert_eq!(clients.get(&2).unwrap().total, 2.0);
-        assert_eq!(clients.get(&2).unwrap().locked, false);
+// One csv row per wallet of each client, the main wallet first
+fn write_wallets_output(args: &Args, clients: &HashMap<u16, Client>) -> Result<(), Box<dyn Error>> {
+    if args.output.is_some() || args.sign_key.is_some() || args.pseudonymize_key.is_some() {
+        return Err("Only the unsigned csv stdout output can be reported by wallet".into());
     }
+    let writer = &mut std::io::stdout().lock();
+    writeln!(writer, "client,wallet,available,held,total,locked,frozen")?;
+    for (client_id, client) in clients {
+        for (wallet, funds) in client.wallet_balances() {
+            writeln!(
+                writer,
+                "{},{},{:.4},{:.4},{:.4},{},{}",
+                client_id,
+                wallet,
+                funds.available,
+                funds.held,
+                funds.available + funds.held,
+                client.locked,
+                client.frozen
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The client state followed by what each client did during the run
+fn write_extended_output(args: &Args, engine: &Engine) -> Result<(), Box<dyn Error>> {
+    if args.output.is_some()
+        || args.format.is_some()
+        || args.report_by.is_some()
+        || args.sign_key.is_some()
+        || args.pseudonymize_key.is_some()
+    {
+        return Err("Only the unsigned csv stdout output can be extended".into());
+    }
+    let writer = &mut std::io::stdout().lock();
+    writeln!(
+        writer,
+        "client,available,held,total,locked,frozen,deposits_count,withdrawals_count,disputes_open,chargebacks_count,last_activity"
+    )?;
+    let open_disputes = engine.open_disputes();
+    let none = Activity::default();
+    for (client_id, client) in engine.clients() {
+        let activity = engine.activity().get(client_id).unwrap_or(&none);
+        writeln!(
+            writer,
+            "{},{:.4},{:.4},{:.4},{},{},{},{},{},{},{}",
+            client_id,
+            client.available,
+            client.held,
+            client.total,
+            client.locked,
+            client.frozen,
+            activity.deposits,
+            activity.withdrawals,
+            open_disputes.get(client_id).copied().unwrap_or_default(),
+            activity.chargebacks,
+            activity
+                .last_activity
+                .map(|at| at.to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+// Written to a locked stdout, see https://nnethercote.github.io/perf-book/io.html
+fn write_clients_state<W: Write, K: Display>(
+    writer: &mut W,
+    clients: &HashMap<K, Client>,
+) -> Result<(), std::io::Error> {
+    writeln!(writer, "client,available,held,total,locked,frozen")?;
+    for (client_id, client) in clients {
+        writeln!(
+            writer,
+            "{},{:.4},{:.4},{:.4},{},{}",
+            client_id, client.available, client.held, client.total, client.locked, client.frozen
+        )?;
+    }
+    Ok(())
 }
@@ -0,0 +1,122 @@
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use std::error::Error;
+use std::io::Read;
+use tokio::runtime::Runtime;
+use url::Url;
+
+// Size of the ranges read from the store, only one of them is in memory at a time
+const RANGE: u64 = 8 * 1024 * 1024;
+
+// s3://bucket/key, gs://bucket/key or az://container/key. The credentials and the settings of
+// the store come from the environment, as with the cloud provider tools (AWS_ACCESS_KEY_ID,
+// AWS_REGION, GOOGLE_SERVICE_ACCOUNT, AZURE_STORAGE_ACCOUNT_NAME...)
+struct Object {
+    runtime: Runtime,
+    store: Box<dyn ObjectStore>,
+    path: Path,
+}
+
+fn open_object(url: &str) -> Result<Object, Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+    let (store, path) = object_store::parse_url_opts(&Url::parse(url)?, options)?;
+    Ok(Object {
+        runtime,
+        store,
+        path,
+    })
+}
+
+// Reads the object range by range, so it never needs to be downloaded whole
+pub struct ObjectReader {
+    object: Object,
+    size: u64,
+    // Offset in the object of the end of the buffer
+    position: u64,
+    buffer: Bytes,
+}
+
+pub fn open(url: &str) -> Result<ObjectReader, Box<dyn Error>> {
+    let object = open_object(url)?;
+    let size = object
+        .runtime
+        .block_on(object.store.head(&object.path))?
+        .size;
+    Ok(ObjectReader {
+        object,
+        size,
+        position: 0,
+        buffer: Bytes::new(),
+    })
+}
+
+impl ObjectReader {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buffer.is_empty() {
+            if self.position >= self.size {
+                return Ok(0);
+            }
+            let end = self.size.min(self.position + RANGE);
+            let object = &self.object;
+            self.buffer = object
+                .runtime
+                .block_on(object.store.get_range(&object.path, self.position..end))
+                .map_err(std::io::Error::other)?;
+            self.position = end;
+        }
+        let length = buf.len().min(self.buffer.len());
+        buf[..length].copy_from_slice(&self.buffer.split_to(length));
+        Ok(length)
+    }
+}
+
+// The outputs are small enough (one row per client at most) to be uploaded in one request
+pub fn put(url: &str, content: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    let object = open_object(url)?;
+    object
+        .runtime
+        .block_on(object.store.put(&object.path, PutPayload::from(content)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranged_reads() {
+        let content: Vec<u8> = (0..RANGE * 2 + 10).map(|i| (i % 251) as u8).collect();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let store = object_store::memory::InMemory::new();
+        let path = Path::from("transactions.csv");
+        runtime
+            .block_on(store.put(&path, PutPayload::from(content.clone())))
+            .unwrap();
+        let mut reader = ObjectReader {
+            object: Object {
+                runtime,
+                store: Box::new(store),
+                path,
+            },
+            size: content.len() as u64,
+            position: 0,
+            buffer: Bytes::new(),
+        };
+
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+    }
+}
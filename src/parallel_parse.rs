@@ -0,0 +1,120 @@
+use crate::config::InputSettings;
+use crate::diagnostics::ParseDiagnostic;
+use crate::{read_transactions_with, Transaction};
+use std::io::Read;
+use std::thread;
+
+// Below this, a chunk isn't worth a thread
+const MIN_CHUNK_BYTES: usize = 64 * 1024;
+
+// Parses a csv file held in memory on up to `threads` threads, each one reading a range of
+// whole rows with the header of the file, and returns the rows in the file order. Rows are
+// split on line breaks, which a quoted field (a free text reason) can contain : a body with
+// quotes is read in one chunk, like the sequential reader does. A row failing to parse is
+// reported with its line in the file, the first one in the file order when several chunks
+// fail.
+pub fn read_transactions_parallel(
+    data: &[u8],
+    input: &InputSettings,
+    threads: usize,
+) -> Result<Vec<Transaction>, ParseDiagnostic> {
+    let header_end = match data.iter().position(|&b| b == b'\n') {
+        Some(position) => position + 1,
+        None => data.len(),
+    };
+    let (header, body) = data.split_at(header_end);
+    let chunks = if body.contains(&b'"') {
+        vec![body]
+    } else {
+        split_rows(body, threads.max(1))
+    };
+    let parsed: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .iter()
+            .map(|chunk| scope.spawn(|| read_transactions_with(header.chain(*chunk), input)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("A parsing thread panicked"))
+            .collect()
+    });
+    let mut transactions = Vec::with_capacity(parsed.iter().flatten().map(Vec::len).sum());
+    // Line of the file before each chunk, the header being line 1 of every chunk
+    let mut lines_before = 0;
+    for (chunk, result) in chunks.iter().zip(parsed) {
+        match result {
+            Ok(rows) => transactions.extend(rows),
+            Err(mut diagnostic) => {
+                diagnostic.line = diagnostic.line.map(|line| line + lines_before);
+                return Err(diagnostic);
+            }
+        }
+        lines_before += chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+    }
+    Ok(transactions)
+}
+
+// Up to `count` ranges of whole rows, each one ending after a line break (but the last)
+fn split_rows(body: &[u8], count: usize) -> Vec<&[u8]> {
+    let size = (body.len() / count).max(MIN_CHUNK_BYTES);
+    let mut chunks = Vec::with_capacity(count);
+    let mut rest = body;
+    while !rest.is_empty() {
+        let end = match rest
+            .get(size..)
+            .and_then(|after| after.iter().position(|&b| b == b'\n'))
+        {
+            Some(position) => size + position + 1,
+            None => rest.len(),
+        };
+        let (chunk, after) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = after;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_keep_the_order() {
+        let mut data = "type, client, tx, amount\n".to_string();
+        for tx in 1..=20_000 {
+            data.push_str(&format!("deposit, {}, {}, 1.5\n", tx % 7, tx));
+        }
+        let input = InputSettings::default();
+        let sequential = read_transactions_with(data.as_bytes(), &input).unwrap();
+        for threads in [1, 3, 8] {
+            let parallel = read_transactions_parallel(data.as_bytes(), &input, threads).unwrap();
+            assert_eq!(parallel.len(), sequential.len());
+            assert!(parallel.iter().zip(&sequential).all(|(a, b)| a.tx == b.tx));
+        }
+
+        // Line 15001 of the file, past the first chunks
+        let broken = data.replacen("deposit, 6, 15000, 1.5", "deposit, 6, 15000, a", 1);
+        let diagnostic = read_transactions_parallel(broken.as_bytes(), &input, 4).unwrap_err();
+        assert_eq!(diagnostic.line, Some(15001));
+    }
+
+    #[test]
+    fn multi_line_reasons() {
+        let mut data = "type,client,tx,amount,reason\n".to_string();
+        for tx in 1..=20_000 {
+            data.push_str(&format!(
+                "deposit,1,{},1.5,\"first line\nsecond line\"\n",
+                tx
+            ));
+        }
+        let input = InputSettings::default();
+        let sequential = read_transactions_with(data.as_bytes(), &input).unwrap();
+        let parallel = read_transactions_parallel(data.as_bytes(), &input, 8).unwrap();
+        assert_eq!(parallel.len(), 20_000);
+        assert!(parallel.iter().zip(&sequential).all(|(a, b)| a.tx == b.tx));
+        assert_eq!(
+            parallel[0].reason.as_deref(),
+            Some("first line\nsecond line")
+        );
+    }
+}
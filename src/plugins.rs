@@ -0,0 +1,188 @@
+use crate::config::PluginSettings;
+use crate::rules::{Action, Rule};
+use crate::{Client, Transaction, TransactionCategory};
+use std::error::Error;
+use wasmtime::{Config, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+const DEFAULT_FUEL: u64 = 1_000_000;
+const DEFAULT_MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+// Rule of the events decided by the plugin, and of those it failed to decide
+pub const PLUGIN_RULE: &str = "plugin";
+pub const PLUGIN_ERROR_RULE: &str = "plugin error";
+
+// (type, client, tx, amount, available, held, locked) -> decision
+type Validate = TypedFunc<(i32, i32, i64, f64, f64, f64, i32), i32>;
+
+// Validation compiled to WebAssembly by a partner, in any language. The module exports
+//
+//   validate(type: i32, client: i32, tx: i64, amount: f64, available: f64, held: f64,
+//            locked: i32) -> i32
+//
+// with type 0 for a deposit and 1 for a withdrawal, and returns 0 to allow, 1 to deny, 2 to
+// hold or 3 to flag. It is evaluated when neither the rules of the config nor the script match.
+//
+// The module can't import anything, so it has no access to the host, and runs without
+// threads or floating point nondeterminism. Each call gets `fuel`, and its memory can't grow
+// past `max_memory` bytes : a plugin running out of either or trapping rejects the
+// transaction. A module declaring more memory than that isn't loaded.
+pub struct Plugin {
+    store: Store<StoreLimits>,
+    validate: Validate,
+    fuel: u64,
+}
+
+impl Plugin {
+    pub fn load(settings: &PluginSettings) -> Result<Plugin, Box<dyn Error>> {
+        let module = std::fs::read(&settings.path)?;
+        let fuel = settings.fuel.unwrap_or(DEFAULT_FUEL);
+        let max_memory = settings
+            .max_memory_bytes
+            .unwrap_or(DEFAULT_MAX_MEMORY_BYTES);
+        Ok(Plugin::compile(&module, fuel, max_memory)?)
+    }
+
+    // From the binary or text format of a module
+    pub fn compile(module: &[u8], fuel: u64, max_memory: usize) -> Result<Plugin, String> {
+        let mut config = Config::new();
+        config
+            .consume_fuel(true)
+            .cranelift_nan_canonicalization(true)
+            .wasm_relaxed_simd(false);
+        let engine = wasmtime::Engine::new(&config).map_err(|e| e.to_string())?;
+        let module = Module::new(&engine, module).map_err(|e| e.to_string())?;
+        if module.imports().len() > 0 {
+            return Err("A validation plugin can't import anything".to_string());
+        }
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(max_memory)
+            .trap_on_grow_failure(true)
+            .build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        let instance =
+            wasmtime::Instance::new(&mut store, &module, &[]).map_err(|e| e.to_string())?;
+        let validate = instance
+            .get_typed_func(&mut store, "validate")
+            .map_err(|e| e.to_string())?;
+        Ok(Plugin {
+            store,
+            validate,
+            fuel,
+        })
+    }
+
+    pub fn decide(&mut self, t: &Transaction, client: &Client) -> Option<Rule> {
+        let action = match self.call(t, client) {
+            Ok(action) => action?,
+            Err(_) => return Some(Rule::named(PLUGIN_ERROR_RULE, Action::Reject)),
+        };
+        Some(Rule::named(PLUGIN_RULE, action))
+    }
+
+    fn call(&mut self, t: &Transaction, client: &Client) -> Result<Option<Action>, String> {
+        let category = match t.category {
+            TransactionCategory::Deposit => 0,
+            TransactionCategory::Withdrawal => 1,
+            _ => return Ok(None),
+        };
+        self.store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        let decision = self
+            .validate
+            .call(
+                &mut self.store,
+                (
+                    category,
+                    t.client_id as i32,
+                    t.tx as i64,
+                    t.amount.unwrap_or(0.0),
+                    client.available,
+                    client.held,
+                    client.locked as i32,
+                ),
+            )
+            .map_err(|e| e.to_string())?;
+        match decision {
+            0 => Ok(None),
+            1 => Ok(Some(Action::Reject)),
+            2 => Ok(Some(Action::Hold)),
+            3 => Ok(Some(Action::Flag)),
+            _ => Err(format!("Unknown decision {}", decision)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn withdrawal(amount: f64) -> Transaction {
+        Transaction {
+            category: TransactionCategory::Withdrawal,
+            client_id: 1,
+            tx: 1,
+            amount: Some(amount),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn plugin_decisions() {
+        // Holds the withdrawals of more than half the available funds
+        let module = r#"
+            (module
+              (func (export "validate")
+                (param i32 i32 i64 f64 f64 f64 i32) (result i32)
+                (if (result i32) (f64.gt (local.get 3) (f64.div (local.get 4) (f64.const 2)))
+                  (then (i32.const 2))
+                  (else (i32.const 0)))))
+        "#;
+        let mut plugin = Plugin::compile(module.as_bytes(), 1_000, 65_536).unwrap();
+        let client = Client {
+            available: 100.0,
+            ..Default::default()
+        };
+        let action = |plugin: &mut Plugin, t| plugin.decide(&t, &client).map(|rule| rule.action);
+        assert_eq!(action(&mut plugin, withdrawal(80.0)), Some(Action::Hold));
+        assert_eq!(action(&mut plugin, withdrawal(20.0)), None);
+
+        // Out of fuel, the plugin rejects the transaction
+        let endless = r#"
+            (module
+              (func (export "validate")
+                (param i32 i32 i64 f64 f64 f64 i32) (result i32)
+                (loop (br 0))
+                (i32.const 0)))
+        "#;
+        let mut plugin = Plugin::compile(endless.as_bytes(), 1_000, 65_536).unwrap();
+        let rule = plugin.decide(&withdrawal(10.0), &client).unwrap();
+        assert_eq!(
+            (rule.name.as_str(), rule.action),
+            (PLUGIN_ERROR_RULE, Action::Reject)
+        );
+
+        // Growing its memory past the limit, the plugin rejects the transaction
+        let growing = r#"
+            (module
+              (memory 1)
+              (func (export "validate")
+                (param i32 i32 i64 f64 f64 f64 i32) (result i32)
+                (drop (memory.grow (i32.const 1)))
+                (i32.const 0)))
+        "#;
+        let mut plugin = Plugin::compile(growing.as_bytes(), 1_000, 65_536).unwrap();
+        let rule = plugin.decide(&withdrawal(10.0), &client).unwrap();
+        assert_eq!(
+            (rule.name.as_str(), rule.action),
+            (PLUGIN_ERROR_RULE, Action::Reject)
+        );
+        let mut plugin = Plugin::compile(growing.as_bytes(), 1_000, 2 * 65_536).unwrap();
+        assert!(plugin.decide(&withdrawal(10.0), &client).is_none());
+        assert!(Plugin::compile(growing.as_bytes(), 1_000, 0).is_err());
+
+        let importing = r#"(module (import "env" "clock" (func)))"#;
+        assert!(Plugin::compile(importing.as_bytes(), 1_000, 65_536).is_err());
+    }
+}
@@ -0,0 +1,760 @@
+use crate::state::{Conflict, ErasureRecord, StateStore};
+use crate::{Client, Dispute, DisputeState, Engine, Transaction, TransactionCategory, Wallet};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions};
+use sqlx::{Postgres, Transaction as DbTransaction};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+// Same content as the state directory, one table per file. The history is only read on the
+// first dispute, and a save upserts the transactions of the run into it.
+const SCHEMA: [&str; 8] = [
+    "CREATE TABLE IF NOT EXISTS clients (
+        client INTEGER PRIMARY KEY,
+        available DOUBLE PRECISION NOT NULL,
+        held DOUBLE PRECISION NOT NULL,
+        total DOUBLE PRECISION NOT NULL,
+        locked BOOLEAN NOT NULL,
+        frozen BOOLEAN NOT NULL,
+        version BIGINT NOT NULL DEFAULT 0
+    )",
+    // Missing from the tables created before the clients were versioned. Checked first, as the
+    // ALTER TABLE would wait for the saves of the other instances.
+    "DO $$ BEGIN
+        IF NOT EXISTS (SELECT 1 FROM information_schema.columns
+            WHERE table_name = 'clients' AND column_name = 'version') THEN
+            ALTER TABLE clients ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+        END IF;
+    END $$",
+    "CREATE TABLE IF NOT EXISTS history (
+        tx BIGINT PRIMARY KEY,
+        type TEXT NOT NULL,
+        client INTEGER NOT NULL,
+        amount DOUBLE PRECISION,
+        reason TEXT,
+        timestamp BIGINT,
+        wallet TEXT
+    )",
+    "CREATE TABLE IF NOT EXISTS disputes (
+        tx BIGINT NOT NULL,
+        client INTEGER NOT NULL,
+        amount DOUBLE PRECISION NOT NULL,
+        state TEXT NOT NULL,
+        reason TEXT,
+        opened_at BIGINT,
+        closed_at BIGINT,
+        provisional_credit BOOLEAN NOT NULL,
+        wallet TEXT
+    )",
+    "CREATE TABLE IF NOT EXISTS held (
+        tx BIGINT PRIMARY KEY,
+        type TEXT NOT NULL,
+        client INTEGER NOT NULL,
+        amount DOUBLE PRECISION,
+        reason TEXT,
+        timestamp BIGINT,
+        wallet TEXT
+    )",
+    "CREATE TABLE IF NOT EXISTS escrow (
+        client INTEGER NOT NULL,
+        bucket TEXT NOT NULL,
+        amount DOUBLE PRECISION NOT NULL,
+        PRIMARY KEY (client, bucket)
+    )",
+    "CREATE TABLE IF NOT EXISTS wallets (
+        client INTEGER NOT NULL,
+        wallet TEXT NOT NULL,
+        available DOUBLE PRECISION NOT NULL,
+        held DOUBLE PRECISION NOT NULL,
+        PRIMARY KEY (client, wallet)
+    )",
+    "CREATE TABLE IF NOT EXISTS erasures (
+        erased_at BIGINT NOT NULL,
+        client INTEGER NOT NULL,
+        history BIGINT NOT NULL,
+        disputes BIGINT NOT NULL,
+        pseudonyms BIGINT NOT NULL,
+        folded DOUBLE PRECISION NOT NULL
+    )",
+];
+
+type TransactionRow = (
+    i64,
+    String,
+    i32,
+    Option<f64>,
+    Option<String>,
+    Option<i64>,
+    Option<String>,
+);
+
+type DisputeRow = (
+    i64,
+    i32,
+    f64,
+    String,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+    bool,
+    Option<String>,
+);
+
+// --state postgres://<user>:<password>@<host>/<database>. A save is a single database
+// transaction, so the state is never seen half written.
+pub struct PostgresStore {
+    runtime: Arc<Runtime>,
+    pool: PgPool,
+    // Version and snapshot of each client as loaded, see save_engine
+    loaded: Mutex<HashMap<u16, (i64, String)>>,
+}
+
+pub fn connect(url: &str) -> Result<PostgresStore, Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let pool = runtime.block_on(async {
+        let pool = PgPoolOptions::new().max_connections(1).connect(url).await?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok::<PgPool, sqlx::Error>(pool)
+    })?;
+    Ok(PostgresStore {
+        runtime: Arc::new(runtime),
+        pool,
+        loaded: Mutex::new(HashMap::new()),
+    })
+}
+
+impl StateStore for PostgresStore {
+    fn load(&self) -> Result<Engine, Box<dyn Error>> {
+        self.runtime.block_on(self.load_engine())
+    }
+
+    fn save(&self, engine: &Engine) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(self.save_engine(engine))
+    }
+
+    fn record_erasure(&self, record: &ErasureRecord) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(async {
+            sqlx::query("INSERT INTO erasures VALUES ($1, $2, $3, $4, $5, $6)")
+                .bind(record.erased_at as i64)
+                .bind(record.client as i32)
+                .bind(record.history as i64)
+                .bind(record.disputes as i64)
+                .bind(record.pseudonyms as i64)
+                .bind(record.folded)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+impl PostgresStore {
+    async fn load_engine(&self) -> Result<Engine, Box<dyn Error>> {
+        let mut engine = Engine::default();
+        // The tables are read from the same snapshot of the database
+        let mut db = self.pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .execute(&mut *db)
+            .await?;
+        let clients: Vec<(i32, f64, f64, f64, bool, bool, i64)> = sqlx::query_as(
+            "SELECT client, available, held, total, locked, frozen, version FROM clients",
+        )
+        .fetch_all(&mut *db)
+        .await?;
+        let mut versions = HashMap::new();
+        for (client_id, available, held, total, locked, frozen, version) in clients {
+            let client_id = client_id.try_into()?;
+            versions.insert(client_id, version);
+            engine.clients.insert(
+                client_id,
+                Client {
+                    available,
+                    held,
+                    total,
+                    locked,
+                    frozen,
+                    escrow: BTreeMap::new(),
+                    wallets: BTreeMap::new(),
+                },
+            );
+        }
+
+        let runtime = self.runtime.clone();
+        let pool = self.pool.clone();
+        engine.set_history_loader(Box::new(move || {
+            runtime
+                .block_on(async {
+                    let mut connection = pool.acquire().await?;
+                    read_transactions(&mut connection, "history").await
+                })
+                .map_err(|e| e.to_string())
+        }));
+
+        let disputes: Vec<DisputeRow> = sqlx::query_as(
+            "SELECT tx, client, amount, state, reason, opened_at, closed_at, provisional_credit, wallet
+            FROM disputes",
+        )
+        .fetch_all(&mut *db)
+        .await?;
+        for row in disputes {
+            let dispute = Dispute {
+                tx: row.0.try_into()?,
+                client_id: row.1.try_into()?,
+                amount: row.2,
+                state: parse(&row.3)?,
+                reason: row.4,
+                opened_at: row.5.map(u64::try_from).transpose()?,
+                closed_at: row.6.map(u64::try_from).transpose()?,
+                provisional_credit: row.7,
+                wallet: row.8,
+            };
+            match dispute.state {
+                DisputeState::Deferred | DisputeState::Opened | DisputeState::UnderReview => {
+                    engine.ongoing_disputes.insert(dispute.tx, dispute);
+                }
+                DisputeState::Resolved | DisputeState::ChargedBack => {
+                    engine.closed_disputes.push(dispute)
+                }
+            }
+        }
+
+        for t in read_transactions(&mut db, "held").await? {
+            engine.held_transactions.insert(t.tx, t);
+        }
+
+        let escrow: Vec<(i32, String, f64)> =
+            sqlx::query_as("SELECT client, bucket, amount FROM escrow")
+                .fetch_all(&mut *db)
+                .await?;
+        for (client_id, bucket, amount) in escrow {
+            engine
+                .clients
+                .entry(client_id.try_into()?)
+                .or_default()
+                .escrow
+                .insert(bucket, amount);
+        }
+
+        let wallets: Vec<(i32, String, f64, f64)> =
+            sqlx::query_as("SELECT client, wallet, available, held FROM wallets")
+                .fetch_all(&mut *db)
+                .await?;
+        for (client_id, wallet, available, held) in wallets {
+            engine
+                .clients
+                .entry(client_id.try_into()?)
+                .or_default()
+                .wallets
+                .insert(wallet, Wallet { available, held });
+        }
+        db.commit().await?;
+
+        let mut loaded = self.loaded.lock().map_err(|e| e.to_string())?;
+        *loaded = client_snapshots(&engine)
+            .into_iter()
+            .map(|(id, snapshot)| (id, (versions[&id], snapshot)))
+            .collect();
+        Ok(engine)
+    }
+
+    // Only the clients the run changed are written, each one only if its version is still the
+    // one loaded. The rows of their disputes, held transactions, escrow and wallets are
+    // replaced along with them. Rows are sent as arrays, one statement per table.
+    async fn save_engine(&self, engine: &Engine) -> Result<(), Box<dyn Error>> {
+        let snapshots = client_snapshots(engine);
+        let loaded = self.loaded.lock().map_err(|e| e.to_string())?.clone();
+        let changed: Vec<u16> = snapshots
+            .iter()
+            .filter(|(id, snapshot)| loaded.get(id).map(|(_, s)| s) != Some(snapshot))
+            .map(|(id, _)| *id)
+            .collect();
+        let (updated, inserted): (Vec<u16>, Vec<u16>) =
+            changed.iter().partition(|id| loaded.contains_key(id));
+
+        let mut db = self.pool.begin().await?;
+        let mut saved: Vec<i32> = sqlx::query_scalar(
+            "UPDATE clients SET available = u.available, held = u.held, total = u.total,
+                locked = u.locked, frozen = u.frozen, version = clients.version + 1
+            FROM UNNEST($1::INTEGER[], $2::DOUBLE PRECISION[], $3::DOUBLE PRECISION[],
+                $4::DOUBLE PRECISION[], $5::BOOLEAN[], $6::BOOLEAN[], $7::BIGINT[])
+                AS u(client, available, held, total, locked, frozen, version)
+            WHERE clients.client = u.client AND clients.version = u.version
+            RETURNING clients.client",
+        )
+        .bind(updated.iter().map(|id| *id as i32).collect::<Vec<_>>())
+        .bind(field(engine, &updated, |c| c.available))
+        .bind(field(engine, &updated, |c| c.held))
+        .bind(field(engine, &updated, |c| c.total))
+        .bind(field(engine, &updated, |c| c.locked))
+        .bind(field(engine, &updated, |c| c.frozen))
+        .bind(updated.iter().map(|id| loaded[id].0).collect::<Vec<_>>())
+        .fetch_all(&mut *db)
+        .await?;
+        saved.extend(
+            sqlx::query_scalar::<_, i32>(
+                "INSERT INTO clients (client, available, held, total, locked, frozen)
+                SELECT * FROM UNNEST($1::INTEGER[], $2::DOUBLE PRECISION[],
+                    $3::DOUBLE PRECISION[], $4::DOUBLE PRECISION[], $5::BOOLEAN[], $6::BOOLEAN[])
+                ON CONFLICT (client) DO NOTHING
+                RETURNING client",
+            )
+            .bind(inserted.iter().map(|id| *id as i32).collect::<Vec<_>>())
+            .bind(field(engine, &inserted, |c| c.available))
+            .bind(field(engine, &inserted, |c| c.held))
+            .bind(field(engine, &inserted, |c| c.total))
+            .bind(field(engine, &inserted, |c| c.locked))
+            .bind(field(engine, &inserted, |c| c.frozen))
+            .fetch_all(&mut *db)
+            .await?,
+        );
+        if saved.len() < changed.len() {
+            db.rollback().await?;
+            let mut clients: Vec<u16> = changed
+                .iter()
+                .filter(|id| !saved.contains(&(**id as i32)))
+                .copied()
+                .collect();
+            clients.sort();
+            return Err(Box::new(Conflict { clients }));
+        }
+
+        // Erased clients go with every row of theirs, unless another instance saved them since
+        let erased: Vec<u16> = engine
+            .erased_clients
+            .iter()
+            .filter(|id| loaded.contains_key(id))
+            .copied()
+            .collect();
+        let erased_ids: Vec<i32> = erased.iter().map(|id| *id as i32).collect();
+        let deleted: Vec<i32> = sqlx::query_scalar(
+            "DELETE FROM clients USING UNNEST($1::INTEGER[], $2::BIGINT[]) AS e(client, version)
+            WHERE clients.client = e.client AND clients.version = e.version
+            RETURNING clients.client",
+        )
+        .bind(&erased_ids)
+        .bind(erased.iter().map(|id| loaded[id].0).collect::<Vec<_>>())
+        .fetch_all(&mut *db)
+        .await?;
+        if deleted.len() < erased.len() {
+            db.rollback().await?;
+            let mut clients: Vec<u16> = erased
+                .iter()
+                .filter(|id| !deleted.contains(&(**id as i32)))
+                .copied()
+                .collect();
+            clients.sort();
+            return Err(Box::new(Conflict { clients }));
+        }
+        for table in ["history", "disputes", "held", "escrow", "wallets"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE client = ANY($1)", table))
+                .bind(&erased_ids)
+                .execute(&mut *db)
+                .await?;
+        }
+
+        // When a tx id was reused, the latest row wins
+        let history: Vec<&Transaction> = engine.transactions_history.values().collect();
+        write_transactions(&mut db, "history", &history).await?;
+        // The rows evicted by the retention go, unless another client reused their tx id since
+        sqlx::query(
+            "DELETE FROM history USING UNNEST($1::BIGINT[], $2::INTEGER[]) AS e(tx, client)
+            WHERE history.tx = e.tx AND history.client = e.client",
+        )
+        .bind(
+            engine
+                .evicted_history
+                .iter()
+                .map(|(_, tx)| *tx as i64)
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            engine
+                .evicted_history
+                .iter()
+                .map(|(client_id, _)| *client_id as i32)
+                .collect::<Vec<_>>(),
+        )
+        .execute(&mut *db)
+        .await?;
+
+        let changed_ids: Vec<i32> = changed.iter().map(|id| *id as i32).collect();
+        for table in ["disputes", "held", "escrow", "wallets"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE client = ANY($1)", table))
+                .bind(&changed_ids)
+                .execute(&mut *db)
+                .await?;
+        }
+
+        let disputes: Vec<&Dispute> = engine
+            .disputes()
+            .filter(|d| changed.contains(&d.client_id))
+            .collect();
+        sqlx::query(
+            "INSERT INTO disputes
+            SELECT * FROM UNNEST($1::BIGINT[], $2::INTEGER[], $3::DOUBLE PRECISION[], $4::TEXT[],
+                $5::TEXT[], $6::BIGINT[], $7::BIGINT[], $8::BOOLEAN[], $9::TEXT[])",
+        )
+        .bind(disputes.iter().map(|d| d.tx as i64).collect::<Vec<_>>())
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.client_id as i32)
+                .collect::<Vec<_>>(),
+        )
+        .bind(disputes.iter().map(|d| d.amount).collect::<Vec<_>>())
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.state.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.reason.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.opened_at.map(|t| t as i64))
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.closed_at.map(|t| t as i64))
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.provisional_credit)
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            disputes
+                .iter()
+                .map(|d| d.wallet.clone())
+                .collect::<Vec<_>>(),
+        )
+        .execute(&mut *db)
+        .await?;
+
+        let held: Vec<&Transaction> = engine
+            .held_transactions()
+            .filter(|t| changed.contains(&t.client_id))
+            .collect();
+        write_transactions(&mut db, "held", &held).await?;
+
+        let escrow: Vec<(u16, &String, f64)> = changed
+            .iter()
+            .flat_map(|id| engine.clients[id].escrow.iter().map(|(b, a)| (*id, b, *a)))
+            .collect();
+        sqlx::query(
+            "INSERT INTO escrow
+            SELECT * FROM UNNEST($1::INTEGER[], $2::TEXT[], $3::DOUBLE PRECISION[])",
+        )
+        .bind(escrow.iter().map(|e| e.0 as i32).collect::<Vec<_>>())
+        .bind(escrow.iter().map(|e| e.1.as_str()).collect::<Vec<_>>())
+        .bind(escrow.iter().map(|e| e.2).collect::<Vec<_>>())
+        .execute(&mut *db)
+        .await?;
+
+        let wallets: Vec<(u16, &String, &Wallet)> = changed
+            .iter()
+            .flat_map(|id| engine.clients[id].wallets.iter().map(|(w, f)| (*id, w, f)))
+            .collect();
+        sqlx::query(
+            "INSERT INTO wallets
+            SELECT * FROM UNNEST($1::INTEGER[], $2::TEXT[], $3::DOUBLE PRECISION[],
+                $4::DOUBLE PRECISION[])",
+        )
+        .bind(wallets.iter().map(|w| w.0 as i32).collect::<Vec<_>>())
+        .bind(wallets.iter().map(|w| w.1.as_str()).collect::<Vec<_>>())
+        .bind(wallets.iter().map(|w| w.2.available).collect::<Vec<_>>())
+        .bind(wallets.iter().map(|w| w.2.held).collect::<Vec<_>>())
+        .execute(&mut *db)
+        .await?;
+
+        db.commit().await?;
+        let mut loaded = self.loaded.lock().map_err(|e| e.to_string())?;
+        for id in changed {
+            let version = loaded.get(&id).map_or(0, |(version, _)| version + 1);
+            loaded.insert(id, (version, snapshots[&id].clone()));
+        }
+        for id in erased {
+            loaded.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+fn field<T>(engine: &Engine, clients: &[u16], value: impl Fn(&Client) -> T) -> Vec<T> {
+    clients
+        .iter()
+        .map(|id| value(&engine.clients[id]))
+        .collect()
+}
+
+// Everything saved along with each client, to find the clients a run changed
+fn client_snapshots(engine: &Engine) -> HashMap<u16, String> {
+    let mut disputes: HashMap<u16, Vec<&Dispute>> = HashMap::new();
+    for dispute in engine.disputes() {
+        disputes.entry(dispute.client_id).or_default().push(dispute);
+    }
+    let mut held: HashMap<u16, Vec<&Transaction>> = HashMap::new();
+    for t in engine.held_transactions() {
+        held.entry(t.client_id).or_default().push(t);
+    }
+    engine
+        .clients
+        .iter()
+        .map(|(id, c)| {
+            let wallets: Vec<(&String, f64, f64)> = c
+                .wallets
+                .iter()
+                .map(|(name, w)| (name, w.available, w.held))
+                .collect();
+            let snapshot = serde_json::to_string(&(
+                (c.available, c.held, c.total, c.locked, c.frozen),
+                &c.escrow,
+                wallets,
+                disputes.get(id),
+                held.get(id),
+            ))
+            .expect("Client state is always serializable");
+            (*id, snapshot)
+        })
+        .collect()
+}
+
+async fn read_transactions(
+    connection: &mut PgConnection,
+    table: &str,
+) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let rows: Vec<TransactionRow> = sqlx::query_as(&format!(
+        "SELECT tx, type, client, amount, reason, timestamp, wallet FROM {}",
+        table
+    ))
+    .fetch_all(connection)
+    .await?;
+    let mut transactions = Vec::with_capacity(rows.len());
+    for (tx, category, client_id, amount, reason, timestamp, wallet) in rows {
+        transactions.push(Transaction {
+            category: parse::<TransactionCategory>(&category)?,
+            client_id: client_id.try_into()?,
+            tx: tx.try_into()?,
+            amount,
+            reason,
+            timestamp: timestamp.map(u64::try_from).transpose()?,
+            wallet,
+            seq: None,
+        });
+    }
+    Ok(transactions)
+}
+
+async fn write_transactions(
+    db: &mut DbTransaction<'_, Postgres>,
+    table: &str,
+    transactions: &[&Transaction],
+) -> Result<(), Box<dyn Error>> {
+    sqlx::query(&format!(
+        "INSERT INTO {}
+        SELECT * FROM UNNEST($1::BIGINT[], $2::TEXT[], $3::INTEGER[], $4::DOUBLE PRECISION[],
+            $5::TEXT[], $6::BIGINT[], $7::TEXT[])
+        ON CONFLICT (tx) DO UPDATE SET type = EXCLUDED.type, client = EXCLUDED.client,
+            amount = EXCLUDED.amount, reason = EXCLUDED.reason, timestamp = EXCLUDED.timestamp,
+            wallet = EXCLUDED.wallet",
+        table
+    ))
+    .bind(transactions.iter().map(|t| t.tx as i64).collect::<Vec<_>>())
+    .bind(
+        transactions
+            .iter()
+            .map(|t| t.category.as_str())
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        transactions
+            .iter()
+            .map(|t| t.client_id as i32)
+            .collect::<Vec<_>>(),
+    )
+    .bind(transactions.iter().map(|t| t.amount).collect::<Vec<_>>())
+    .bind(
+        transactions
+            .iter()
+            .map(|t| t.reason.clone())
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        transactions
+            .iter()
+            .map(|t| t.timestamp.map(|t| t as i64))
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        transactions
+            .iter()
+            .map(|t| t.wallet.clone())
+            .collect::<Vec<_>>(),
+    )
+    .execute(&mut **db)
+    .await?;
+    Ok(())
+}
+
+// The enums are stored with their names in the csv files
+fn parse<T: DeserializeOwned>(name: &str) -> Result<T, serde::de::value::Error> {
+    T::deserialize(name.into_deserializer())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn enum_names() {
+        assert_eq!(
+            parse::<TransactionCategory>("chargeback").unwrap(),
+            TransactionCategory::Chargeback
+        );
+        assert_eq!(
+            parse::<DisputeState>("under_review").unwrap(),
+            DisputeState::UnderReview
+        );
+        assert!(parse::<DisputeState>("closed").is_err());
+    }
+
+    // Needs a server : PAYMENTS_ENGINE_TEST_POSTGRES=postgres://... cargo test --features
+    // postgres -- --ignored
+    #[test]
+    #[ignore]
+    fn save_and_load() {
+        let url = std::env::var("PAYMENTS_ENGINE_TEST_POSTGRES").unwrap();
+        let store = connect(&url).unwrap();
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut expected = store.load().unwrap();
+        for t in &transactions {
+            expected.process(t).unwrap();
+        }
+        store.save(&expected).unwrap();
+
+        let mut engine = store.load().unwrap();
+        engine.load_history().unwrap();
+        for (client_id, client) in &expected.clients {
+            assert_eq!(engine.clients[client_id].total, client.total);
+            assert_eq!(engine.clients[client_id].locked, client.locked);
+        }
+        assert_eq!(engine.disputes().count(), expected.disputes().count());
+        assert_eq!(
+            engine.transactions_history.len(),
+            expected.transactions_history.len()
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn evicted_history() {
+        let url = std::env::var("PAYMENTS_ENGINE_TEST_POSTGRES").unwrap();
+        let store = connect(&url).unwrap();
+        let transactions =
+            get_transactions_from_file("src/testSamples/historyRetention.csv").unwrap();
+        let mut engine = store.load().unwrap();
+        engine.set_config(toml::from_str("[history]\nretention_seconds = 900").unwrap());
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        engine.evict_history().unwrap();
+        store.save(&engine).unwrap();
+
+        // The expired deposit and the charged back one aren't loaded back
+        let mut engine = connect(&url).unwrap().load().unwrap();
+        engine.load_history().unwrap();
+        assert!(!engine.transactions_history.contains_key(&1));
+        assert!(!engine.transactions_history.contains_key(&3));
+        assert!(engine.transactions_history.contains_key(&5));
+    }
+
+    #[test]
+    #[ignore]
+    fn erased_client() {
+        let url = std::env::var("PAYMENTS_ENGINE_TEST_POSTGRES").unwrap();
+        let deposit = |client_id: u16, tx: u32| Transaction {
+            category: TransactionCategory::Deposit,
+            client_id,
+            tx,
+            amount: Some(1.0),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        };
+        let store = connect(&url).unwrap();
+        let mut engine = store.load().unwrap();
+        engine.process(&deposit(200, 200_000)).unwrap();
+        store.save(&engine).unwrap();
+
+        let store = connect(&url).unwrap();
+        let mut engine = store.load().unwrap();
+        engine.set_config(toml::from_str("[admin]\nanonymized_account = 201").unwrap());
+        let folded = engine.clients.get(&201).map_or(0.0, |client| client.total) + 1.0;
+        engine.erase_client(200).unwrap();
+        store.save(&engine).unwrap();
+
+        // Neither the balances nor the history of the client are loaded back
+        let mut engine = connect(&url).unwrap().load().unwrap();
+        engine.load_history().unwrap();
+        assert!(!engine.clients.contains_key(&200));
+        assert!(!engine.transactions_history.contains_key(&200_000));
+        assert_eq!(engine.clients[&201].total, folded);
+    }
+
+    #[test]
+    #[ignore]
+    fn concurrent_saves() {
+        let url = std::env::var("PAYMENTS_ENGINE_TEST_POSTGRES").unwrap();
+        let deposit = |client_id: u16, tx: u32| Transaction {
+            category: TransactionCategory::Deposit,
+            client_id,
+            tx,
+            amount: Some(1.0),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        };
+        let store = connect(&url).unwrap();
+        let mut engine = store.load().unwrap();
+        engine.process(&deposit(100, 100_000)).unwrap();
+        engine.process(&deposit(101, 100_001)).unwrap();
+        store.save(&engine).unwrap();
+
+        let (first, second) = (connect(&url).unwrap(), connect(&url).unwrap());
+        let mut first_engine = first.load().unwrap();
+        let mut second_engine = second.load().unwrap();
+        first_engine.process(&deposit(100, 100_002)).unwrap();
+        first.save(&first_engine).unwrap();
+
+        // Client 101 alone doesn't conflict, client 100 was saved by the first instance
+        second_engine.process(&deposit(101, 100_003)).unwrap();
+        second.save(&second_engine).unwrap();
+        second_engine.process(&deposit(100, 100_004)).unwrap();
+        let error = second.save(&second_engine).unwrap_err();
+        assert_eq!(error.downcast_ref::<Conflict>().unwrap().clients, vec![100]);
+
+        let engine = connect(&url).unwrap().load().unwrap();
+        let total = engine.clients[&100].total;
+        assert_eq!(total, first_engine.clients[&100].total);
+    }
+}
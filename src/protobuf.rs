@@ -0,0 +1,239 @@
+use crate::{Transaction, TransactionCategory};
+use std::error::Error;
+use std::io::{BufReader, ErrorKind, Read};
+use std::net::TcpStream;
+
+// A transaction message is a few dozen bytes : anything larger is a corrupt or hostile frame,
+// refused before allocating its buffer
+pub const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+// Hand written equivalent of proto/transaction.proto, so we don't need protoc at build time
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtoTransaction {
+    #[prost(enumeration = "ProtoCategory", tag = "1")]
+    category: i32,
+    #[prost(uint32, tag = "2")]
+    client: u32,
+    #[prost(uint32, tag = "3")]
+    tx: u32,
+    #[prost(double, optional, tag = "4")]
+    amount: Option<f64>,
+    #[prost(string, optional, tag = "5")]
+    reason: Option<String>,
+    #[prost(uint64, optional, tag = "6")]
+    timestamp: Option<u64>,
+    #[prost(string, optional, tag = "7")]
+    wallet: Option<String>,
+    #[prost(uint64, optional, tag = "8")]
+    seq: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+enum ProtoCategory {
+    Deposit = 0,
+    Withdrawal = 1,
+    Dispute = 2,
+    Resolve = 3,
+    Chargeback = 4,
+    Review = 5,
+    Freeze = 6,
+    Unfreeze = 7,
+    Adjustment = 8,
+    Place = 9,
+    Release = 10,
+    Capture = 11,
+    Transfer = 12,
+    Bonus = 13,
+    OpeningBalance = 14,
+    AssertBalance = 15,
+    Close = 16,
+}
+
+impl TryFrom<ProtoTransaction> for Transaction {
+    type Error = String;
+
+    fn try_from(proto: ProtoTransaction) -> Result<Self, Self::Error> {
+        let category = match ProtoCategory::try_from(proto.category) {
+            Ok(ProtoCategory::Deposit) => TransactionCategory::Deposit,
+            Ok(ProtoCategory::Withdrawal) => TransactionCategory::Withdrawal,
+            Ok(ProtoCategory::Dispute) => TransactionCategory::Dispute,
+            Ok(ProtoCategory::Resolve) => TransactionCategory::Resolve,
+            Ok(ProtoCategory::Chargeback) => TransactionCategory::Chargeback,
+            Ok(ProtoCategory::Review) => TransactionCategory::Review,
+            Ok(ProtoCategory::Freeze) => TransactionCategory::Freeze,
+            Ok(ProtoCategory::Unfreeze) => TransactionCategory::Unfreeze,
+            Ok(ProtoCategory::Adjustment) => TransactionCategory::Adjustment,
+            Ok(ProtoCategory::Place) => TransactionCategory::Place,
+            Ok(ProtoCategory::Release) => TransactionCategory::Release,
+            Ok(ProtoCategory::Capture) => TransactionCategory::Capture,
+            Ok(ProtoCategory::Transfer) => TransactionCategory::Transfer,
+            Ok(ProtoCategory::Bonus) => TransactionCategory::Bonus,
+            Ok(ProtoCategory::OpeningBalance) => TransactionCategory::OpeningBalance,
+            Ok(ProtoCategory::AssertBalance) => TransactionCategory::AssertBalance,
+            Ok(ProtoCategory::Close) => TransactionCategory::Close,
+            Err(_) => return Err(format!("Unknown transaction type {}", proto.category)),
+        };
+        let client_id = u16::try_from(proto.client)
+            .map_err(|_| format!("Client id {} doesn't fit in a u16", proto.client))?;
+        Ok(Transaction {
+            category,
+            client_id,
+            tx: proto.tx,
+            amount: proto.amount,
+            reason: proto.reason,
+            timestamp: proto.timestamp,
+            wallet: proto.wallet,
+            seq: proto.seq,
+        })
+    }
+}
+
+// Connects to a producer and reads messages until it closes the connection
+pub fn get_transactions_from_socket(address: &str) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    read_transactions(TcpStream::connect(address)?)
+}
+
+pub fn read_transactions<R: Read>(reader: R) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let mut reader = BufReader::new(reader);
+    let mut transactions = Vec::new();
+    let mut buffer = Vec::new();
+    while let Some(length) = read_length_delimiter(&mut reader)? {
+        if length > MAX_MESSAGE_BYTES {
+            return Err(format!(
+                "Incorrect protobuf message : {}. Its length of {} bytes is over the maximum of {}",
+                transactions.len() + 1,
+                length,
+                MAX_MESSAGE_BYTES
+            )
+            .into());
+        }
+        buffer.resize(length, 0);
+        reader.read_exact(&mut buffer)?;
+        let proto = <ProtoTransaction as prost::Message>::decode(buffer.as_slice())?;
+        let transaction = Transaction::try_from(proto).map_err(|e| {
+            format!(
+                "Incorrect protobuf message : {}. {}",
+                transactions.len() + 1,
+                e
+            )
+        })?;
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+// Returns None on a clean end of stream, i.e. when no byte of the varint was read
+fn read_length_delimiter<R: Read>(reader: &mut R) -> Result<Option<usize>, Box<dyn Error>> {
+    let mut length: u64 = 0;
+    for i in 0..10 {
+        let mut byte = [0u8; 1];
+        if let Err(e) = reader.read_exact(&mut byte) {
+            if i == 0 && e.kind() == ErrorKind::UnexpectedEof {
+                return Ok(None);
+            }
+            return Err(e.into());
+        }
+        length |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(usize::try_from(length)?));
+        }
+    }
+    Err("Invalid length delimiter in protobuf stream".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    fn encode(messages: &[ProtoTransaction]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for message in messages {
+            message.encode_length_delimited(&mut buffer).unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn read_length_delimited_stream() {
+        let buffer = encode(&[
+            ProtoTransaction {
+                category: ProtoCategory::Deposit as i32,
+                client: 1,
+                tx: 1,
+                amount: Some(1.5),
+                reason: None,
+                timestamp: Some(1700000000),
+                wallet: None,
+                seq: None,
+            },
+            ProtoTransaction {
+                category: ProtoCategory::Dispute as i32,
+                client: 1,
+                tx: 1,
+                amount: None,
+                reason: Some("fraud".to_string()),
+                timestamp: None,
+                wallet: None,
+                seq: None,
+            },
+        ]);
+        let transactions = read_transactions(buffer.as_slice()).unwrap();
+
+        assert_eq!(transactions.len(), 2);
+        assert!(matches!(
+            transactions[0].category,
+            TransactionCategory::Deposit
+        ));
+        assert_eq!(transactions[0].amount, Some(1.5));
+        assert!(matches!(
+            transactions[1].category,
+            TransactionCategory::Dispute
+        ));
+        assert_eq!(transactions[0].timestamp, Some(1700000000));
+        assert_eq!(transactions[1].amount, None);
+        assert_eq!(transactions[1].reason.as_deref(), Some("fraud"));
+    }
+
+    #[test]
+    fn oversized_message() {
+        // Length prefix of 4GiB, without the message
+        let buffer = [0x80, 0x80, 0x80, 0x80, 0x10];
+        let error = read_transactions(buffer.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("over the maximum"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_client_id() {
+        let buffer = encode(&[ProtoTransaction {
+            category: ProtoCategory::Deposit as i32,
+            client: 70000,
+            tx: 1,
+            amount: Some(1.0),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        }]);
+        read_transactions(buffer.as_slice()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn truncated_stream() {
+        let mut buffer = encode(&[ProtoTransaction {
+            category: ProtoCategory::Deposit as i32,
+            client: 1,
+            tx: 1,
+            amount: Some(1.0),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        }]);
+        buffer.pop();
+        read_transactions(buffer.as_slice()).unwrap();
+    }
+}
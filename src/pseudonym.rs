@@ -0,0 +1,64 @@
+use crate::signature;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+// Stable keyed pseudonym of a client id : the first 16 hex digits of its HMAC-SHA256.
+// The same key always gives the same pseudonym, and without the key it can't be traced back.
+pub fn pseudonym(key: &[u8], client_id: u16) -> String {
+    signature::sign(key, client_id.to_string().as_bytes())[..16].to_string()
+}
+
+// Same clients keyed by pseudonym, along with the pseudonym of each client id
+pub fn pseudonymize<T: Clone>(
+    key: &[u8],
+    clients: &HashMap<u16, T>,
+) -> (HashMap<String, T>, Vec<(String, u16)>) {
+    let mut mapping: Vec<(String, u16)> = clients
+        .keys()
+        .map(|client_id| (pseudonym(key, *client_id), *client_id))
+        .collect();
+    mapping.sort();
+    let pseudonymized = mapping
+        .iter()
+        .map(|(pseudonym, client_id)| (pseudonym.clone(), clients[client_id].clone()))
+        .collect();
+    (pseudonymized, mapping)
+}
+
+// pseudonym,client csv, to be kept internally to trace a pseudonym back to its client
+pub fn write_mapping<W: Write>(writer: W, mapping: &[(String, u16)]) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(["pseudonym", "client"])?;
+    for row in mapping {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn read_mapping<R: Read>(reader: R) -> Result<Vec<(String, u16)>, csv::Error> {
+    csv::Reader::from_reader(reader).deserialize().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_keyed_pseudonyms() {
+        let clients = HashMap::from([(1, "a"), (2, "b")]);
+        let (pseudonymized, mapping) = pseudonymize(b"key", &clients);
+        assert_eq!(pseudonymized.len(), 2);
+        assert_eq!(pseudonym(b"key", 1), pseudonym(b"key", 1));
+        assert_ne!(pseudonym(b"key", 1), pseudonym(b"other key", 1));
+        assert_eq!(pseudonymized[&pseudonym(b"key", 2)], "b");
+        assert_eq!(pseudonym(b"key", 2).len(), 16);
+
+        let mut output = Vec::new();
+        write_mapping(&mut output, &mapping).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("pseudonym,client\n"));
+        assert!(output.contains(&format!("{},1\n", pseudonym(b"key", 1))));
+        assert_eq!(read_mapping(output.as_bytes()).unwrap(), mapping);
+    }
+}
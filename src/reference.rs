@@ -0,0 +1,312 @@
+use crate::concurrent::ConcurrentEngine;
+use crate::{Client, Engine, Outcome, Transaction, TransactionCategory, ASSERTION_TOLERANCE};
+use std::collections::BTreeMap;
+
+const COMPARED_SHARDS: usize = 8;
+
+// Deliberately simple version of the rules, without rollback, rules or sharding, to compare
+// the engines against. It should only change along with the rules themselves.
+#[derive(Default)]
+pub struct ReferenceEngine {
+    clients: BTreeMap<u16, Client>,
+    history: BTreeMap<u32, Transaction>,
+    // Client and amount of each ongoing dispute, and whether it is under review, by tx id
+    disputes: BTreeMap<u32, (u16, f64, bool)>,
+    // Escrowed amount by client and bucket
+    escrow: BTreeMap<(u16, String), f64>,
+}
+
+impl ReferenceEngine {
+    pub fn clients(&self) -> &BTreeMap<u16, Client> {
+        &self.clients
+    }
+
+    // Ok(true) when applied, Ok(false) when ignored, Err when the batch would stop
+    pub fn process(&mut self, t: &Transaction) -> Result<bool, String> {
+        if t.wallet().is_some()
+            || matches!(
+                t.category,
+                TransactionCategory::Transfer | TransactionCategory::Bonus
+            )
+        {
+            return Err("Wallets aren't modelled by the reference".to_string());
+        }
+        if let TransactionCategory::Custom(name) = t.category {
+            return Err(format!(
+                "{} transactions aren't modelled by the reference",
+                name
+            ));
+        }
+        let mut client = self.clients.get(&t.client_id).cloned().unwrap_or_default();
+        if client.locked && t.category != TransactionCategory::AssertBalance {
+            self.clients.insert(t.client_id, client);
+            return Ok(false);
+        }
+        let applied = match t.category {
+            TransactionCategory::Deposit => {
+                let amount = t.amount.ok_or("Deposit without amount")?;
+                if amount <= 0.0 {
+                    return Err("Cannot deposit a negative amount".to_string());
+                }
+                client.available += amount;
+                client.total += amount;
+                if client.total > f64::MAX {
+                    return Err("You are getting way too rich".to_string());
+                }
+                self.history.insert(t.tx, t.clone());
+                true
+            }
+            TransactionCategory::Withdrawal => {
+                let amount = t.amount.ok_or("Withdrawal without amount")?;
+                if amount <= 0.0 {
+                    return Err("Cannot withdraw a negative amount".to_string());
+                }
+                if !client.frozen && amount < client.available {
+                    client.available -= amount;
+                    client.total -= amount;
+                    self.history.insert(t.tx, t.clone());
+                    true
+                } else {
+                    false
+                }
+            }
+            TransactionCategory::Dispute => match self.history.get(&t.tx) {
+                Some(disputed)
+                    if !self.disputes.contains_key(&t.tx)
+                        && disputed.category == TransactionCategory::Deposit
+                        && disputed.client_id == t.client_id =>
+                {
+                    let amount = disputed.amount.unwrap_or_default();
+                    client.available -= amount;
+                    client.held += amount;
+                    self.disputes.insert(t.tx, (t.client_id, amount, false));
+                    true
+                }
+                _ => false,
+            },
+            // Reviews only change the state of the dispute
+            TransactionCategory::Review => match self.disputes.get_mut(&t.tx) {
+                Some((client_id, _, under_review))
+                    if *client_id == t.client_id && !*under_review =>
+                {
+                    *under_review = true;
+                    true
+                }
+                _ => false,
+            },
+            TransactionCategory::Resolve => match self.disputes.get(&t.tx) {
+                Some(&(client_id, amount, _)) if client_id == t.client_id => {
+                    self.disputes.remove(&t.tx);
+                    client.available += amount;
+                    client.held -= amount;
+                    true
+                }
+                _ => false,
+            },
+            TransactionCategory::Chargeback => match self.disputes.get(&t.tx) {
+                Some(&(client_id, amount, _)) if client_id == t.client_id => {
+                    self.disputes.remove(&t.tx);
+                    client.held -= amount;
+                    client.total -= amount;
+                    client.locked = true;
+                    true
+                }
+                _ => false,
+            },
+            TransactionCategory::Freeze => !std::mem::replace(&mut client.frozen, true),
+            TransactionCategory::Unfreeze => std::mem::replace(&mut client.frozen, false),
+            // Without the house account of the default config, nothing is swept
+            TransactionCategory::Close if client.held != 0.0 => false,
+            TransactionCategory::Close => {
+                client.locked = true;
+                true
+            }
+            // Compared with the default config, which doesn't allow adjustments nor opening
+            // balances
+            TransactionCategory::Adjustment | TransactionCategory::OpeningBalance => false,
+            TransactionCategory::AssertBalance => {
+                let available = t.amount.ok_or("Balance assertion without amount")?;
+                let total = match t.reason.as_deref().filter(|r| !r.is_empty()) {
+                    Some(total) => Some(total.parse::<f64>().map_err(|e| e.to_string())?),
+                    None => None,
+                };
+                (available - client.available).abs() < ASSERTION_TOLERANCE
+                    && total.is_none_or(|total| (total - client.total).abs() < ASSERTION_TOLERANCE)
+            }
+            TransactionCategory::Transfer
+            | TransactionCategory::Bonus
+            | TransactionCategory::Custom(_) => unreachable!(),
+            TransactionCategory::Place
+            | TransactionCategory::Release
+            | TransactionCategory::Capture => {
+                let bucket = t.reason.clone().filter(|bucket| !bucket.is_empty());
+                let frozen = t.category == TransactionCategory::Capture && client.frozen;
+                let (Some(bucket), false) = (bucket, frozen) else {
+                    self.clients.insert(t.client_id, client);
+                    return Ok(false);
+                };
+                if t.amount.is_some_and(|amount| amount <= 0.0) {
+                    return Err("Cannot escrow a negative amount".to_string());
+                }
+                let key = (t.client_id, bucket);
+                let escrowed = self.escrow.get(&key).copied();
+                match (t.category, t.amount, escrowed) {
+                    (TransactionCategory::Place, None, _) => {
+                        return Err("Place without amount".to_string())
+                    }
+                    (TransactionCategory::Place, Some(amount), _) if amount <= client.available => {
+                        client.available -= amount;
+                        client.held += amount;
+                        self.escrow
+                            .insert(key, escrowed.unwrap_or_default() + amount);
+                        true
+                    }
+                    (
+                        TransactionCategory::Release | TransactionCategory::Capture,
+                        _,
+                        Some(escrowed),
+                    ) if t.amount.unwrap_or(escrowed) <= escrowed => {
+                        let amount = t.amount.unwrap_or(escrowed);
+                        if escrowed - amount <= 0.0 {
+                            self.escrow.remove(&key);
+                        } else {
+                            self.escrow.insert(key, escrowed - amount);
+                        }
+                        client.held -= amount;
+                        if t.category == TransactionCategory::Release {
+                            client.available += amount;
+                        } else {
+                            client.total -= amount;
+                        }
+                        true
+                    }
+                    _ => false,
+                }
+            }
+        };
+        self.clients.insert(t.client_id, client);
+        Ok(applied)
+    }
+}
+
+// Runs the engine, the concurrent engine and the reference on the same transactions,
+// and lists every difference : transactions applied by one and not the other, and final
+// client states differing once formatted like the output
+pub fn compare(transactions: &[Transaction]) -> Vec<String> {
+    let mut differences = Vec::new();
+    let mut engine = Engine::default();
+    let concurrent = ConcurrentEngine::with_shards(COMPARED_SHARDS);
+    let mut reference = ReferenceEngine::default();
+    for (i, t) in transactions.iter().enumerate() {
+        let expected = reference.process(t);
+        for (name, outcome) in [
+            ("engine", engine.process(t)),
+            ("concurrent", concurrent.submit(t)),
+        ] {
+            let outcome = outcome.map(|event| event.outcome == Outcome::Applied);
+            if outcome != expected {
+                differences.push(format!(
+                    "row {} ({} of tx {} for client {}) : {} gave {:?}, the reference {:?}",
+                    i + 1,
+                    t.category.as_str(),
+                    t.tx,
+                    t.client_id,
+                    name,
+                    outcome,
+                    expected
+                ));
+            }
+        }
+    }
+
+    let concurrent_clients = concurrent.clients();
+    for (name, clients) in [
+        ("engine", engine.clients()),
+        ("concurrent", &concurrent_clients),
+    ] {
+        if clients.len() != reference.clients.len() {
+            differences.push(format!(
+                "{} has {} clients, the reference {}",
+                name,
+                clients.len(),
+                reference.clients.len()
+            ));
+        }
+        for (client_id, expected) in &reference.clients {
+            let actual = clients.get(client_id).map(format_client);
+            if actual.as_deref() != Some(format_client(expected).as_str()) {
+                differences.push(format!(
+                    "client {} : {} has {:?}, the reference {}",
+                    client_id,
+                    name,
+                    actual,
+                    format_client(expected)
+                ));
+            }
+        }
+    }
+    differences
+}
+
+fn format_client(client: &Client) -> String {
+    format!(
+        "{:.4},{:.4},{:.4},{},{}",
+        client.available, client.held, client.total, client.locked, client.frozen
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, simulation};
+
+    #[test]
+    fn engines_agree_with_the_reference() {
+        for file in [
+            "providedExample.csv",
+            "dispute.csv",
+            "trickyDispute.csv",
+            "trickyResolve.csv",
+            "chargeback.csv",
+            "disputeLifecycle.csv",
+            "freeze.csv",
+            "escrow.csv",
+        ] {
+            let transactions =
+                get_transactions_from_file(&format!("src/testSamples/{}", file)).unwrap();
+            assert_eq!(compare(&transactions), Vec::<String>::new(), "{}", file);
+        }
+        for seed in 0..10 {
+            let transactions = simulation::workload(seed, 2_000);
+            assert_eq!(
+                compare(&transactions),
+                Vec::<String>::new(),
+                "seed {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn differences_are_reported() {
+        // Each shard of the concurrent engine keeps its own history, so a tx id reused by a
+        // client of another shard doesn't replace the first transaction there
+        let transactions = crate::read_transactions(
+            "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,1,2.0
+dispute,1,1,
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let differences = compare(&transactions);
+        assert_eq!(
+            differences[0],
+            "row 3 (dispute of tx 1 for client 1) : concurrent gave Ok(true), the reference Ok(false)"
+        );
+        assert!(differences[1..]
+            .iter()
+            .all(|difference| difference.contains("concurrent")));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::table_output::write_clients_table;
+use crate::{Dispute, Engine, Outcome, Transaction, TransactionCategory};
+use std::io::{BufRead, Write};
+
+const HELP: &str = "Commands :
+  deposit <client> <tx> <amount>
+  withdrawal <client> <tx> <amount>
+  dispute <client> <tx>
+  resolve <client> <tx>
+  chargeback <client> <tx>
+  review <client> <tx>     move a dispute under review
+  freeze <client>          block the withdrawals of a client
+  unfreeze <client>
+  show <client>            balances of a client
+  disputes                 transactions currently under dispute
+  undo                     revert the last accepted transaction
+  rollback <n>             revert the last n accepted transactions
+  dump                     state of every client
+  help
+  quit";
+
+const QUERY_HELP: &str = "Commands :
+  show <client>            balances of a client
+  history <client>         recorded transactions of a client
+  disputes                 transactions currently under dispute
+  dump                     state of every client
+  help
+  quit";
+
+// Reads commands line by line until quit or end of input. The whole session can be undone.
+pub fn run<R: BufRead, W: Write>(input: R, output: &mut W) -> Result<(), std::io::Error> {
+    let mut engine = Engine::with_rollback_capacity(usize::MAX);
+    writeln!(output, "Type help to list the commands")?;
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            ["quit"] | ["exit"] => return Ok(()),
+            ["help"] => writeln!(output, "{}", HELP)?,
+            ["show", client_id] => show(&engine, client_id, output)?,
+            ["disputes"] => write_disputes(&engine, output)?,
+            ["undo"] => rollback(&mut engine, 1, output)?,
+            ["rollback", n] => match n.parse::<usize>() {
+                Ok(n) => rollback(&mut engine, n, output)?,
+                Err(e) => writeln!(output, "Invalid number of transactions : {}", e)?,
+            },
+            ["dump"] => write_clients_table(output, &engine.clients, false, None)?,
+            [category, arguments @ ..] => match parse_transaction(category, arguments) {
+                Ok(t) => match engine.process(&t) {
+                    Ok(event) => match event.outcome {
+                        Outcome::Applied => match event.rule {
+                            Some(rule) => writeln!(output, "Applied, flagged by {}", rule)?,
+                            None => writeln!(output, "Applied")?,
+                        },
+                        Outcome::Held => {
+                            writeln!(output, "Held by {}", event.rule.unwrap_or_default())?
+                        }
+                        Outcome::Deferred => {
+                            writeln!(output, "Deferred until the funds are available")?
+                        }
+                        Outcome::Ignored(reason) => writeln!(output, "Ignored : {}", reason)?,
+                    },
+                    Err(e) => writeln!(output, "Rejected : {}", e)?,
+                },
+                Err(e) => writeln!(output, "{}", e)?,
+            },
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// Same prompt over a persisted state, with only the commands reading it : the engine is
+// borrowed immutably, nothing typed here can apply a transaction or be saved
+pub fn query<R: BufRead, W: Write>(
+    engine: &Engine,
+    input: R,
+    output: &mut W,
+) -> Result<(), std::io::Error> {
+    writeln!(output, "Read-only, type help to list the commands")?;
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            ["quit"] | ["exit"] => return Ok(()),
+            ["help"] => writeln!(output, "{}", QUERY_HELP)?,
+            ["show", client_id] => show(engine, client_id, output)?,
+            ["history", client_id] => write_history(engine, client_id, output)?,
+            ["disputes"] => write_disputes(engine, output)?,
+            ["dump"] => write_clients_table(output, &engine.clients, false, None)?,
+            [command, ..] if TransactionCategory::built_in(command).is_some() => writeln!(
+                output,
+                "The state is read-only, {} isn't available",
+                command
+            )?,
+            [command, ..] => writeln!(output, "Unknown command {}, type help", command)?,
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn show<W: Write>(engine: &Engine, client_id: &str, output: &mut W) -> Result<(), std::io::Error> {
+    let client_id = match client_id.parse::<u16>() {
+        Ok(client_id) => client_id,
+        Err(e) => return writeln!(output, "Invalid client id : {}", e),
+    };
+    let Some(client) = engine.clients.get(&client_id) else {
+        return writeln!(output, "Unknown client {}", client_id);
+    };
+    writeln!(
+        output,
+        "client {} : available {:.4}, held {:.4}, total {:.4}, locked {}, frozen {}",
+        client_id, client.available, client.held, client.total, client.locked, client.frozen
+    )?;
+    for (bucket, amount) in &client.escrow {
+        writeln!(output, "  escrow {} : {:.4}", bucket, amount)?;
+    }
+    Ok(())
+}
+
+fn write_history<W: Write>(
+    engine: &Engine,
+    client_id: &str,
+    output: &mut W,
+) -> Result<(), std::io::Error> {
+    let client_id = match client_id.parse::<u16>() {
+        Ok(client_id) => client_id,
+        Err(e) => return writeln!(output, "Invalid client id : {}", e),
+    };
+    let mut history: Vec<&Transaction> = engine
+        .transactions_history
+        .values()
+        .filter(|t| t.client_id == client_id)
+        .collect();
+    history.sort_by_key(|t| t.tx);
+    if history.is_empty() {
+        return writeln!(output, "No recorded transaction for client {}", client_id);
+    }
+    for t in history {
+        writeln!(
+            output,
+            "tx {} : {} {:.4}",
+            t.tx,
+            t.category.as_str(),
+            t.amount.unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+fn write_disputes<W: Write>(engine: &Engine, output: &mut W) -> Result<(), std::io::Error> {
+    let mut disputes: Vec<&Dispute> = engine.ongoing_disputes.values().collect();
+    disputes.sort_by_key(|d| d.tx);
+    for dispute in disputes {
+        writeln!(
+            output,
+            "tx {} : client {}, amount {:.4}, {}",
+            dispute.tx,
+            dispute.client_id,
+            dispute.amount,
+            dispute.state.as_str()
+        )?;
+    }
+    Ok(())
+}
+
+fn rollback<W: Write>(engine: &mut Engine, n: usize, output: &mut W) -> Result<(), std::io::Error> {
+    let reverted = engine.rollback(n);
+    if reverted.is_empty() {
+        return writeln!(output, "Nothing to undo");
+    }
+    for t in reverted {
+        writeln!(
+            output,
+            "Undone {} of tx {} for client {}",
+            t.category.as_str(),
+            t.tx,
+            t.client_id
+        )?;
+    }
+    Ok(())
+}
+
+fn parse_transaction(category: &str, arguments: &[&str]) -> Result<Transaction, String> {
+    let category = match category {
+        "deposit" => TransactionCategory::Deposit,
+        "withdrawal" | "withdraw" => TransactionCategory::Withdrawal,
+        "dispute" => TransactionCategory::Dispute,
+        "resolve" => TransactionCategory::Resolve,
+        "chargeback" => TransactionCategory::Chargeback,
+        "review" => TransactionCategory::Review,
+        "freeze" => TransactionCategory::Freeze,
+        "unfreeze" => TransactionCategory::Unfreeze,
+        other => return Err(format!("Unknown command {}, type help", other)),
+    };
+    let needs_amount = matches!(
+        category,
+        TransactionCategory::Deposit | TransactionCategory::Withdrawal
+    );
+    let admin = matches!(
+        category,
+        TransactionCategory::Freeze | TransactionCategory::Unfreeze
+    );
+    let (client_id, tx, amount) = match (needs_amount, arguments) {
+        (false, [client_id]) if admin => (client_id, &"0", None),
+        (false, _) if admin => return Err(format!("Usage : {} <client>", category.as_str())),
+        (true, [client_id, tx, amount]) => (client_id, tx, Some(amount)),
+        (false, [client_id, tx]) => (client_id, tx, None),
+        (true, _) => {
+            return Err(format!(
+                "Usage : {} <client> <tx> <amount>",
+                category.as_str()
+            ))
+        }
+        (false, _) => return Err(format!("Usage : {} <client> <tx>", category.as_str())),
+    };
+    Ok(Transaction {
+        category,
+        client_id: client_id
+            .parse()
+            .map_err(|e| format!("Invalid client id : {}", e))?,
+        tx: tx.parse().map_err(|e| format!("Invalid tx id : {}", e))?,
+        amount: amount
+            .map(|amount| amount.parse())
+            .transpose()
+            .map_err(|e| format!("Invalid amount : {}", e))?,
+        reason: None,
+        timestamp: None,
+        wallet: None,
+        seq: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_script(script: &str) -> String {
+        let mut output = Vec::new();
+        run(script.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn apply_and_inspect() {
+        let output = run_script(
+            "deposit 1 1 5.0
+withdrawal 1 2 10.0
+dispute 1 1
+review 1 1
+disputes
+show 1",
+        );
+        assert!(output.contains("> Applied\n> Ignored : Insufficient available funds\n> Applied"));
+        assert!(output.contains("tx 1 : client 1, amount 5.0000, under_review"));
+        assert!(output.contains(
+            "client 1 : available 0.0000, held 5.0000, total 5.0000, locked false, frozen false"
+        ));
+    }
+
+    #[test]
+    fn undo_last_transaction() {
+        let output = run_script(
+            "deposit 1 1 5.0
+deposit 1 2 1.0
+dispute 1 1
+chargeback 1 1
+undo
+undo
+show 1
+rollback 5
+undo",
+        );
+        assert!(output.contains("Undone chargeback of tx 1 for client 1"));
+        assert!(output.contains("Undone dispute of tx 1 for client 1"));
+        assert!(output.contains("Undone deposit of tx 2 for client 1\nUndone deposit of tx 1"));
+        assert!(output.contains(
+            "client 1 : available 6.0000, held 0.0000, total 6.0000, locked false, frozen false"
+        ));
+        assert!(output.contains("Nothing to undo"));
+    }
+
+    #[test]
+    fn read_only_queries() {
+        let mut engine = Engine::default();
+        for t in crate::get_transactions_from_file("src/testSamples/dispute.csv").unwrap() {
+            engine.process(&t).unwrap();
+        }
+        let mut output = Vec::new();
+        let script = "history 1\ndeposit 1 9 5.0\nshow 1\ndisputes";
+        query(&engine, script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("tx 1 : deposit "));
+        assert!(output.contains("The state is read-only, deposit isn't available"));
+        assert!(output.contains("client 1 : available "));
+        assert!(output.contains("tx 1 : client 1, amount 1.0000, opened"));
+        assert!(!engine.transactions_history.contains_key(&9));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let output = run_script(
+            "deposit 1 1
+deposit 1 1 -2.0
+transfer 1 2
+show 1",
+        );
+        assert!(output.contains("Usage : deposit <client> <tx> <amount>"));
+        assert!(output.contains("Rejected : Cannot deposit a negative amount"));
+        assert!(output.contains("Unknown command transfer, type help"));
+    }
+}
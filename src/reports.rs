@@ -0,0 +1,818 @@
+use crate::risk::{self, RiskModel};
+use crate::{Client, Dispute, DisputeState, Engine, Transaction, TransactionCategory};
+use serde::Serialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::io::Write;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Ongoing dispute and its age in days, if it was opened with a timestamp
+type AgedDispute<'a> = (&'a Dispute, Option<u64>);
+
+// One csv row per dispute record : tx,client,amount,state,reason,opened_at,closed_at,shortfall
+// with the amount held by the dispute, and how much less than the disputed transaction it is
+// when the history has it (see InsufficientFunds::Cap)
+pub fn write_disputes_report<W: Write>(writer: W, engine: &Engine) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "tx",
+        "client",
+        "amount",
+        "state",
+        "reason",
+        "opened_at",
+        "closed_at",
+        "shortfall",
+    ])?;
+    for dispute in engine.disputes() {
+        let disputed = engine
+            .transactions_history
+            .get(&dispute.tx)
+            .and_then(|t| t.amount);
+        wtr.write_record([
+            dispute.tx.to_string(),
+            dispute.client_id.to_string(),
+            format!("{:.4}", dispute.amount),
+            dispute.state.as_str().to_string(),
+            dispute.reason.clone().unwrap_or_default(),
+            dispute.opened_at.map(|t| t.to_string()).unwrap_or_default(),
+            dispute.closed_at.map(|t| t.to_string()).unwrap_or_default(),
+            disputed
+                .map(|disputed| format!("{:.4}", (disputed - dispute.amount).max(0.0)))
+                .unwrap_or_default(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// Funds an ongoing dispute holds, none when it is deferred
+fn held_by(dispute: &Dispute) -> f64 {
+    match dispute.state {
+        DisputeState::Deferred => 0.0,
+        _ => dispute.amount,
+    }
+}
+
+// Ongoing disputes grouped by age (0-7, 8-30 and 30+ days since they were opened, at `now` in
+// unix seconds), oldest first within a group, with the funds held by each group
+pub fn write_aging_report<W: Write>(
+    writer: &mut W,
+    engine: &Engine,
+    now: u64,
+) -> Result<(), std::io::Error> {
+    let mut buckets: [(&str, Vec<AgedDispute>); 4] = AGE_BUCKETS.map(|name| (name, Vec::new()));
+    for dispute in engine.ongoing_disputes.values() {
+        let (bucket, age) = age_bucket(dispute, now);
+        buckets[bucket].1.push((dispute, age));
+    }
+    for (name, disputes) in &mut buckets {
+        disputes.sort_by_key(|(dispute, age)| (std::cmp::Reverse(*age), dispute.tx));
+        writeln!(
+            writer,
+            "{} : {} disputes, {:.4} held",
+            name,
+            disputes.len(),
+            disputes
+                .iter()
+                .fold(0.0, |total, (d, _)| total + held_by(d))
+        )?;
+        for (dispute, age) in disputes.iter() {
+            writeln!(
+                writer,
+                "  tx {} : client {}, {:.4}, {}{}",
+                dispute.tx,
+                dispute.client_id,
+                dispute.amount,
+                dispute.state.as_str(),
+                age.map(|age| format!(", opened {} days ago", age))
+                    .unwrap_or_default()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Balances of a client as of a unix time, rebuilt from the history and the dispute records
+pub struct PointInTime {
+    // Not frozen : freezes aren't recorded
+    pub client: Client,
+    // History entries and disputes without a timestamp, which can't be placed in time and are
+    // left out
+    pub undated: usize,
+}
+
+// The history keeps a single entry per tx id, and held transactions are counted from their
+// timestamp once approved. Clawed back bonuses aren't recorded, they are still counted, and
+// neither are the residual balances swept to the house account.
+pub fn balance_at(engine: &Engine, client_id: u16, at: u64) -> PointInTime {
+    let mut client = Client::default();
+    let mut undated = 0;
+    let mut history: Vec<&Transaction> = Vec::new();
+    for t in engine.transactions_history.values() {
+        match t.timestamp {
+            _ if t.client_id != client_id => (),
+            Some(timestamp) if timestamp <= at => history.push(t),
+            Some(_) => (),
+            None => undated += 1,
+        }
+    }
+    // Releases and captures without an amount take what their bucket has at the time
+    history.sort_by_key(|t| (t.timestamp, t.tx));
+    for t in history {
+        let amount = t.amount.unwrap_or_default();
+        let bucket = t.reason.clone().unwrap_or_default();
+        match t.category {
+            TransactionCategory::Deposit
+            | TransactionCategory::Bonus
+            | TransactionCategory::Adjustment => {
+                client.available += amount;
+                client.total += amount;
+            }
+            TransactionCategory::Withdrawal => {
+                client.available -= amount;
+                client.total -= amount;
+            }
+            TransactionCategory::Place => {
+                client.available -= amount;
+                client.held += amount;
+                *client.escrow.entry(bucket).or_default() += amount;
+            }
+            // Only recorded when the residual balance was swept, from a client without held
+            // funds
+            TransactionCategory::Close => {
+                client.available = 0.0;
+                client.total = 0.0;
+            }
+            // Only applied to a client without held funds
+            TransactionCategory::OpeningBalance => {
+                client.available = amount;
+                client.total = amount + client.held;
+            }
+            TransactionCategory::Release | TransactionCategory::Capture => {
+                let escrowed = client.escrow.remove(&bucket).unwrap_or_default();
+                let amount = t.amount.unwrap_or(escrowed);
+                if escrowed - amount > 0.0 {
+                    client.escrow.insert(bucket, escrowed - amount);
+                }
+                client.held -= amount;
+                match t.category {
+                    TransactionCategory::Release => client.available += amount,
+                    _ => client.total -= amount,
+                }
+            }
+            _ => (),
+        }
+    }
+    let disputes = engine
+        .disputes()
+        .filter(|d| d.client_id == client_id && d.state != DisputeState::Deferred);
+    for dispute in disputes {
+        let Some(opened_at) = dispute.opened_at else {
+            undated += 1;
+            continue;
+        };
+        if opened_at > at {
+            continue;
+        }
+        client.held += dispute.amount;
+        if dispute.provisional_credit {
+            client.total += dispute.amount;
+        } else {
+            client.available -= dispute.amount;
+        }
+        let closed = match dispute.state {
+            DisputeState::Resolved | DisputeState::ChargedBack => dispute.closed_at,
+            _ => continue,
+        };
+        let Some(closed_at) = closed else {
+            undated += 1;
+            continue;
+        };
+        if closed_at > at {
+            continue;
+        }
+        client.held -= dispute.amount;
+        match (dispute.state, dispute.provisional_credit) {
+            (DisputeState::Resolved, false) | (DisputeState::ChargedBack, true) => {
+                client.available += dispute.amount
+            }
+            (DisputeState::Resolved, true) => client.total -= dispute.amount,
+            _ => {
+                client.total -= dispute.amount;
+                client.locked = true;
+            }
+        }
+    }
+    PointInTime { client, undated }
+}
+
+// One csv row per locked client : client,tx,amount,locked_at,available,held,total with the
+// chargeback that locked it and the balances right after it, rebuilt from the history. The
+// chargeback columns are empty when its dispute record is missing or the client was closed,
+// and the balances when it has no timestamp.
+pub fn write_locks_report<W: Write>(writer: W, engine: &Engine) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "client",
+        "tx",
+        "amount",
+        "locked_at",
+        "available",
+        "held",
+        "total",
+    ])?;
+    let mut locked: Vec<u16> = engine
+        .clients
+        .iter()
+        .filter(|(_, client)| client.locked)
+        .map(|(client_id, _)| *client_id)
+        .collect();
+    locked.sort_unstable();
+    for client_id in locked {
+        // Closed disputes come first in closing order, the first chargeback locked the client
+        let chargeback = engine.disputes().find(|d| {
+            d.client_id == client_id
+                && d.state == DisputeState::ChargedBack
+                && !d.provisional_credit
+        });
+        let balances = chargeback
+            .and_then(|d| d.closed_at)
+            .map(|at| balance_at(engine, client_id, at).client);
+        let amount = |amount: f64| format!("{:.4}", amount);
+        wtr.write_record([
+            client_id.to_string(),
+            chargeback.map(|d| d.tx.to_string()).unwrap_or_default(),
+            chargeback.map(|d| amount(d.amount)).unwrap_or_default(),
+            chargeback
+                .and_then(|d| d.closed_at)
+                .map(|at| at.to_string())
+                .unwrap_or_default(),
+            balances
+                .as_ref()
+                .map(|c| amount(c.available))
+                .unwrap_or_default(),
+            balances
+                .as_ref()
+                .map(|c| amount(c.held))
+                .unwrap_or_default(),
+            balances
+                .as_ref()
+                .map(|c| amount(c.total))
+                .unwrap_or_default(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// One csv row per deposit or withdrawal flagged by the anomaly detector during the run :
+// row,tx,client,type,amount,mean,std_dev,z_score
+pub fn write_anomalies_report<W: Write>(writer: W, engine: &Engine) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "row", "tx", "client", "type", "amount", "mean", "std_dev", "z_score",
+    ])?;
+    let amount = |amount: f64| format!("{:.4}", amount);
+    for anomaly in engine.anomalies() {
+        wtr.write_record([
+            anomaly.row.to_string(),
+            anomaly.tx.to_string(),
+            anomaly.client_id.to_string(),
+            anomaly.category.as_str().to_string(),
+            amount(anomaly.amount),
+            amount(anomaly.mean),
+            amount(anomaly.std_dev),
+            format!("{:.2}", anomaly.z_score),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// One csv row per client : client,chargebacks,dispute_ratio,withdrawals_last_day,age_days,score
+// as of `now`, the riskiest clients first
+pub fn write_risk_report<W: Write>(
+    writer: W,
+    engine: &Engine,
+    model: &dyn RiskModel,
+    now: u64,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "client",
+        "chargebacks",
+        "dispute_ratio",
+        "withdrawals_last_day",
+        "age_days",
+        "score",
+    ])?;
+    let mut scored: Vec<_> = risk::features(engine, now)
+        .into_iter()
+        .map(|(client_id, features)| (client_id, model.score(&features), features))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (client_id, score, features) in scored {
+        wtr.write_record([
+            client_id.to_string(),
+            features.chargebacks.to_string(),
+            format!("{:.4}", features.dispute_ratio),
+            features.withdrawals_last_day.to_string(),
+            features
+                .age_days
+                .map(|age| age.to_string())
+                .unwrap_or_default(),
+            format!("{:.2}", score),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+const AGE_BUCKETS: [&str; 4] = ["0-7 days", "8-30 days", "30+ days", "no timestamp"];
+
+// Index in AGE_BUCKETS of an ongoing dispute, and its age in days
+fn age_bucket(dispute: &Dispute, now: u64) -> (usize, Option<u64>) {
+    let age = dispute
+        .opened_at
+        .map(|opened_at| now.saturating_sub(opened_at) / DAY);
+    let bucket = match age {
+        Some(0..=7) => 0,
+        Some(8..=30) => 1,
+        Some(_) => 2,
+        None => 3,
+    };
+    (bucket, age)
+}
+
+// Funds held across all the clients at `now`, by source (ongoing disputes, escrow and
+// transactions held by a rule), the disputed ones by age as in the aging report, and by tier
+// of the client in the config
+pub fn write_exposure_report<W: Write>(
+    writer: &mut W,
+    engine: &Engine,
+    now: u64,
+) -> Result<(), std::io::Error> {
+    let held = engine.clients.values().fold(0.0, |held, c| held + c.held);
+    writeln!(
+        writer,
+        "Held funds : {:.4} across {} clients",
+        held,
+        engine.clients.values().filter(|c| c.held > 0.0).count()
+    )?;
+
+    let mut by_age = [0.0; 4];
+    for dispute in engine.ongoing_disputes.values() {
+        by_age[age_bucket(dispute, now).0] += held_by(dispute);
+    }
+    let escrow = engine
+        .clients
+        .values()
+        .flat_map(|c| c.escrow.values())
+        .fold(0.0, |held, amount| held + amount);
+    let rule_holds = engine
+        .held_transactions
+        .values()
+        .filter_map(|t| t.amount)
+        .fold(0.0, |held, amount| held + amount);
+    writeln!(writer, "By source :")?;
+    writeln!(writer, "  disputes : {:.4}", by_age.iter().sum::<f64>())?;
+    writeln!(writer, "  escrow : {:.4}", escrow)?;
+    writeln!(writer, "  rule holds : {:.4}", rule_holds)?;
+    writeln!(writer, "By dispute age :")?;
+    for (name, amount) in AGE_BUCKETS.iter().zip(by_age) {
+        writeln!(writer, "  {} : {:.4}", name, amount)?;
+    }
+
+    // Tiers by name, then the clients without one
+    let mut by_tier: BTreeMap<Option<&str>, f64> = BTreeMap::new();
+    for (client_id, client) in &engine.clients {
+        *by_tier.entry(engine.config.tier(*client_id)).or_default() += client.held;
+    }
+    writeln!(writer, "By tier :")?;
+    let untiered = by_tier.remove(&None);
+    for (tier, amount) in by_tier {
+        writeln!(writer, "  {} : {:.4}", tier.unwrap_or_default(), amount)?;
+    }
+    if let Some(amount) = untiered {
+        writeln!(writer, "  no tier : {:.4}", amount)?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+pub struct Stats {
+    pub categories: Vec<CategoryStats>,
+    pub top_by_total: Vec<ClientAmount>,
+    pub top_by_held: Vec<ClientAmount>,
+    // None when no deposit was recorded
+    pub deposit_amounts: Option<Distribution>,
+    pub clients: usize,
+    // Share of the clients that are locked, and of the disputes that were charged back
+    pub lock_rate: f64,
+    pub chargeback_rate: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CategoryStats {
+    pub category: &'static str,
+    pub count: usize,
+    pub volume: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ClientAmount {
+    pub client: u16,
+    pub amount: f64,
+}
+
+// Greater is ranked first : higher amount, then lower client id
+impl Ord for ClientAmount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.amount
+            .total_cmp(&other.amount)
+            .then(other.client.cmp(&self.client))
+    }
+}
+
+impl PartialOrd for ClientAmount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ClientAmount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ClientAmount {}
+
+// Percentiles use the nearest rank method
+#[derive(Serialize, Debug)]
+pub struct Distribution {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+// Computed from the engine state : deposits and withdrawals come from the history, the dispute
+// flow from the dispute records. Disputes under review are counted apart from the category
+// counts, as the records don't keep whether a closed dispute went through a review.
+pub fn stats(engine: &Engine, top: usize) -> Stats {
+    let mut categories = Vec::new();
+    for category in [
+        TransactionCategory::Deposit,
+        TransactionCategory::Withdrawal,
+    ] {
+        let amounts: Vec<f64> = engine
+            .transactions_history
+            .values()
+            .filter(|t| t.category == category)
+            .filter_map(|t| t.amount)
+            .collect();
+        categories.push(CategoryStats {
+            category: category.as_str(),
+            count: amounts.len(),
+            volume: amounts.iter().fold(0.0, |volume, amount| volume + amount),
+        });
+    }
+    let disputes: Vec<&Dispute> = engine.disputes().collect();
+    for (category, states) in [
+        ("dispute", &[][..]),
+        ("resolve", &[DisputeState::Resolved][..]),
+        ("chargeback", &[DisputeState::ChargedBack][..]),
+        ("under_review", &[DisputeState::UnderReview][..]),
+    ] {
+        let matching: Vec<&&Dispute> = disputes
+            .iter()
+            .filter(|d| states.is_empty() || states.contains(&d.state))
+            .collect();
+        categories.push(CategoryStats {
+            category,
+            count: matching.len(),
+            volume: matching.iter().fold(0.0, |volume, d| volume + d.amount),
+        });
+    }
+
+    let mut deposits: Vec<f64> = engine
+        .transactions_history
+        .values()
+        .filter(|t| t.category == TransactionCategory::Deposit)
+        .filter_map(|t| t.amount)
+        .collect();
+    deposits.sort_by(f64::total_cmp);
+    let percentile = |p: f64| deposits[((p * deposits.len() as f64).ceil() as usize).max(1) - 1];
+    let deposit_amounts = (!deposits.is_empty()).then(|| Distribution {
+        min: deposits[0],
+        p50: percentile(0.5),
+        p90: percentile(0.9),
+        p99: percentile(0.99),
+        max: deposits[deposits.len() - 1],
+    });
+
+    let clients = engine.clients.len();
+    let locked = engine.clients.values().filter(|c| c.locked).count();
+    let charged_back = disputes
+        .iter()
+        .filter(|d| d.state == DisputeState::ChargedBack)
+        .count();
+    Stats {
+        categories,
+        top_by_total: top_clients(engine, top, |c| c.total),
+        top_by_held: top_clients(engine, top, |c| c.held),
+        deposit_amounts,
+        clients,
+        lock_rate: rate(locked, clients),
+        chargeback_rate: rate(charged_back, disputes.len()),
+    }
+}
+
+// Highest amounts first, ties broken by client id, on the balances once the input is
+// processed. A single pass over the clients keeping the `top` best in a heap, so millions of
+// clients are never sorted.
+pub fn top_clients(
+    engine: &Engine,
+    top: usize,
+    amount: impl Fn(&Client) -> f64,
+) -> Vec<ClientAmount> {
+    // The worst of the kept clients on top
+    let mut heap: BinaryHeap<Reverse<ClientAmount>> = BinaryHeap::with_capacity(top + 1);
+    for (client_id, client) in &engine.clients {
+        let ranked = ClientAmount {
+            client: *client_id,
+            amount: amount(client),
+        };
+        if heap.len() < top {
+            heap.push(Reverse(ranked));
+        } else if heap.peek().is_some_and(|Reverse(worst)| ranked > *worst) {
+            heap.pop();
+            heap.push(Reverse(ranked));
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(ranked)| ranked)
+        .collect()
+}
+
+// Metric of `report top`
+fn client_metric(by: &str) -> Option<fn(&Client) -> f64> {
+    match by {
+        "available" => Some(|c| c.available),
+        "held" => Some(|c| c.held),
+        "total" => Some(|c| c.total),
+        _ => None,
+    }
+}
+
+// One csv row per client : client,<by> for the `top` clients with the highest amounts
+pub fn write_top_report<W: Write>(
+    writer: W,
+    engine: &Engine,
+    by: &str,
+    top: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let metric = client_metric(by).ok_or(format!("Unknown metric : {}", by))?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(["client", by])?;
+    for client in top_clients(engine, top, metric) {
+        wtr.write_record([client.client.to_string(), format!("{:.4}", client.amount)])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn rate(count: usize, out_of: usize) -> f64 {
+    if out_of == 0 {
+        0.0
+    } else {
+        count as f64 / out_of as f64
+    }
+}
+
+impl Stats {
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        writeln!(writer, "Transactions :")?;
+        for category in &self.categories {
+            writeln!(
+                writer,
+                "  {} : {}, {:.4}",
+                category.category, category.count, category.volume
+            )?;
+        }
+        for (title, clients) in [
+            ("Top clients by total :", &self.top_by_total),
+            ("Top clients by held :", &self.top_by_held),
+        ] {
+            writeln!(writer, "{}", title)?;
+            for client in clients {
+                writeln!(writer, "  client {} : {:.4}", client.client, client.amount)?;
+            }
+        }
+        match &self.deposit_amounts {
+            Some(d) => writeln!(
+                writer,
+                "Deposit amounts : min {:.4}, p50 {:.4}, p90 {:.4}, p99 {:.4}, max {:.4}",
+                d.min, d.p50, d.p90, d.p99, d.max
+            )?,
+            None => writeln!(writer, "Deposit amounts : no deposit")?,
+        }
+        writeln!(
+            writer,
+            "{} clients, {:.2}% locked, {:.2}% of disputes charged back",
+            self.clients,
+            self.lock_rate * 100.0,
+            self.chargeback_rate * 100.0
+        )
+    }
+
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn disputes_report() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let mut output = Vec::new();
+        write_disputes_report(&mut output, &engine).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "tx,client,amount,state,reason,opened_at,closed_at,shortfall
+1,1,1.0000,resolved,,1700000100,1700000400,0.0000
+2,2,2.0000,charged_back,fraud,1700000200,1700000500,0.0000
+3,1,3.0000,under_review,duplicate,1700000300,,0.0000
+"
+        );
+    }
+
+    #[test]
+    fn aging_report() {
+        let transactions = get_transactions_from_file("src/testSamples/disputeAging.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let mut output = Vec::new();
+        write_aging_report(&mut output, &engine, 1700000000 + 40 * DAY).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "0-7 days : 1 disputes, 4.0000 held
+  tx 4 : client 2, 4.0000, opened, opened 5 days ago
+8-30 days : 1 disputes, 2.0000 held
+  tx 2 : client 1, 2.0000, under_review, opened 10 days ago
+30+ days : 2 disputes, 4.0000 held
+  tx 3 : client 1, 3.0000, opened, opened 40 days ago
+  tx 1 : client 1, 1.0000, opened, opened 31 days ago
+no timestamp : 1 disputes, 5.0000 held
+  tx 5 : client 2, 5.0000, opened
+"
+        );
+    }
+
+    #[test]
+    fn balances_at_a_time() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let balances = |client_id, at| {
+            let PointInTime { client, undated } = balance_at(&engine, client_id, at);
+            assert_eq!(undated, 0);
+            (client.available, client.held, client.total, client.locked)
+        };
+        assert_eq!(balances(1, 1699999999), (0.0, 0.0, 0.0, false));
+        assert_eq!(balances(1, 1700000150), (3.0, 1.0, 4.0, false));
+        assert_eq!(balances(1, 1700000400), (1.0, 3.0, 4.0, false));
+        assert_eq!(balances(2, 1700000499), (0.0, 2.0, 2.0, false));
+        assert_eq!(balances(2, 1700000500), (0.0, 0.0, 0.0, true));
+        for (client_id, client) in &engine.clients {
+            assert_eq!(
+                balances(*client_id, u64::MAX),
+                (client.available, client.held, client.total, client.locked)
+            );
+        }
+    }
+
+    #[test]
+    fn locks_report() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let mut output = Vec::new();
+        write_locks_report(&mut output, &engine).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,tx,amount,locked_at,available,held,total
+2,2,2.0000,1700000500,0.0000,0.0000,0.0000
+"
+        );
+    }
+
+    #[test]
+    fn exposure_report() {
+        let transactions = get_transactions_from_file("src/testSamples/disputeAging.csv").unwrap();
+        let mut engine = Engine::default();
+        engine.set_config(toml::from_str("[tiers]\nvip = [2]").unwrap());
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let mut output = Vec::new();
+        write_exposure_report(&mut output, &engine, 1700000000 + 40 * DAY).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Held funds : 15.0000 across 2 clients
+By source :
+  disputes : 15.0000
+  escrow : 0.0000
+  rule holds : 0.0000
+By dispute age :
+  0-7 days : 4.0000
+  8-30 days : 2.0000
+  30+ days : 4.0000
+  no timestamp : 5.0000
+By tier :
+  vip : 9.0000
+  no tier : 6.0000
+"
+        );
+    }
+
+    #[test]
+    fn stats_report() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let mut output = Vec::new();
+        stats(&engine, 1).write_text(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Transactions :
+  deposit : 3, 6.0000
+  withdrawal : 0, 0.0000
+  dispute : 3, 6.0000
+  resolve : 1, 1.0000
+  chargeback : 1, 2.0000
+  under_review : 1, 3.0000
+Top clients by total :
+  client 1 : 4.0000
+Top clients by held :
+  client 1 : 3.0000
+Deposit amounts : min 1.0000, p50 2.0000, p90 3.0000, p99 3.0000, max 3.0000
+2 clients, 50.00% locked, 33.33% of disputes charged back
+"
+        );
+
+        let mut output = Vec::new();
+        stats(&engine, 1).write_json(&mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["top_by_held"][0]["client"], 1);
+        assert_eq!(json["deposit_amounts"]["p50"], 2.0);
+        assert_eq!(json["lock_rate"], 0.5);
+    }
+
+    #[test]
+    fn top_report() {
+        let mut engine = Engine::default();
+        for (client_id, total) in [(1, 5.0), (2, 9.0), (3, 1.0), (4, 9.0), (5, 7.0)] {
+            engine.clients.entry(client_id).or_default().total = total;
+        }
+        let mut output = Vec::new();
+        write_top_report(&mut output, &engine, "total", 3).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,total\n2,9.0000\n4,9.0000\n5,7.0000\n"
+        );
+        assert!(write_top_report(Vec::new(), &engine, "score", 3).is_err());
+        assert!(top_clients(&engine, 0, |c| c.total).is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{Engine, Transaction};
+use serde::Deserialize;
+use std::io::{Read, Write};
+
+// An analyst's decision on a held transaction, read from a csv file with a tx,decision header
+#[derive(Deserialize, Clone, Debug)]
+pub struct ReviewDecision {
+    pub tx: u32,
+    pub decision: Decision,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    // The transaction goes through : a deposit's funds become available, a withdrawal's leave
+    Approve,
+    // The transaction is reversed : a deposit's funds leave, a withdrawal's become available again
+    Deny,
+}
+
+pub fn read_decisions<R: Read>(reader: R) -> Result<Vec<ReviewDecision>, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    rdr.deserialize().collect()
+}
+
+// Every transaction waiting for a review, by tx id, with the same columns as the input
+pub fn write_review_queue<W: Write>(writer: W, engine: &Engine) -> Result<(), csv::Error> {
+    let mut held: Vec<&Transaction> = engine.held_transactions().collect();
+    held.sort_by_key(|t| t.tx);
+    let mut wtr = csv::Writer::from_writer(writer);
+    for t in held {
+        wtr.serialize(t)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn queue_and_decide() {
+        let transactions = get_transactions_from_file("src/testSamples/reviewQueue.csv").unwrap();
+        let mut engine = Engine::default();
+        engine.set_config(
+            toml::from_str(
+                r#"
+                [[rules]]
+                name = "large amounts"
+                min_amount = 100.0
+                max_amount = 400.0
+                action = "hold"
+                "#,
+            )
+            .unwrap(),
+        );
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let mut queue = Vec::new();
+        write_review_queue(&mut queue, &engine).unwrap();
+        assert_eq!(
+            String::from_utf8(queue).unwrap(),
+            "type,client,tx,amount,reason,timestamp,wallet
+deposit,1,2,200.0,,,
+withdrawal,1,3,150.0,,,
+deposit,2,4,300.0,,,
+"
+        );
+
+        let decisions = read_decisions(
+            "tx, decision
+2, approve
+3, approve
+4, deny
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        for decision in &decisions {
+            engine.decide(decision.tx, decision.decision).unwrap();
+        }
+        assert_eq!(
+            engine.decide(4, Decision::Approve),
+            Err("Transaction is not held")
+        );
+        assert_eq!(engine.held_transactions().count(), 0);
+
+        let client = &engine.clients()[&1];
+        assert_eq!(client.available, 550.0);
+        assert_eq!(client.held, 0.0);
+        assert_eq!(client.total, 550.0);
+        let client = &engine.clients()[&2];
+        assert_eq!(client.available, 0.0);
+        assert_eq!(client.held, 0.0);
+        assert_eq!(client.total, 0.0);
+    }
+}
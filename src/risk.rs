@@ -0,0 +1,118 @@
+use crate::{DisputeState, Engine, TransactionCategory};
+use std::collections::BTreeMap;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// What a risk model sees of a client, as of the reference time of the report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RiskFeatures {
+    pub chargebacks: usize,
+    // Disputes over the deposits and withdrawals of the client, 0 without any
+    pub dispute_ratio: f64,
+    // Withdrawals in the day before the reference time, among those with a timestamp
+    pub withdrawals_last_day: usize,
+    // Days since the first timestamped deposit or withdrawal, None without timestamps
+    pub age_days: Option<u64>,
+}
+
+// Risk teams substitute their own model by implementing this trait and passing it to
+// reports::write_risk_report. Higher scores are riskier.
+pub trait RiskModel {
+    fn score(&self, features: &RiskFeatures) -> f64;
+}
+
+// Score between 0 and 100 : 30 points per chargeback, up to 50 for the dispute ratio, 5 per
+// withdrawal in the last day up to 20, and 10 for an account younger than 30 days
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultRiskModel;
+
+impl RiskModel for DefaultRiskModel {
+    fn score(&self, features: &RiskFeatures) -> f64 {
+        let chargebacks = 30.0 * features.chargebacks as f64;
+        let disputes = 50.0 * features.dispute_ratio.min(1.0);
+        let velocity = 5.0 * features.withdrawals_last_day.min(4) as f64;
+        let new_account = match features.age_days {
+            Some(age) if age < 30 => 10.0,
+            _ => 0.0,
+        };
+        (chargebacks + disputes + velocity + new_account).min(100.0)
+    }
+}
+
+// Features of every client of the engine, from the transactions history and the dispute
+// records
+pub fn features(engine: &Engine, now: u64) -> BTreeMap<u16, RiskFeatures> {
+    let mut features: BTreeMap<u16, RiskFeatures> = engine
+        .clients()
+        .keys()
+        .map(|client_id| (*client_id, RiskFeatures::default()))
+        .collect();
+    let mut transactions: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut first_seen: BTreeMap<u16, u64> = BTreeMap::new();
+    for t in engine.transactions_history.values() {
+        *transactions.entry(t.client_id).or_default() += 1;
+        if let Some(at) = t.timestamp {
+            let first = first_seen.entry(t.client_id).or_insert(at);
+            *first = (*first).min(at);
+            if t.category == TransactionCategory::Withdrawal && at <= now && now - at < DAY {
+                features
+                    .entry(t.client_id)
+                    .or_default()
+                    .withdrawals_last_day += 1;
+            }
+        }
+    }
+    let mut disputes: BTreeMap<u16, usize> = BTreeMap::new();
+    for dispute in engine.disputes() {
+        *disputes.entry(dispute.client_id).or_default() += 1;
+        if dispute.state == DisputeState::ChargedBack {
+            features.entry(dispute.client_id).or_default().chargebacks += 1;
+        }
+    }
+    for (client_id, client) in features.iter_mut() {
+        let transactions = transactions.get(client_id).copied().unwrap_or_default();
+        if transactions > 0 {
+            let disputes = disputes.get(client_id).copied().unwrap_or_default();
+            client.dispute_ratio = disputes as f64 / transactions as f64;
+        }
+        client.age_days = first_seen
+            .get(client_id)
+            .map(|first| now.saturating_sub(*first) / DAY);
+    }
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn default_scores() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        let features = features(&engine, 1700000100 + 40 * DAY);
+        assert_eq!(
+            features[&2],
+            RiskFeatures {
+                chargebacks: 1,
+                dispute_ratio: 1.0,
+                withdrawals_last_day: 0,
+                age_days: Some(40),
+            }
+        );
+        assert_eq!(DefaultRiskModel.score(&features[&1]), 50.0);
+        assert_eq!(DefaultRiskModel.score(&features[&2]), 80.0);
+
+        let velocity = RiskFeatures {
+            withdrawals_last_day: 9,
+            age_days: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(DefaultRiskModel.score(&velocity), 30.0);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::config::Config;
+use crate::{Transaction, TransactionCategory};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+// A policy over deposits and withdrawals, every condition given must hold for the rule to match
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    pub category: Option<TransactionCategory>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub tier: Option<String>,
+    pub velocity: Option<Velocity>,
+    pub action: Action,
+}
+
+impl Rule {
+    // A rule matching anything, for the decisions taken outside the config
+    pub fn named(name: &str, action: Action) -> Rule {
+        Rule {
+            name: name.to_string(),
+            category: None,
+            min_amount: None,
+            max_amount: None,
+            tier: None,
+            velocity: None,
+            action,
+        }
+    }
+}
+
+// More than `count` deposits and withdrawals of the client within the last `window_seconds`,
+// this one included. Only transactions with a timestamp are counted.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Velocity {
+    pub count: usize,
+    pub window_seconds: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    // Ignored, the balances are left untouched
+    Reject,
+    // Applied as usual, the event names the rule
+    Flag,
+    // The funds are moved to held until the transaction is reviewed
+    Hold,
+}
+
+impl Rule {
+    fn matches(&self, t: &Transaction, tier: Option<&str>, recent: &VecDeque<u64>) -> bool {
+        let amount = t.amount.unwrap_or_default();
+        self.category.is_none_or(|category| category == t.category)
+            && self.min_amount.is_none_or(|min| amount >= min)
+            && self.max_amount.is_none_or(|max| amount <= max)
+            && self
+                .tier
+                .as_deref()
+                .is_none_or(|rule_tier| tier == Some(rule_tier))
+            && self.velocity.as_ref().is_none_or(|velocity| {
+                t.timestamp.is_some_and(|now| {
+                    let since = now.saturating_sub(velocity.window_seconds);
+                    recent.iter().filter(|&&at| at >= since).count() > velocity.count
+                })
+            })
+    }
+}
+
+// `recent` holds the timestamps of the client's latest deposits and withdrawals,
+// this transaction included
+pub fn evaluate<'a>(
+    config: &'a Config,
+    t: &Transaction,
+    recent: &VecDeque<u64>,
+) -> Option<&'a Rule> {
+    match t.category {
+        TransactionCategory::Deposit | TransactionCategory::Withdrawal => {
+            let tier = config.tier(t.client_id);
+            config
+                .rules
+                .iter()
+                .find(|rule| rule.matches(t, tier, recent))
+        }
+        _ => None,
+    }
+}
+
+// Longest window of the velocity conditions, 0 when there is none
+pub fn velocity_window(config: &Config) -> u64 {
+    config
+        .rules
+        .iter()
+        .filter_map(|rule| rule.velocity.as_ref())
+        .map(|velocity| velocity.window_seconds)
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(category: TransactionCategory, amount: f64, timestamp: u64) -> Transaction {
+        Transaction {
+            category,
+            client_id: 1,
+            tx: 1,
+            amount: Some(amount),
+            reason: None,
+            timestamp: Some(timestamp),
+            wallet: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let config: Config = toml::from_str(
+            r#"
+            [tiers]
+            new = [1]
+
+            [[rules]]
+            name = "burst"
+            velocity = { count = 2, window_seconds = 60 }
+            action = "reject"
+
+            [[rules]]
+            name = "large withdrawals of new clients"
+            category = "withdrawal"
+            tier = "new"
+            min_amount = 100.0
+            action = "hold"
+            "#,
+        )
+        .unwrap();
+
+        let withdrawal = transaction(TransactionCategory::Withdrawal, 150.0, 1000);
+        let recent = VecDeque::from([1000]);
+        assert_eq!(
+            evaluate(&config, &withdrawal, &recent).unwrap().action,
+            Action::Hold
+        );
+        let deposit = transaction(TransactionCategory::Deposit, 150.0, 1000);
+        assert!(evaluate(&config, &deposit, &recent).is_none());
+        let recent = VecDeque::from([900, 950, 980, 1000]);
+        assert_eq!(evaluate(&config, &deposit, &recent).unwrap().name, "burst");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::ScriptSettings;
+use crate::rules::{Action, Rule};
+use crate::{Client, Transaction};
+use rhai::{Dynamic, Map, Scope, AST};
+use std::error::Error;
+use std::fs;
+
+const DEFAULT_MAX_OPERATIONS: u64 = 100_000;
+// Rule of the events decided by the script, and of those it failed to decide
+pub const SCRIPT_RULE: &str = "script";
+pub const SCRIPT_ERROR_RULE: &str = "script error";
+
+// Rules too dynamic for the config, as a rhai script defining `decide(tx, client)`. It gets
+// the deposit or withdrawal (type, client, tx, amount, reason, timestamp and wallet) and the
+// client's balances (available, held, total, locked and frozen), and returns "allow", "deny",
+// "hold" or "flag". It is evaluated when no rule of the config matches.
+//
+// The script can't reach the file system or the network, and is stopped after
+// `max_operations` : a script failing rejects the transaction.
+pub struct Script {
+    engine: rhai::Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(settings: &ScriptSettings) -> Result<Script, Box<dyn Error>> {
+        let source = fs::read_to_string(&settings.path)?;
+        let max_operations = settings.max_operations.unwrap_or(DEFAULT_MAX_OPERATIONS);
+        Ok(Script::compile(&source, max_operations)?)
+    }
+
+    pub fn compile(source: &str, max_operations: u64) -> Result<Script, String> {
+        let mut engine = rhai::Engine::new();
+        engine
+            .set_max_operations(max_operations)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(10_000)
+            .set_max_array_size(10_000)
+            .set_max_map_size(1_000)
+            .on_print(|_| ())
+            .on_debug(|_, _, _| ());
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(Script { engine, ast })
+    }
+
+    pub fn decide(&self, t: &Transaction, client: &Client) -> Option<Rule> {
+        let action = match self.call(t, client) {
+            Ok(action) => action?,
+            Err(_) => return Some(Rule::named(SCRIPT_ERROR_RULE, Action::Reject)),
+        };
+        Some(Rule::named(SCRIPT_RULE, action))
+    }
+
+    fn call(&self, t: &Transaction, client: &Client) -> Result<Option<Action>, String> {
+        let optional = |value: Option<Dynamic>| value.unwrap_or(Dynamic::UNIT);
+        let mut transaction = Map::new();
+        transaction.insert("type".into(), t.category.as_str().into());
+        transaction.insert("client".into(), (t.client_id as i64).into());
+        transaction.insert("tx".into(), (t.tx as i64).into());
+        transaction.insert("amount".into(), optional(t.amount.map(Dynamic::from)));
+        transaction.insert(
+            "reason".into(),
+            optional(t.reason.clone().map(Dynamic::from)),
+        );
+        transaction.insert(
+            "timestamp".into(),
+            optional(t.timestamp.map(|at| Dynamic::from(at as i64))),
+        );
+        transaction.insert(
+            "wallet".into(),
+            optional(t.wallet().map(|wallet| Dynamic::from(wallet.to_string()))),
+        );
+        let mut balances = Map::new();
+        balances.insert("available".into(), client.available.into());
+        balances.insert("held".into(), client.held.into());
+        balances.insert("total".into(), client.total.into());
+        balances.insert("locked".into(), client.locked.into());
+        balances.insert("frozen".into(), client.frozen.into());
+
+        let decision: String = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                "decide",
+                (transaction, balances),
+            )
+            .map_err(|e| e.to_string())?;
+        match decision.as_str() {
+            "allow" => Ok(None),
+            "deny" => Ok(Some(Action::Reject)),
+            "hold" => Ok(Some(Action::Hold)),
+            "flag" => Ok(Some(Action::Flag)),
+            _ => Err(format!("Unknown decision {}", decision)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionCategory;
+
+    fn deposit(amount: f64) -> Transaction {
+        Transaction {
+            category: TransactionCategory::Deposit,
+            client_id: 1,
+            tx: 1,
+            amount: Some(amount),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn script_decisions() {
+        let script = Script::compile(
+            r#"
+            fn decide(tx, client) {
+                if tx.amount > 1000.0 { "hold" }
+                else if client.frozen { "deny" }
+                else if tx.amount > client.total { "flag" }
+                else { "allow" }
+            }
+            "#,
+            1_000,
+        )
+        .unwrap();
+        let mut client = Client {
+            total: 50.0,
+            ..Default::default()
+        };
+        let action = |t, client: &Client| script.decide(&t, client).map(|rule| rule.action);
+        assert_eq!(action(deposit(2000.0), &client), Some(Action::Hold));
+        assert_eq!(action(deposit(100.0), &client), Some(Action::Flag));
+        assert_eq!(action(deposit(10.0), &client), None);
+        client.frozen = true;
+        assert_eq!(action(deposit(10.0), &client), Some(Action::Reject));
+
+        // Past its operations, the script rejects the transaction
+        let endless = Script::compile("fn decide(tx, client) { loop {} }", 1_000).unwrap();
+        let rule = endless.decide(&deposit(10.0), &client).unwrap();
+        assert_eq!(
+            (rule.name.as_str(), rule.action),
+            (SCRIPT_ERROR_RULE, Action::Reject)
+        );
+    }
+}
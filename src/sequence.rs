@@ -0,0 +1,261 @@
+use crate::config::DisputeSettings;
+use crate::{Transaction, TransactionCategory};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+// Input put back in sequence order, with what couldn't be
+pub struct Sequenced {
+    pub transactions: Vec<Transaction>,
+    // Index in the input of each released row, for the inputs keeping an id per row
+    pub order: Vec<usize>,
+    // Sequence numbers never received, as first and last of each gap
+    pub gaps: Vec<(u64, u64)>,
+    // Sequence numbers released after a greater one, as they came later than the window allows
+    // or twice. They are still applied, out of order.
+    pub late: Vec<u64>,
+}
+
+// Puts the rows in the given order, `order` holding the current index of each row to keep
+pub fn permute<T>(rows: &mut Vec<T>, order: &[usize]) {
+    let mut taken: Vec<Option<T>> = rows.drain(..).map(Some).collect();
+    rows.extend(
+        order
+            .iter()
+            .map(|&index| taken[index].take().expect("A row is placed once")),
+    );
+}
+
+// For slightly out of order streams (e.g. several Kafka partitions merged by the producer) :
+// rows are buffered in a window of `window` rows and the one with the smallest `seq` is
+// released whenever the window is full, so a row can come up to `window` rows late. Every
+// row needs a seq.
+pub fn reorder(transactions: Vec<Transaction>, window: usize) -> Result<Sequenced, String> {
+    let mut rows: Vec<Option<Transaction>> = Vec::with_capacity(transactions.len());
+    let mut buffer = BinaryHeap::with_capacity(window + 1);
+    let mut sequenced = Sequenced {
+        transactions: Vec::with_capacity(transactions.len()),
+        order: Vec::with_capacity(transactions.len()),
+        gaps: Vec::new(),
+        late: Vec::new(),
+    };
+    let mut next = None;
+    let mut release = |(seq, index): (u64, usize), rows: &mut Vec<Option<Transaction>>| {
+        match next {
+            Some(next) if seq > next => sequenced.gaps.push((next, seq - 1)),
+            Some(next) if seq < next => sequenced.late.push(seq),
+            _ => (),
+        }
+        next = Some(next.map_or(seq + 1, |next: u64| next.max(seq + 1)));
+        let t = rows[index].take().expect("A row is released once");
+        sequenced.transactions.push(t);
+        sequenced.order.push(index);
+    };
+    for (index, t) in transactions.into_iter().enumerate() {
+        let seq = t
+            .seq
+            .ok_or_else(|| format!("Row {} has no seq, the input can't be reordered", index + 1))?;
+        rows.push(Some(t));
+        buffer.push(Reverse((seq, index)));
+        if buffer.len() > window {
+            if let Some(Reverse(row)) = buffer.pop() {
+                release(row, &mut rows);
+            }
+        }
+    }
+    while let Some(Reverse(row)) = buffer.pop() {
+        release(row, &mut rows);
+    }
+    Ok(sequenced)
+}
+
+// When sources race, a dispute (or a later row of its flow) can come before the deposit or
+// withdrawal it references. Such rows are moved right after that transaction, keeping their
+// order, when it comes within the grace of the dispute settings. The others stay in place and
+// are ignored as before. Returns the number of rows moved.
+pub fn park_early_disputes(
+    transactions: &mut Vec<Transaction>,
+    settings: &DisputeSettings,
+) -> usize {
+    let (order, parked) = parking_order(transactions, settings);
+    if parked > 0 {
+        permute(transactions, &order);
+    }
+    parked
+}
+
+// Order of the rows once the early disputes are parked, as the index of each row, along with
+// the number of rows moved
+pub fn parking_order(
+    transactions: &[Transaction],
+    settings: &DisputeSettings,
+) -> (Vec<usize>, usize) {
+    let mut order: Vec<usize> = (0..transactions.len()).collect();
+    if settings.grace_rows.is_none() && settings.grace_seconds.is_none() {
+        return (order, 0);
+    }
+    let mut first_seen: HashMap<u32, usize> = HashMap::new();
+    for (index, t) in transactions.iter().enumerate() {
+        if matches!(
+            t.category,
+            TransactionCategory::Deposit | TransactionCategory::Withdrawal
+        ) {
+            first_seen.entry(t.tx).or_insert(index);
+        }
+    }
+    let mut parked: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut moved = vec![false; transactions.len()];
+    for (index, t) in transactions.iter().enumerate() {
+        let dispute_flow = matches!(
+            t.category,
+            TransactionCategory::Dispute
+                | TransactionCategory::Review
+                | TransactionCategory::Resolve
+                | TransactionCategory::Chargeback
+        );
+        let Some(&arrival) = first_seen
+            .get(&t.tx)
+            .filter(|&&at| dispute_flow && at > index)
+        else {
+            continue;
+        };
+        let within_rows = settings
+            .grace_rows
+            .is_none_or(|grace| arrival - index <= grace);
+        let within_seconds = settings.grace_seconds.is_none_or(|grace| {
+            match (t.timestamp, transactions[arrival].timestamp) {
+                (Some(parked_at), Some(arrived_at)) => {
+                    arrived_at.saturating_sub(parked_at) <= grace
+                }
+                _ => false,
+            }
+        });
+        if within_rows && within_seconds {
+            parked.entry(arrival).or_default().push(index);
+            moved[index] = true;
+        }
+    }
+    let count = moved.iter().filter(|moved| **moved).count();
+    if count == 0 {
+        return (order, 0);
+    }
+    order.clear();
+    for (index, moved) in moved.iter().enumerate() {
+        if *moved {
+            continue;
+        }
+        order.push(index);
+        order.extend(parked.remove(&index).unwrap_or_default());
+    }
+    (order, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionCategory;
+
+    fn rows(seqs: &[u64]) -> Vec<Transaction> {
+        seqs.iter()
+            .map(|&seq| Transaction {
+                category: TransactionCategory::Deposit,
+                client_id: 1,
+                tx: seq as u32,
+                amount: Some(1.0),
+                reason: None,
+                timestamp: None,
+                wallet: None,
+                seq: Some(seq),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reordering_window() {
+        let sequenced = reorder(rows(&[1, 3, 2, 4, 7, 5, 6, 10]), 2).unwrap();
+        let order: Vec<u32> = sequenced.transactions.iter().map(|t| t.tx).collect();
+        assert_eq!(order, [1, 2, 3, 4, 5, 6, 7, 10]);
+        assert_eq!(sequenced.gaps, [(8, 9)]);
+        assert!(sequenced.late.is_empty());
+
+        // Beyond the window, the late row is applied after the greater ones
+        let sequenced = reorder(rows(&[3, 4, 1, 2]), 1).unwrap();
+        let order: Vec<u32> = sequenced.transactions.iter().map(|t| t.tx).collect();
+        assert_eq!(order, [3, 1, 2, 4]);
+        assert_eq!(sequenced.late, [1, 2]);
+        let mut ids = vec!["a", "b", "c", "d"];
+        permute(&mut ids, &sequenced.order);
+        assert_eq!(ids, ["a", "c", "d", "b"]);
+
+        let mut unsequenced = rows(&[1, 2]);
+        unsequenced[1].seq = None;
+        assert!(reorder(unsequenced, 2).is_err());
+    }
+
+    #[test]
+    fn early_disputes() {
+        let row = |category, tx, timestamp| Transaction {
+            category,
+            client_id: 1,
+            tx,
+            amount: (category == TransactionCategory::Deposit).then_some(1.0),
+            reason: None,
+            timestamp: Some(timestamp),
+            wallet: None,
+            seq: None,
+        };
+        let input = vec![
+            row(TransactionCategory::Dispute, 1, 100),
+            row(TransactionCategory::Resolve, 1, 101),
+            row(TransactionCategory::Dispute, 2, 102),
+            row(TransactionCategory::Deposit, 1, 103),
+            row(TransactionCategory::Deposit, 3, 104),
+            row(TransactionCategory::Deposit, 2, 200),
+        ];
+        let order = |transactions: &[Transaction]| {
+            transactions
+                .iter()
+                .map(|t| (t.category.as_str(), t.tx))
+                .collect::<Vec<_>>()
+        };
+
+        let mut transactions = input.clone();
+        let settings: DisputeSettings = toml::from_str("grace_rows = 3").unwrap();
+        assert_eq!(park_early_disputes(&mut transactions, &settings), 3);
+        assert_eq!(
+            order(&transactions),
+            [
+                ("deposit", 1),
+                ("dispute", 1),
+                ("resolve", 1),
+                ("deposit", 3),
+                ("deposit", 2),
+                ("dispute", 2)
+            ]
+        );
+
+        // The deposit of tx 2 comes 98 seconds after its dispute
+        let mut transactions = input.clone();
+        let settings: DisputeSettings = toml::from_str("grace_seconds = 60").unwrap();
+        assert_eq!(
+            parking_order(&transactions, &settings),
+            (vec![2, 3, 0, 1, 4, 5], 2)
+        );
+        assert_eq!(park_early_disputes(&mut transactions, &settings), 2);
+        assert_eq!(
+            order(&transactions)[..4],
+            [
+                ("dispute", 2),
+                ("deposit", 1),
+                ("dispute", 1),
+                ("resolve", 1)
+            ]
+        );
+
+        let mut transactions = input.clone();
+        assert_eq!(
+            park_early_disputes(&mut transactions, &DisputeSettings::default()),
+            0
+        );
+        assert_eq!(order(&transactions), order(&input));
+    }
+}
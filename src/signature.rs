@@ -0,0 +1,70 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::error::Error;
+use std::fs;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// The key file is read as is, surrounding whitespace aside, so a key generated with
+// e.g. `openssl rand -hex 32 > key` works
+pub fn read_key(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key = fs::read(path)?;
+    let key = key.trim_ascii().to_vec();
+    if key.is_empty() {
+        return Err(format!("The signing key file {} is empty", path).into());
+    }
+    Ok(key)
+}
+
+// Detached HMAC-SHA256 of the data, hex encoded
+pub fn sign(key: &[u8], data: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Compares in constant time
+pub fn verify(key: &[u8], data: &[u8], signature: &str) -> bool {
+    let signature = signature.trim();
+    let Some(signature) = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+    else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        // RFC 4231, test case 2
+        let signature = sign(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(verify(b"Jefe", b"what do ya want for nothing?", &signature));
+        assert!(!verify(
+            b"Jefe",
+            b"what do ya want for nothing!",
+            &signature
+        ));
+        assert!(!verify(
+            b"Jeff",
+            b"what do ya want for nothing?",
+            &signature
+        ));
+        assert!(!verify(b"Jefe", b"what do ya want for nothing?", "not hex"));
+    }
+}
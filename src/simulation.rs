@@ -0,0 +1,202 @@
+use crate::{DisputeState, Engine, Outcome, Transaction, TransactionCategory};
+use std::collections::HashMap;
+
+const CLIENTS: u64 = 200;
+
+// Seeded pseudo random generator (splitmix64), so a workload only depends on its seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+pub struct SimulationReport {
+    pub applied: usize,
+    pub ignored: usize,
+}
+
+// Random deposits and withdrawals interleaved with adversarial sequences : disputes of
+// transactions that don't exist yet, dispute flows on transactions already resolved or
+// charged back, and deposits redelivered with the tx id of a previous one
+pub fn workload(seed: u64, transactions: usize) -> Vec<Transaction> {
+    let mut rng = Rng(seed);
+    let mut deposits: Vec<(u32, u16)> = Vec::new();
+    let mut next_tx: u32 = 1;
+    let mut workload = Vec::with_capacity(transactions);
+    for _ in 0..transactions {
+        let amount = Some((rng.below(10_000_000) + 1) as f64 / 10_000.0);
+        let client_id = (rng.below(CLIENTS) + 1) as u16;
+        let existing = match deposits.len() {
+            0 => None,
+            n => Some(deposits[rng.below(n as u64) as usize]),
+        };
+        let (category, client_id, tx, amount) = match (rng.below(100), existing) {
+            (0..=39, Some((tx, client_id))) if rng.below(20) == 0 => {
+                (TransactionCategory::Deposit, client_id, tx, amount)
+            }
+            (0..=39, _) => {
+                deposits.push((next_tx, client_id));
+                next_tx += 1;
+                (TransactionCategory::Deposit, client_id, next_tx - 1, amount)
+            }
+            (40..=59, _) | (_, None) => {
+                next_tx += 1;
+                (
+                    TransactionCategory::Withdrawal,
+                    client_id,
+                    next_tx - 1,
+                    amount,
+                )
+            }
+            (60..=74, _) if rng.below(5) == 0 => (
+                TransactionCategory::Dispute,
+                client_id,
+                next_tx + rng.below(10) as u32,
+                None,
+            ),
+            (60..=74, Some((tx, client_id))) => (TransactionCategory::Dispute, client_id, tx, None),
+            (75..=79, Some((tx, client_id))) => (TransactionCategory::Review, client_id, tx, None),
+            (80..=95, Some((tx, client_id))) => (TransactionCategory::Resolve, client_id, tx, None),
+            (_, Some((tx, client_id))) => (TransactionCategory::Chargeback, client_id, tx, None),
+        };
+        workload.push(Transaction {
+            category,
+            client_id,
+            tx,
+            amount,
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        });
+    }
+    workload
+}
+
+// Runs the workload of the seed and checks the invariants after every transaction.
+// The error gives the seed and the transaction breaking an invariant, for reproduction.
+pub fn simulate(seed: u64, transactions: usize) -> Result<SimulationReport, String> {
+    let mut engine = Engine::default();
+    // Deposits minus withdrawals minus chargebacks, by client
+    let mut expected_totals: HashMap<u16, f64> = HashMap::new();
+    let mut report = SimulationReport {
+        applied: 0,
+        ignored: 0,
+    };
+    for (i, t) in workload(seed, transactions).iter().enumerate() {
+        let fail = |invariant: String| {
+            format!(
+                "Seed {} : {} after transaction {} ({} of tx {} for client {})",
+                seed,
+                invariant,
+                i + 1,
+                t.category.as_str(),
+                t.tx,
+                t.client_id
+            )
+        };
+        let was_locked = engine.clients.get(&t.client_id).is_some_and(|c| c.locked);
+        let charged_back = engine.ongoing_disputes.get(&t.tx).map(|d| d.amount);
+        let event = engine.process(t).map_err(fail)?;
+        match event.outcome {
+            Outcome::Applied => {
+                report.applied += 1;
+                if was_locked {
+                    return Err(fail("a locked account was modified".to_string()));
+                }
+                let expected = expected_totals.entry(t.client_id).or_default();
+                match t.category {
+                    TransactionCategory::Deposit => *expected += t.amount.unwrap_or_default(),
+                    TransactionCategory::Withdrawal => *expected -= t.amount.unwrap_or_default(),
+                    TransactionCategory::Chargeback => {
+                        *expected -= charged_back.unwrap_or_default()
+                    }
+                    _ => (),
+                }
+            }
+            Outcome::Held | Outcome::Deferred | Outcome::Ignored(_) => report.ignored += 1,
+        }
+        check_invariants(&engine, &expected_totals, t.client_id).map_err(fail)?;
+    }
+    Ok(report)
+}
+
+fn check_invariants(
+    engine: &Engine,
+    expected_totals: &HashMap<u16, f64>,
+    client_id: u16,
+) -> Result<(), String> {
+    let Some(client) = engine.clients.get(&client_id) else {
+        return Ok(());
+    };
+    let held: f64 = engine
+        .ongoing_disputes
+        .values()
+        .filter(|d| d.client_id == client_id)
+        .map(|d| d.amount)
+        .sum();
+    let expected_total = expected_totals.get(&client_id).copied().unwrap_or_default();
+    if !close(client.available + client.held, client.total) {
+        return Err(format!(
+            "available {} + held {} != total {}",
+            client.available, client.held, client.total
+        ));
+    }
+    if !close(client.held, held) {
+        return Err(format!(
+            "held {} != {} held by the ongoing disputes",
+            client.held, held
+        ));
+    }
+    if !close(client.total, expected_total) {
+        return Err(format!(
+            "total {} != {} applied",
+            client.total, expected_total
+        ));
+    }
+    for dispute in engine.ongoing_disputes.values() {
+        if !matches!(
+            dispute.state,
+            DisputeState::Deferred | DisputeState::Opened | DisputeState::UnderReview
+        ) {
+            return Err(format!("ongoing dispute of tx {} is closed", dispute.tx));
+        }
+    }
+    Ok(())
+}
+
+// Sums of floats only agree up to rounding
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workload_only_depends_on_the_seed() {
+        let first = format!("{:?}", workload(42, 500));
+        assert_eq!(first, format!("{:?}", workload(42, 500)));
+        assert_ne!(first, format!("{:?}", workload(43, 500)));
+    }
+
+    #[test]
+    fn invariants_hold() {
+        for seed in 0..20 {
+            let report = simulate(seed, 2_000).unwrap_or_else(|e| panic!("{}", e));
+            assert!(report.applied > 0);
+            assert!(report.ignored > 0);
+        }
+    }
+}
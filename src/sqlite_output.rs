@@ -0,0 +1,142 @@
+use crate::{Client, Event, Outcome};
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::error::Error;
+
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS clients;
+    DROP TABLE IF EXISTS applied_transactions;
+    DROP TABLE IF EXISTS rejections;
+
+    CREATE TABLE clients (
+        client INTEGER PRIMARY KEY,
+        available REAL NOT NULL,
+        held REAL NOT NULL,
+        total REAL NOT NULL,
+        locked INTEGER NOT NULL,
+        frozen INTEGER NOT NULL
+    );
+    CREATE TABLE applied_transactions (
+        row INTEGER NOT NULL,
+        type TEXT NOT NULL,
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        amount REAL
+    );
+    CREATE TABLE rejections (
+        row INTEGER NOT NULL,
+        type TEXT NOT NULL,
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        amount REAL,
+        reason TEXT NOT NULL
+    );
+
+    CREATE INDEX applied_transactions_client ON applied_transactions (client);
+    CREATE INDEX applied_transactions_tx ON applied_transactions (tx);
+    CREATE INDEX rejections_client ON rejections (client);
+    CREATE INDEX rejections_tx ON rejections (tx);
+";
+
+// Replaces the tables of a previous run, everything is written in a single sqlite transaction
+pub fn write_sqlite_output(
+    database_path: &str,
+    clients: &HashMap<u16, Client>,
+    events: &[Event],
+) -> Result<(), Box<dyn Error>> {
+    let mut connection = Connection::open(database_path)?;
+    let sql_transaction = connection.transaction()?;
+    sql_transaction.execute_batch(SCHEMA)?;
+    {
+        let mut insert_client = sql_transaction.prepare(
+            "INSERT INTO clients (client, available, held, total, locked, frozen) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for (client_id, client) in clients {
+            insert_client.execute(params![
+                client_id,
+                client.available,
+                client.held,
+                client.total,
+                client.locked,
+                client.frozen
+            ])?;
+        }
+
+        let mut insert_applied = sql_transaction.prepare(
+            "INSERT INTO applied_transactions (row, type, client, tx, amount) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut insert_rejection = sql_transaction.prepare(
+            "INSERT INTO rejections (row, type, client, tx, amount, reason) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for event in events {
+            let t = &event.transaction;
+            match event.outcome {
+                // Held transactions and deferred disputes were accepted, only waiting for a
+                // review or for funds
+                Outcome::Applied | Outcome::Held | Outcome::Deferred => {
+                    insert_applied.execute(params![
+                        event.row,
+                        t.category.as_str(),
+                        t.client_id,
+                        t.tx,
+                        t.amount
+                    ])?
+                }
+                Outcome::Ignored(reason) => insert_rejection.execute(params![
+                    event.row,
+                    t.category.as_str(),
+                    t.client_id,
+                    t.tx,
+                    t.amount,
+                    reason
+                ])?,
+            };
+        }
+    }
+    sql_transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, process_transactions};
+
+    #[test]
+    fn write_clients_and_transactions() {
+        let transactions = get_transactions_from_file("src/testSamples/trickyResolve.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        let events = process_transactions(&transactions, &mut clients).unwrap();
+
+        let database_path = std::env::temp_dir().join("payments-engine-sqlite-test.db");
+        let database_path = database_path.to_str().unwrap();
+        // Written twice to check that a previous run is replaced
+        write_sqlite_output(database_path, &clients, &events).unwrap();
+        write_sqlite_output(database_path, &clients, &events).unwrap();
+
+        let connection = Connection::open(database_path).unwrap();
+        let count = |table: &str| -> usize {
+            connection
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        let applied = events
+            .iter()
+            .filter(|e| e.outcome == Outcome::Applied)
+            .count();
+        assert_eq!(count("clients"), clients.len());
+        assert_eq!(count("applied_transactions"), applied);
+        assert_eq!(count("rejections"), events.len() - applied);
+
+        let available: f64 = connection
+            .query_row(
+                "SELECT available FROM clients WHERE client = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(available, 1.5);
+    }
+}
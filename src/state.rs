@@ -0,0 +1,646 @@
+use crate::encryption::StateKey;
+use crate::{
+    Client, Dispute, DisputeState, Engine, Event, Outcome, Transaction, TransactionCategory, Wallet,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+// Engine state persisted between runs, as csv files in a directory :
+//   clients.csv   client,available,held,total,locked,frozen
+//   history.csv   recorded deposits and withdrawals, same columns as the input. It is only
+//                 read on the first dispute, a run without disputes appends to it.
+//   disputes.csv  every dispute record, ongoing or closed
+//   held.csv      deposits and withdrawals held by a rule, same columns as the input
+//   escrow.csv    client,bucket,amount of the funds placed in escrow
+//   wallets.csv   client,wallet,available,held of the named wallets
+//   erasures.csv  erased_at,client,history,disputes,pseudonyms,folded of each erasure, written
+//                 by erase-client alone
+// Amounts are written with all their digits so a reload gives back the exact same numbers.
+// With a key, each file is encrypted and saved with an .enc extension instead.
+const STATE_FILES: [&str; 6] = [
+    "clients.csv",
+    "history.csv",
+    "disputes.csv",
+    "held.csv",
+    "escrow.csv",
+    "wallets.csv",
+];
+
+const HISTORY_HEADER: &str = "type,client,tx,amount,reason,timestamp,wallet\n";
+const ERASURES_FILE: &str = "erasures.csv";
+const ERASURES_HEADER: &str = "erased_at,client,history,disputes,pseudonyms,folded\n";
+
+// Where the engine state is persisted between runs : a directory of csv files, or a database.
+// A store shared by several instances fails the save with a Conflict when clients it loaded
+// were saved by another instance in the meantime, and saves nothing then.
+pub trait StateStore {
+    fn load(&self) -> Result<Engine, Box<dyn Error>>;
+    fn save(&self, engine: &Engine) -> Result<(), Box<dyn Error>>;
+    // Audit trail of the right to erasure requests, kept with the state
+    fn record_erasure(&self, record: &ErasureRecord) -> Result<(), Box<dyn Error>>;
+}
+
+// What an erasure removed, without anything identifying the person beyond the client id
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ErasureRecord {
+    // Unix seconds
+    pub erased_at: u64,
+    pub client: u16,
+    pub history: usize,
+    pub disputes: usize,
+    pub pseudonyms: usize,
+    pub folded: f64,
+}
+
+#[derive(Debug)]
+pub struct Conflict {
+    pub clients: Vec<u16>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let clients: Vec<String> = self.clients.iter().map(u16::to_string).collect();
+        write!(
+            f,
+            "Clients {} were saved by another instance since the state was loaded",
+            clients.join(", ")
+        )
+    }
+}
+
+impl Error for Conflict {}
+
+pub struct DirectoryStore {
+    pub directory: String,
+    pub key: Option<StateKey>,
+}
+
+impl StateStore for DirectoryStore {
+    fn load(&self) -> Result<Engine, Box<dyn Error>> {
+        load_engine_with_key(&self.directory, self.key.as_ref())
+    }
+
+    fn save(&self, engine: &Engine) -> Result<(), Box<dyn Error>> {
+        save_engine_with_key(engine, &self.directory, self.key.as_ref())
+    }
+
+    // Rewritten whole, encrypted like the other files when the state is
+    fn record_erasure(&self, record: &ErasureRecord) -> Result<(), Box<dyn Error>> {
+        let directory = Path::new(&self.directory);
+        let key = self.key.as_ref();
+        let content = read_state_file(directory, ERASURES_FILE, key)?
+            .unwrap_or_else(|| ERASURES_HEADER.as_bytes().to_vec());
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(content);
+        wtr.serialize(record)?;
+        write_state_file(directory, ERASURES_FILE, wtr.into_inner()?, key)?;
+        let file = state_file_name(ERASURES_FILE, key.is_some());
+        fs::rename(
+            directory.join(format!("{}.tmp", file)),
+            directory.join(&file),
+        )?;
+        let _ = fs::remove_file(directory.join(state_file_name(ERASURES_FILE, key.is_none())));
+        Ok(())
+    }
+}
+
+pub fn load_engine(directory: &str) -> Result<Engine, Box<dyn Error>> {
+    load_engine_with_key(directory, None)
+}
+
+pub fn load_engine_with_key(
+    directory: &str,
+    key: Option<&StateKey>,
+) -> Result<Engine, Box<dyn Error>> {
+    let mut engine = Engine::default();
+    let directory = Path::new(directory);
+    if !directory.exists() {
+        return Ok(engine);
+    }
+
+    let clients = read_state_file(directory, "clients.csv", key)?
+        .ok_or("The state directory has no clients.csv")?;
+    engine.clients = read_clients(clients.as_slice())?;
+
+    let history_directory = directory.to_path_buf();
+    let history_key = key.cloned();
+    engine.set_history_loader(Box::new(move || {
+        read_history(&history_directory, history_key.as_ref()).map_err(|e| e.to_string())
+    }));
+
+    let disputes = read_state_file(directory, "disputes.csv", key)?
+        .ok_or("The state directory has no disputes.csv")?;
+    let mut rdr = csv::Reader::from_reader(disputes.as_slice());
+    for record in rdr.deserialize() {
+        let dispute: Dispute = record?;
+        match dispute.state {
+            DisputeState::Deferred | DisputeState::Opened | DisputeState::UnderReview => {
+                engine.ongoing_disputes.insert(dispute.tx, dispute);
+            }
+            DisputeState::Resolved | DisputeState::ChargedBack => {
+                engine.closed_disputes.push(dispute)
+            }
+        }
+    }
+
+    // Missing from the states saved before rules could hold transactions
+    if let Some(held) = read_state_file(directory, "held.csv", key)? {
+        let mut rdr = csv::Reader::from_reader(held.as_slice());
+        for record in rdr.deserialize() {
+            let t: Transaction = record?;
+            engine.held_transactions.insert(t.tx, t);
+        }
+    }
+
+    // Missing from the states saved before funds could be placed in escrow
+    if let Some(escrow) = read_state_file(directory, "escrow.csv", key)? {
+        let mut rdr = csv::Reader::from_reader(escrow.as_slice());
+        for record in rdr.deserialize() {
+            let (client_id, bucket, amount): (u16, String, f64) = record?;
+            engine
+                .clients
+                .entry(client_id)
+                .or_default()
+                .escrow
+                .insert(bucket, amount);
+        }
+    }
+
+    // Missing from the states saved before clients could have wallets
+    if let Some(wallets) = read_state_file(directory, "wallets.csv", key)? {
+        let mut rdr = csv::Reader::from_reader(wallets.as_slice());
+        for record in rdr.deserialize() {
+            let (client_id, wallet, available, held): (u16, String, f64, f64) = record?;
+            engine
+                .clients
+                .entry(client_id)
+                .or_default()
+                .wallets
+                .insert(wallet, Wallet { available, held });
+        }
+    }
+    Ok(engine)
+}
+
+// Balances in the csv output format, which is also the one of clients.csv
+pub fn read_clients<R: Read>(reader: R) -> Result<HashMap<u16, Client>, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut clients = HashMap::new();
+    for record in rdr.deserialize() {
+        let row: ClientRow = record?;
+        clients.insert(
+            row.client,
+            Client {
+                available: row.available,
+                held: row.held,
+                total: row.total,
+                locked: row.locked,
+                frozen: row.frozen,
+                escrow: BTreeMap::new(),
+                wallets: BTreeMap::new(),
+            },
+        );
+    }
+    Ok(clients)
+}
+
+// Opening balances from the output of a previous run, for daily runs without a --state : the
+// history and the disputes of the previous runs aren't known, so their deposits can't be
+// disputed and the funds held by their disputes stay held
+pub fn set_initial_state<R: Read>(engine: &mut Engine, reader: R) -> Result<(), csv::Error> {
+    engine.clients = read_clients(reader)?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ClientRow {
+    client: u16,
+    available: f64,
+    held: f64,
+    total: f64,
+    locked: bool,
+    // Missing from the states saved before clients could be frozen
+    #[serde(default)]
+    frozen: bool,
+}
+
+pub fn save_engine(engine: &Engine, directory: &str) -> Result<(), Box<dyn Error>> {
+    save_engine_with_key(engine, directory, None)
+}
+
+// Files are written next to the previous ones then renamed, so an interrupted save
+// never leaves a half written file behind
+pub fn save_engine_with_key(
+    engine: &Engine,
+    directory: &str,
+    key: Option<&StateKey>,
+) -> Result<(), Box<dyn Error>> {
+    let directory = Path::new(directory);
+    fs::create_dir_all(directory)?;
+
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(["client", "available", "held", "total", "locked", "frozen"])?;
+    for (client_id, client) in &engine.clients {
+        wtr.serialize((
+            client_id,
+            client.available,
+            client.held,
+            client.total,
+            client.locked,
+            client.frozen,
+        ))?;
+    }
+    write_state_file(directory, "clients.csv", wtr.into_inner()?, key)?;
+
+    // When the history wasn't read, it only holds the transactions of this run
+    let mut previous = match engine.history_loaded() {
+        true => Vec::new(),
+        false => read_state_file(directory, "history.csv", key)?.unwrap_or_default(),
+    };
+    // A history saved before the last column was added is rewritten with the current columns
+    if !previous.is_empty() && !previous.starts_with(HISTORY_HEADER.as_bytes()) {
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        for t in csv::Reader::from_reader(previous.as_slice()).deserialize() {
+            let t: Transaction = t?;
+            wtr.serialize(t)?;
+        }
+        previous = wtr.into_inner()?;
+    }
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(previous.is_empty())
+        .from_writer(previous);
+    for t in engine.transactions_history.values() {
+        wtr.serialize(t)?;
+    }
+    write_state_file(directory, "history.csv", wtr.into_inner()?, key)?;
+
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for dispute in engine.disputes() {
+        wtr.serialize(dispute)?;
+    }
+    write_state_file(directory, "disputes.csv", wtr.into_inner()?, key)?;
+
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for t in engine.held_transactions() {
+        wtr.serialize(t)?;
+    }
+    write_state_file(directory, "held.csv", wtr.into_inner()?, key)?;
+
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(["client", "bucket", "amount"])?;
+    for (client_id, client) in &engine.clients {
+        for (bucket, amount) in &client.escrow {
+            wtr.serialize((client_id, bucket, amount))?;
+        }
+    }
+    write_state_file(directory, "escrow.csv", wtr.into_inner()?, key)?;
+
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(["client", "wallet", "available", "held"])?;
+    for (client_id, client) in &engine.clients {
+        for (wallet, funds) in &client.wallets {
+            wtr.serialize((client_id, wallet, funds.available, funds.held))?;
+        }
+    }
+    write_state_file(directory, "wallets.csv", wtr.into_inner()?, key)?;
+
+    for file in STATE_FILES {
+        let file = state_file_name(file, key.is_some());
+        fs::rename(
+            directory.join(format!("{}.tmp", file)),
+            directory.join(&file),
+        )?;
+    }
+    // Once the encrypted files are in place, the plaintext ones of a previous save go away
+    // (and the other way around when the key is dropped)
+    for file in STATE_FILES {
+        let _ = fs::remove_file(directory.join(state_file_name(file, key.is_none())));
+    }
+    Ok(())
+}
+
+// In recording order, a reused tx id appearing once per run that recorded it : the engine keeps
+// the latest row
+fn read_history(
+    directory: &Path,
+    key: Option<&StateKey>,
+) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let Some(history) = read_state_file(directory, "history.csv", key)? else {
+        return Ok(Vec::new());
+    };
+    let mut rdr = csv::Reader::from_reader(history.as_slice());
+    Ok(rdr
+        .deserialize()
+        .collect::<Result<Vec<Transaction>, csv::Error>>()?)
+}
+
+fn state_file_name(file: &str, encrypted: bool) -> String {
+    if encrypted {
+        format!("{}.enc", file)
+    } else {
+        file.to_string()
+    }
+}
+
+// None when the file doesn't exist, encrypted or not
+fn read_state_file(
+    directory: &Path,
+    file: &str,
+    key: Option<&StateKey>,
+) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let encrypted = directory.join(state_file_name(file, true));
+    if encrypted.exists() {
+        let key = key.ok_or("The state is encrypted, please provide its key")?;
+        return Ok(Some(key.decrypt(file, &fs::read(encrypted)?)?));
+    }
+    let plaintext = directory.join(file);
+    if plaintext.exists() {
+        return Ok(Some(fs::read(plaintext)?));
+    }
+    Ok(None)
+}
+
+fn write_state_file(
+    directory: &Path,
+    file: &str,
+    content: Vec<u8>,
+    key: Option<&StateKey>,
+) -> Result<(), Box<dyn Error>> {
+    let content = match key {
+        Some(key) => key.encrypt(file, &content),
+        None => content,
+    };
+    let path = directory.join(format!("{}.tmp", state_file_name(file, key.is_some())));
+    Ok(fs::write(path, content)?)
+}
+
+pub struct BackfillReport {
+    // Events of the transactions that weren't seen before, applied or ignored by the rules
+    pub events: Vec<Event>,
+    pub skipped: usize,
+}
+
+impl BackfillReport {
+    pub fn applied(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter().filter(|e| e.outcome == Outcome::Applied)
+    }
+}
+
+// Processes an older file against the current state. Deposits and withdrawals whose tx id is
+// already in the history are skipped, and so are the dispute flow transactions referencing
+// them : their dispute flow was processed along with them by a previous run.
+pub fn backfill(
+    engine: &mut Engine,
+    transactions: &[Transaction],
+) -> Result<BackfillReport, String> {
+    engine.load_history()?;
+    let known: HashSet<u32> = engine.transactions_history.keys().copied().collect();
+    let mut report = BackfillReport {
+        events: Vec::new(),
+        skipped: 0,
+    };
+    for t in transactions {
+        let seen = match t.category {
+            TransactionCategory::Deposit
+            | TransactionCategory::Withdrawal
+            | TransactionCategory::Adjustment
+            | TransactionCategory::OpeningBalance
+            | TransactionCategory::Place
+            | TransactionCategory::Release
+            | TransactionCategory::Capture
+            | TransactionCategory::Transfer
+            | TransactionCategory::Bonus => engine.transactions_history.contains_key(&t.tx),
+            TransactionCategory::Dispute
+            | TransactionCategory::Review
+            | TransactionCategory::Resolve
+            | TransactionCategory::Chargeback => known.contains(&t.tx),
+            // Admin rows and balance assertions leave no trace in the history, replaying them
+            // is harmless. Custom ones aren't recorded either, there is no telling whether
+            // they were seen.
+            TransactionCategory::Freeze
+            | TransactionCategory::Unfreeze
+            | TransactionCategory::Close
+            | TransactionCategory::AssertBalance
+            | TransactionCategory::Custom(_) => false,
+        };
+        if seen {
+            report.skipped += 1;
+        } else {
+            report.events.push(engine.process(t)?);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_transactions_from_file;
+
+    fn temp_directory(name: &str) -> String {
+        let directory = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&directory);
+        directory.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let directory = temp_directory("payments-engine-state-test");
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        save_engine(&engine, &directory).unwrap();
+
+        let mut loaded = load_engine(&directory).unwrap();
+        loaded.load_history().unwrap();
+        assert_eq!(loaded.clients.len(), engine.clients.len());
+        for (client_id, client) in &engine.clients {
+            assert_eq!(loaded.clients[client_id].available, client.available);
+            assert_eq!(loaded.clients[client_id].held, client.held);
+            assert_eq!(loaded.clients[client_id].total, client.total);
+            assert_eq!(loaded.clients[client_id].locked, client.locked);
+        }
+        assert_eq!(
+            loaded.transactions_history.len(),
+            engine.transactions_history.len()
+        );
+        assert_eq!(loaded.disputes().count(), engine.disputes().count());
+        assert_eq!(loaded.ongoing_disputes[&1].state, DisputeState::Opened);
+    }
+
+    #[test]
+    fn save_and_load_encrypted() {
+        let directory = temp_directory("payments-engine-encrypted-state-test");
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        save_engine(&engine, &directory).unwrap();
+        let key = StateKey::new(b"passphrase");
+        save_engine_with_key(&engine, &directory, Some(&key)).unwrap();
+
+        let clients = fs::read(Path::new(&directory).join("clients.csv.enc")).unwrap();
+        assert!(!clients.starts_with(b"client,"));
+        assert!(!Path::new(&directory).join("clients.csv").exists());
+        assert!(load_engine(&directory).is_err());
+        assert!(load_engine_with_key(&directory, Some(&StateKey::new(b"wrong"))).is_err());
+        let loaded = load_engine_with_key(&directory, Some(&key)).unwrap();
+        assert_eq!(loaded.clients[&1].held, engine.clients[&1].held);
+        assert_eq!(loaded.disputes().count(), engine.disputes().count());
+    }
+
+    #[test]
+    fn erasures_are_encrypted() {
+        let directory = temp_directory("payments-engine-erasures-test");
+        let key = StateKey::new(b"passphrase");
+        save_engine_with_key(&Engine::default(), &directory, Some(&key)).unwrap();
+        let store = DirectoryStore {
+            directory: directory.clone(),
+            key: Some(key),
+        };
+        let record = ErasureRecord {
+            erased_at: 1_700_000_000,
+            client: 2,
+            history: 1,
+            disputes: 0,
+            pseudonyms: 1,
+            folded: 2.0,
+        };
+        store.record_erasure(&record).unwrap();
+        store
+            .record_erasure(&ErasureRecord {
+                client: 3,
+                ..record
+            })
+            .unwrap();
+
+        assert!(!Path::new(&directory).join("erasures.csv").exists());
+        let erasures = read_state_file(Path::new(&directory), "erasures.csv", store.key.as_ref())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(erasures).unwrap(),
+            "erased_at,client,history,disputes,pseudonyms,folded\n\
+            1700000000,2,1,0,1,2.0\n\
+            1700000000,3,1,0,1,2.0\n"
+        );
+    }
+
+    #[test]
+    fn dispute_across_runs() {
+        let directory = temp_directory("payments-engine-runs-test");
+        let runs = [
+            "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,2,2,3.0\n",
+            // Without disputes, the history isn't read and the new deposit is appended
+            "type,client,tx,amount\ndeposit,1,3,1.0\n",
+            "type,client,tx,amount\ndispute,1,1,\ndispute,1,3,\n",
+            "type,client,tx,amount\nresolve,1,3,\nchargeback,1,1,\n",
+        ];
+        for (i, run) in runs.iter().enumerate() {
+            let mut engine = load_engine(&directory).unwrap();
+            for t in crate::read_transactions(run.as_bytes()).unwrap() {
+                engine.process(&t).unwrap();
+            }
+            assert_eq!(engine.history_loaded(), i == 0 || i == 2);
+            save_engine(&engine, &directory).unwrap();
+        }
+
+        let mut engine = load_engine(&directory).unwrap();
+        let client = &engine.clients[&1];
+        assert_eq!(
+            (client.available, client.held, client.total, client.locked),
+            (1.0, 0.0, 1.0, true)
+        );
+        engine.load_history().unwrap();
+        assert_eq!(engine.transactions_history.len(), 3);
+    }
+
+    #[test]
+    fn reused_tx_across_runs() {
+        let directory = temp_directory("payments-engine-reused-tx-test");
+        let runs = [
+            "type,client,tx,amount\ndeposit,1,1,5.0\n",
+            // The reused tx id is appended to the history, after the first row
+            "type,client,tx,amount\ndeposit,1,1,3.0\n",
+            "type,client,tx,amount\ndispute,1,1,\n",
+        ];
+        for run in runs {
+            let mut engine = load_engine(&directory).unwrap();
+            for t in crate::read_transactions(run.as_bytes()).unwrap() {
+                engine.process(&t).unwrap();
+            }
+            save_engine(&engine, &directory).unwrap();
+        }
+
+        // Like in a single run, the dispute holds the latest deposit of tx 1
+        let engine = load_engine(&directory).unwrap();
+        let client = &engine.clients[&1];
+        assert_eq!(
+            (client.available, client.held, client.total),
+            (5.0, 3.0, 8.0)
+        );
+    }
+
+    #[test]
+    fn load_missing_state() {
+        let directory = temp_directory("payments-engine-missing-state-test");
+        let engine = load_engine(&directory).unwrap();
+        assert!(engine.clients.is_empty());
+    }
+
+    #[test]
+    fn backfill_skips_seen_transactions() {
+        let transactions = get_transactions_from_file("src/testSamples/dispute.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+        let held = engine.clients[&1].held;
+
+        // Replaying the same file changes nothing, only the withdrawal that failed
+        // for lack of funds isn't in the history and is attempted again
+        let report = backfill(&mut engine, &transactions).unwrap();
+        assert_eq!(report.skipped, transactions.len() - 1);
+        assert_eq!(report.applied().count(), 0);
+        assert_eq!(engine.clients[&1].held, held);
+
+        // An older file with a new deposit and its dispute, plus a dispute of a known deposit
+        let older = get_transactions_from_file("src/testSamples/backfill.csv").unwrap();
+        let report = backfill(&mut engine, &older).unwrap();
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.applied().count(), 2);
+        assert_eq!(engine.clients[&3].held, 4.0);
+    }
+
+    #[test]
+    fn initial_state() {
+        let output =
+            "client,available,held,total,locked,frozen\n1,1.5000,0.0000,1.5000,false,false\n";
+        let mut engine = Engine::default();
+        set_initial_state(&mut engine, output.as_bytes()).unwrap();
+        let deposit = Transaction {
+            category: TransactionCategory::Deposit,
+            client_id: 1,
+            tx: 1,
+            amount: Some(2.0),
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        };
+        engine.process(&deposit).unwrap();
+        assert_eq!(engine.clients()[&1].available, 3.5);
+        assert_eq!(engine.clients()[&1].total, 3.5);
+    }
+}
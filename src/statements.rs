@@ -0,0 +1,217 @@
+use crate::currency::format_amount;
+use crate::dates::format_time;
+use crate::reports::{balance_at, PointInTime};
+use crate::{Client, DisputeState, Engine};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+// Statement of a client over a period : the balances at its start and at its end, and what
+// happened in between from the history and the dispute records
+pub struct Statement {
+    pub client_id: u16,
+    // Unix seconds, the end excluded
+    pub start: u64,
+    pub end: u64,
+    pub opening: Client,
+    pub closing: Client,
+    pub lines: Vec<Line>,
+    // Entries without a timestamp, left out of the balances
+    pub undated: usize,
+}
+
+pub struct Line {
+    pub timestamp: u64,
+    pub category: &'static str,
+    pub tx: u32,
+    pub amount: Option<f64>,
+}
+
+// One statement per client active in the period (from `start` included to `end` excluded, in
+// unix seconds), by client id. Transfers between wallets are listed, they don't change the
+// client's balances.
+pub fn for_period(engine: &Engine, start: u64, end: u64) -> Vec<Statement> {
+    let period = start..end;
+    let mut lines: BTreeMap<u16, Vec<Line>> = BTreeMap::new();
+    for t in engine.transactions_history.values() {
+        if let Some(timestamp) = t.timestamp.filter(|at| period.contains(at)) {
+            lines.entry(t.client_id).or_default().push(Line {
+                timestamp,
+                category: t.category.as_str(),
+                tx: t.tx,
+                amount: t.amount,
+            });
+        }
+    }
+    for dispute in engine.disputes() {
+        let closing = match dispute.state {
+            DisputeState::Resolved => Some("resolve"),
+            DisputeState::ChargedBack => Some("chargeback"),
+            _ => None,
+        };
+        let events = [
+            (Some("dispute"), dispute.opened_at),
+            (closing, dispute.closed_at),
+        ];
+        for (category, timestamp) in events {
+            if let (Some(category), Some(timestamp)) = (category, timestamp) {
+                if period.contains(&timestamp) {
+                    lines.entry(dispute.client_id).or_default().push(Line {
+                        timestamp,
+                        category,
+                        tx: dispute.tx,
+                        amount: Some(dispute.amount),
+                    });
+                }
+            }
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(client_id, mut lines)| {
+            lines.sort_by_key(|line| (line.timestamp, line.tx));
+            let opening = balance_at(engine, client_id, start.saturating_sub(1)).client;
+            let PointInTime { client, undated } = balance_at(engine, client_id, end - 1);
+            Statement {
+                client_id,
+                start,
+                end,
+                opening,
+                closing: client,
+                lines,
+                undated,
+            }
+        })
+        .collect()
+}
+
+impl Statement {
+    // date,type,tx,amount,available,held,total : an opening row, a row per transaction, then
+    // a closing row. The balances are only on the opening and closing rows.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), csv::Error> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        wtr.write_record(["date", "type", "tx", "amount", "available", "held", "total"])?;
+        let balance = |category: &str, timestamp: u64, client: &Client| {
+            [
+                format_time(timestamp),
+                category.to_string(),
+                String::new(),
+                String::new(),
+                format!("{:.4}", client.available),
+                format!("{:.4}", client.held),
+                format!("{:.4}", client.total),
+            ]
+        };
+        wtr.write_record(balance("opening", self.start, &self.opening))?;
+        for line in &self.lines {
+            wtr.write_record([
+                format_time(line.timestamp),
+                line.category.to_string(),
+                line.tx.to_string(),
+                line.amount.map(|a| format!("{:.4}", a)).unwrap_or_default(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ])?;
+        }
+        wtr.write_record(balance("closing", self.end - 1, &self.closing))?;
+        wtr.flush()?;
+        Ok(())
+    }
+
+    // Amounts in the display currency when one is given
+    pub fn write_text<W: Write>(
+        &self,
+        writer: &mut W,
+        period: &str,
+        currency: Option<&str>,
+    ) -> std::io::Result<()> {
+        let balance = |client: &Client| {
+            format!(
+                "available {}, held {}, total {}{}",
+                format_amount(client.available, currency),
+                format_amount(client.held, currency),
+                format_amount(client.total, currency),
+                if client.locked { ", locked" } else { "" }
+            )
+        };
+        writeln!(
+            writer,
+            "Statement of client {} for {}",
+            self.client_id, period
+        )?;
+        writeln!(
+            writer,
+            "Opening balance ({}) : {}",
+            format_time(self.start),
+            balance(&self.opening)
+        )?;
+        for line in &self.lines {
+            writeln!(
+                writer,
+                "  {} {} tx {}{}",
+                format_time(line.timestamp),
+                line.category,
+                line.tx,
+                line.amount
+                    .map(|a| format!(" : {}", format_amount(a, currency)))
+                    .unwrap_or_default()
+            )?;
+        }
+        writeln!(
+            writer,
+            "Closing balance ({}) : {}",
+            format_time(self.end - 1),
+            balance(&self.closing)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::parse_month;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn monthly_statements() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeLifecycle.csv").unwrap();
+        let mut engine = Engine::default();
+        for t in &transactions {
+            engine.process(t).unwrap();
+        }
+
+        let (start, end) = parse_month("2023-11").unwrap();
+        let statements = for_period(&engine, start, end);
+        assert_eq!(statements.len(), 2);
+        let mut output = Vec::new();
+        statements[1].write_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "date,type,tx,amount,available,held,total
+2023-11-01T00:00:00Z,opening,,,0.0000,0.0000,0.0000
+2023-11-14T22:13:30Z,deposit,2,2.0000,,,
+2023-11-14T22:16:40Z,dispute,2,2.0000,,,
+2023-11-14T22:21:40Z,chargeback,2,2.0000,,,
+2023-11-30T23:59:59Z,closing,,,0.0000,0.0000,0.0000
+"
+        );
+        let mut output = Vec::new();
+        statements[1]
+            .write_text(&mut output, "2023-11", None)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Statement of client 2 for 2023-11
+Opening balance (2023-11-01T00:00:00Z) : available 0.0000, held 0.0000, total 0.0000
+  2023-11-14T22:13:30Z deposit tx 2 : 2.0000
+  2023-11-14T22:16:40Z dispute tx 2 : 2.0000
+  2023-11-14T22:21:40Z chargeback tx 2 : 2.0000
+Closing balance (2023-11-30T23:59:59Z) : available 0.0000, held 0.0000, total 0.0000, locked
+"
+        );
+
+        let (start, end) = parse_month("2023-12").unwrap();
+        assert!(for_period(&engine, start, end).is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::currency::format_amount;
+use crate::Client;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::Write;
+
+const HEADERS: [&str; 6] = ["client", "available", "held", "total", "locked", "frozen"];
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+// Human readable version of the csv output : clients sorted by id, right aligned columns
+// and a summary footer. Locked clients are shown in red and clients with held funds in yellow.
+// Clients are keyed by id, or by pseudonym in the pseudonymized output. Amounts are written in
+// the display currency when one is given.
+pub fn write_clients_table<W: Write, K: Display + Ord + Hash>(
+    writer: &mut W,
+    clients: &HashMap<K, Client>,
+    color: bool,
+    currency: Option<&str>,
+) -> Result<(), std::io::Error> {
+    let mut client_ids: Vec<&K> = clients.keys().collect();
+    client_ids.sort();
+
+    let rows: Vec<[String; 6]> = client_ids
+        .iter()
+        .map(|client_id| {
+            let client = &clients[client_id];
+            [
+                client_id.to_string(),
+                format_amount(client.available, currency),
+                format_amount(client.held, currency),
+                format_amount(client.total, currency),
+                client.locked.to_string(),
+                client.frozen.to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = HEADERS.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let (bold, reset) = if color { (BOLD, RESET) } else { ("", "") };
+    writeln!(
+        writer,
+        "{}{}{}",
+        bold,
+        format_row(&HEADERS.map(String::from), &widths),
+        reset
+    )?;
+    writeln!(writer, "{}", separator(&widths))?;
+    for (client_id, row) in client_ids.iter().zip(&rows) {
+        let client = &clients[client_id];
+        let row_color = match (color, client.locked, client.held != 0.0) {
+            (false, _, _) => "",
+            (true, true, _) => RED,
+            (true, false, true) => YELLOW,
+            (true, false, false) => "",
+        };
+        let row_reset = if row_color.is_empty() { "" } else { RESET };
+        writeln!(
+            writer,
+            "{}{}{}",
+            row_color,
+            format_row(row, &widths),
+            row_reset
+        )?;
+    }
+    writeln!(writer, "{}", separator(&widths))?;
+
+    let locked = clients.values().filter(|c| c.locked).count();
+    writeln!(
+        writer,
+        "{} clients ({} locked), available {}, held {}, total {}",
+        clients.len(),
+        locked,
+        format_amount(clients.values().map(|c| c.available).sum(), currency),
+        format_amount(clients.values().map(|c| c.held).sum(), currency),
+        format_amount(clients.values().map(|c| c.total).sum(), currency),
+    )?;
+    Ok(())
+}
+
+fn format_row(cells: &[String; 6], widths: &[usize; 6]) -> String {
+    cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+        .collect::<Vec<String>>()
+        .join(" | ")
+}
+
+fn separator(widths: &[usize; 6]) -> String {
+    widths
+        .iter()
+        .map(|width| "-".repeat(*width))
+        .collect::<Vec<String>>()
+        .join("-+-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_transactions_from_file, process_transactions};
+
+    #[test]
+    fn aligned_table_with_summary() {
+        let transactions = get_transactions_from_file("src/testSamples/chargeback.csv").unwrap();
+        let mut clients: HashMap<u16, Client> = HashMap::new();
+        process_transactions(&transactions, &mut clients).unwrap();
+
+        let mut output = Vec::new();
+        write_clients_table(&mut output, &clients, false, None).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            "client | available |   held |  total | locked | frozen
+-------+-----------+--------+--------+--------+-------
+     1 |    0.5000 | 0.0000 | 0.5000 |   true |  false
+     2 |    2.0000 | 0.0000 | 2.0000 |  false |  false
+-------+-----------+--------+--------+--------+-------
+2 clients (1 locked), available 2.5000, held 0.0000, total 2.5000
+"
+        );
+
+        let mut output = Vec::new();
+        write_clients_table(&mut output, &clients, false, Some("USD")).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("2 clients (1 locked), available $2.5000, held $0.0000, total $2.5000\n"));
+    }
+}
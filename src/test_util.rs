@@ -0,0 +1,172 @@
+use crate::{Client, Engine, Event, Outcome, Transaction, TransactionCategory};
+
+// Engine with shortcuts for the tests of host applications, running the exact same rules.
+// The transaction shortcuts panic on the errors that would abort a batch, e.g. a negative
+// amount, use `process` to test those.
+#[derive(Default)]
+pub struct TestEngine {
+    engine: Engine,
+}
+
+impl TestEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn engine(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    pub fn process(&mut self, t: &Transaction) -> Result<Event, String> {
+        self.engine.process(t)
+    }
+
+    pub fn deposit(&mut self, client_id: u16, tx: u32, amount: f64) -> Outcome {
+        self.submit(TransactionCategory::Deposit, client_id, tx, Some(amount))
+    }
+
+    pub fn withdraw(&mut self, client_id: u16, tx: u32, amount: f64) -> Outcome {
+        self.submit(TransactionCategory::Withdrawal, client_id, tx, Some(amount))
+    }
+
+    pub fn dispute(&mut self, client_id: u16, tx: u32) -> Outcome {
+        self.submit(TransactionCategory::Dispute, client_id, tx, None)
+    }
+
+    pub fn review(&mut self, client_id: u16, tx: u32) -> Outcome {
+        self.submit(TransactionCategory::Review, client_id, tx, None)
+    }
+
+    pub fn resolve(&mut self, client_id: u16, tx: u32) -> Outcome {
+        self.submit(TransactionCategory::Resolve, client_id, tx, None)
+    }
+
+    pub fn chargeback(&mut self, client_id: u16, tx: u32) -> Outcome {
+        self.submit(TransactionCategory::Chargeback, client_id, tx, None)
+    }
+
+    pub fn freeze(&mut self, client_id: u16) -> Outcome {
+        self.submit(TransactionCategory::Freeze, client_id, 0, None)
+    }
+
+    pub fn unfreeze(&mut self, client_id: u16) -> Outcome {
+        self.submit(TransactionCategory::Unfreeze, client_id, 0, None)
+    }
+
+    // Only applied when the config allows adjustments
+    pub fn adjust(&mut self, client_id: u16, tx: u32, amount: f64, reason: &str) -> Outcome {
+        self.run(&Transaction {
+            category: TransactionCategory::Adjustment,
+            client_id,
+            tx,
+            amount: Some(amount),
+            reason: Some(reason.to_string()),
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        })
+    }
+
+    // Panics when the client is unknown
+    pub fn client(&self, client_id: u16) -> &Client {
+        self.engine
+            .clients()
+            .get(&client_id)
+            .unwrap_or_else(|| panic!("Client {} has no transaction", client_id))
+    }
+
+    #[track_caller]
+    pub fn assert_balance(&self, client_id: u16, available: f64, held: f64, total: f64) {
+        let client = self.client(client_id);
+        assert_eq!(
+            (client.available, client.held, client.total),
+            (available, held, total),
+            "Balance of client {} as (available, held, total)",
+            client_id
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_locked(&self, client_id: u16) {
+        assert!(
+            self.client(client_id).locked,
+            "Client {} should be locked",
+            client_id
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_unlocked(&self, client_id: u16) {
+        assert!(
+            !self.client(client_id).locked,
+            "Client {} should not be locked",
+            client_id
+        );
+    }
+
+    fn submit(
+        &mut self,
+        category: TransactionCategory,
+        client_id: u16,
+        tx: u32,
+        amount: Option<f64>,
+    ) -> Outcome {
+        let t = Transaction {
+            category,
+            client_id,
+            tx,
+            amount,
+            reason: None,
+            timestamp: None,
+            wallet: None,
+            seq: None,
+        };
+        self.run(&t)
+    }
+
+    fn run(&mut self, t: &Transaction) -> Outcome {
+        self.engine
+            .process(t)
+            .unwrap_or_else(|e| {
+                panic!(
+                    "{} of tx {} was rejected : {}",
+                    t.category.as_str(),
+                    t.tx,
+                    e
+                )
+            })
+            .outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concise_engine_tests() {
+        let mut engine = TestEngine::new();
+        assert_eq!(engine.deposit(1, 1, 10.0), Outcome::Applied);
+        assert_eq!(
+            engine.withdraw(1, 2, 20.0),
+            Outcome::Ignored("Insufficient available funds")
+        );
+        engine.dispute(1, 1);
+        engine.assert_balance(1, 0.0, 10.0, 10.0);
+        engine.chargeback(1, 1);
+        engine.assert_balance(1, 0.0, 0.0, 0.0);
+        engine.assert_locked(1);
+        assert_eq!(
+            engine.deposit(1, 3, 1.0),
+            Outcome::Ignored("Client account is locked")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Balance of client 1")]
+    fn balance_assertion_failure() {
+        let mut engine = TestEngine::new();
+        engine.deposit(1, 1, 10.0);
+        engine.assert_balance(1, 5.0, 0.0, 5.0);
+    }
+}
@@ -0,0 +1,108 @@
+// Bloom filter over the tx ids recorded in the history, checked before the history itself so
+// the dispute rows referencing unknown transactions don't have to miss in the big map. It
+// never forgets a tx id (a rolled back one only costs a false positive), and is rebuilt twice
+// as large from the history when it fills up.
+#[derive(Clone, Debug, Default)]
+pub struct TxFilter {
+    bits: Vec<u64>,
+    count: usize,
+}
+
+const BITS_PER_TX: usize = 16;
+const HASHES: u64 = 4;
+const MIN_CAPACITY: usize = 1024;
+
+impl TxFilter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        TxFilter {
+            bits: vec![0; capacity * BITS_PER_TX / 64],
+            count: 0,
+        }
+    }
+
+    // False positives are possible, false negatives aren't
+    pub fn may_contain(&self, tx: u32) -> bool {
+        !self.bits.is_empty()
+            && positions(self.bits.len(), tx).all(|(word, bit)| self.bits[word] & bit != 0)
+    }
+
+    // Grows from all the recorded tx ids when full, `recorded` including `tx` or not. A tx id
+    // already set isn't counted again, so inserting it twice doesn't fill the filter.
+    pub fn insert(&mut self, tx: u32, recorded: impl ExactSizeIterator<Item = u32>) {
+        if self.count >= self.capacity() {
+            *self = Self::with_capacity(recorded.len() * 2);
+            for recorded in recorded {
+                self.set(recorded);
+            }
+        }
+        self.set(tx);
+    }
+
+    fn capacity(&self) -> usize {
+        self.bits.len() * 64 / BITS_PER_TX
+    }
+
+    fn set(&mut self, tx: u32) {
+        let mut new = false;
+        for (word, bit) in positions(self.bits.len(), tx) {
+            new |= self.bits[word] & bit == 0;
+            self.bits[word] |= bit;
+        }
+        if new {
+            self.count += 1;
+        }
+    }
+}
+
+// Word and bit of each hash, by double hashing a splitmix64 mix of the tx id
+fn positions(words: usize, tx: u32) -> impl Iterator<Item = (usize, u64)> {
+    let h1 = mix(tx as u64);
+    let h2 = mix(h1) | 1;
+    let bits = (words * 64) as u64;
+    (0..HASHES).map(move |i| {
+        let position = h1.wrapping_add(i.wrapping_mul(h2)) % bits;
+        ((position / 64) as usize, 1 << (position % 64))
+    })
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives_across_rebuilds() {
+        let mut filter = TxFilter::default();
+        assert!(!filter.may_contain(1));
+        let mut recorded = Vec::new();
+        for tx in (0..20_000).map(|i| i * 7) {
+            filter.insert(tx, recorded.iter().copied());
+            recorded.push(tx);
+        }
+        assert!(recorded.iter().all(|tx| filter.may_contain(*tx)));
+        let false_positives = (0..20_000)
+            .map(|i| i * 7 + 3)
+            .filter(|tx| filter.may_contain(*tx))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn repeated_ids_dont_fill_the_filter() {
+        let mut filter = TxFilter::default();
+        filter.insert(1, std::iter::empty());
+        let capacity = filter.capacity();
+        for _ in 0..capacity * 2 {
+            filter.insert(1, std::iter::once(1));
+        }
+        assert_eq!(filter.count, 1);
+        assert_eq!(filter.capacity(), capacity);
+    }
+}
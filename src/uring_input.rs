@@ -0,0 +1,127 @@
+use io_uring::{opcode, types, IoUring};
+use std::fs::File;
+use std::io::{Error, Read};
+use std::os::unix::io::AsRawFd;
+
+// Large reads keep an NVMe drive busy, the page cache readahead alone tops out earlier
+const BUFFER_SIZE: usize = 1 << 20;
+
+// Reads a file through io_uring, the kernel filling the next buffer while the current one is
+// parsed
+pub struct UringReader {
+    ring: IoUring,
+    file: File,
+    current: Vec<u8>,
+    position: usize,
+    // Filled by the kernel while in_flight, it must not be touched until the read completes
+    next: Vec<u8>,
+    in_flight: bool,
+    // Offset of the next read to submit
+    offset: u64,
+}
+
+impl UringReader {
+    pub fn new(file: File) -> Result<Self, Error> {
+        let mut reader = UringReader {
+            ring: IoUring::new(2)?,
+            file,
+            current: Vec::new(),
+            position: 0,
+            next: vec![0; BUFFER_SIZE],
+            in_flight: false,
+            offset: 0,
+        };
+        reader.submit()?;
+        Ok(reader)
+    }
+
+    fn submit(&mut self) -> Result<(), Error> {
+        self.next.resize(BUFFER_SIZE, 0);
+        let read = opcode::Read::new(
+            types::Fd(self.file.as_raw_fd()),
+            self.next.as_mut_ptr(),
+            BUFFER_SIZE as u32,
+        )
+        .offset(self.offset)
+        .build();
+        // Safety : the buffer outlives the read, see `wait` and the Drop implementation
+        unsafe { self.ring.submission().push(&read) }
+            .map_err(|_| Error::other("The io_uring submission queue is full"))?;
+        self.ring.submit()?;
+        self.in_flight = true;
+        Ok(())
+    }
+
+    // Bytes read by the kernel into `next`
+    fn wait(&mut self) -> Result<usize, Error> {
+        self.ring.submit_and_wait(1)?;
+        let completion = self
+            .ring
+            .completion()
+            .next()
+            .ok_or_else(|| Error::other("No io_uring completion"))?;
+        self.in_flight = false;
+        if completion.result() < 0 {
+            return Err(Error::from_raw_os_error(-completion.result()));
+        }
+        Ok(completion.result() as usize)
+    }
+}
+
+impl Read for UringReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.position == self.current.len() {
+            if !self.in_flight {
+                return Ok(0);
+            }
+            let read = self.wait()?;
+            self.next.truncate(read);
+            std::mem::swap(&mut self.current, &mut self.next);
+            self.position = 0;
+            self.offset += read as u64;
+            // Nothing read means the end of the file
+            if read > 0 {
+                self.submit()?;
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.position);
+        buf[..n].copy_from_slice(&self.current[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl Drop for UringReader {
+    fn drop(&mut self) {
+        if self.in_flight {
+            let _ = self.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_bytes_as_std() {
+        let path = "src/testSamples/providedExample.csv";
+        let mut bytes = Vec::new();
+        UringReader::new(File::open(path).unwrap())
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, std::fs::read(path).unwrap());
+
+        // Over several buffers
+        let path = std::env::temp_dir().join("payments-engine-uring-test");
+        let content: Vec<u8> = (0..BUFFER_SIZE * 5 / 2).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+        let mut bytes = Vec::new();
+        UringReader::new(File::open(&path).unwrap())
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, content);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{Client, Engine, Transaction};
+use std::collections::BTreeSet;
+use std::io::Write;
+
+// Final balances of a client under the base config and under the variant
+#[derive(Debug, Clone)]
+pub struct ClientComparison {
+    pub client_id: u16,
+    pub base: Client,
+    pub variant: Client,
+}
+
+impl ClientComparison {
+    pub fn differs(&self) -> bool {
+        self.base.available != self.variant.available
+            || self.base.held != self.variant.held
+            || self.base.total != self.variant.total
+            || self.base.locked != self.variant.locked
+    }
+}
+
+// Processes each transaction on both engines in a single pass over the input, so product can
+// see what a fee schedule or a dispute policy would have changed on real data. A client
+// missing from one engine (all its transactions rejected) is compared with empty balances.
+pub fn compare(
+    base: &mut Engine,
+    variant: &mut Engine,
+    transactions: &[Transaction],
+) -> Result<Vec<ClientComparison>, String> {
+    for t in transactions {
+        base.process(t)?;
+        variant.process(t)?;
+    }
+    let client_ids: BTreeSet<u16> = base
+        .clients()
+        .keys()
+        .chain(variant.clients().keys())
+        .copied()
+        .collect();
+    let balances = |engine: &Engine, client_id| {
+        engine
+            .clients()
+            .get(&client_id)
+            .cloned()
+            .unwrap_or_default()
+    };
+    Ok(client_ids
+        .into_iter()
+        .map(|client_id| ClientComparison {
+            client_id,
+            base: balances(base, client_id),
+            variant: balances(variant, client_id),
+        })
+        .collect())
+}
+
+// One row per client, the differences being the variant minus the base
+pub fn write_comparison<W: Write>(
+    writer: W,
+    comparisons: &[ClientComparison],
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "client",
+        "base_available",
+        "variant_available",
+        "available_difference",
+        "base_held",
+        "variant_held",
+        "held_difference",
+        "base_total",
+        "variant_total",
+        "total_difference",
+        "base_locked",
+        "variant_locked",
+    ])?;
+    let amount = |amount: f64| format!("{:.4}", amount);
+    for comparison in comparisons {
+        let (base, variant) = (&comparison.base, &comparison.variant);
+        wtr.write_record([
+            comparison.client_id.to_string(),
+            amount(base.available),
+            amount(variant.available),
+            amount(variant.available - base.available),
+            amount(base.held),
+            amount(variant.held),
+            amount(variant.held - base.held),
+            amount(base.total),
+            amount(variant.total),
+            amount(variant.total - base.total),
+            base.locked.to_string(),
+            variant.locked.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::get_transactions_from_file;
+
+    #[test]
+    fn capped_holds_variant() {
+        let transactions =
+            get_transactions_from_file("src/testSamples/disputeInsufficientFunds.csv").unwrap();
+        let mut base = Engine::default();
+        let mut variant = Engine::default();
+        let config: Config = toml::from_str("[disputes]\ninsufficient_funds = \"cap\"").unwrap();
+        variant.set_config(config);
+        let comparisons = compare(&mut base, &mut variant, &transactions).unwrap();
+        assert!(comparisons[0].differs());
+
+        let mut output = Vec::new();
+        write_comparison(&mut output, &comparisons).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,base_available,variant_available,available_difference,base_held,variant_held,\
+held_difference,base_total,variant_total,total_difference,base_locked,variant_locked
+1,1.0000,9.0000,8.0000,0.0000,0.0000,0.0000,1.0000,9.0000,8.0000,true,true
+"
+        );
+    }
+}